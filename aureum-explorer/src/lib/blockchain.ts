@@ -119,6 +119,23 @@ export async function getRecentBlocks(count: number = 10): Promise<any[]> {
     }
 }
 
+export interface AddressTxQuery {
+    direction?: "all" | "sent" | "received";
+    tx_type?: string;
+    limit?: number;
+    cursor?: [number, number] | null;
+    newest_first?: boolean;
+}
+
+/**
+ * Get one page of an address's transaction history from the node index.
+ * Pass the returned `next_cursor` back as `cursor` to fetch the following page.
+ */
+export async function getTransactionsByAddress(address: string, query: AddressTxQuery = {}): Promise<{ transactions: any[]; next_cursor: [number, number] | null }> {
+    const result = await rpcCall("aureum_getTransactionsByAddress", [address, query]);
+    return result ?? { transactions: [], next_cursor: null };
+}
+
 /**
  * Get property information
  */
@@ -58,6 +58,11 @@ impl ComplianceEngine {
         }
     }
 
+    /// Same rules evaluated against a different storage handle, e.g. a block overlay
+    pub fn with_storage(&self, storage: Arc<ChainStorage>) -> Self {
+        Self { storage, jurisdiction_rules: self.jurisdiction_rules.clone() }
+    }
+
     pub fn register_profile(&self, profile: ComplianceProfile) {
         info!("Registering compliance profile for {}", profile.address);
         self.storage.save_compliance_profile(&profile);
@@ -1,9 +1,16 @@
 use serde::{Serialize, Deserialize};
 use parity_scale_codec::{Encode, Decode};
-use crate::core::{Block, ValidatorSet, ValidatorRole};
+use crate::core::{Block, Validator, ValidatorSet, ValidatorRole, ValidatorStatus, DEFAULT_CHAIN_ID};
+use crate::wal::ConsensusWal;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use log::{info, warn, error};
 
+/// Domain separator for vote signatures, keeping them distinct from transaction signatures
+pub const VOTE_DOMAIN_TAG: &[u8] = b"AUREUM_VOTE";
+/// Domain separator for block proposal signatures
+pub const PROPOSAL_DOMAIN_TAG: &[u8] = b"AUREUM_PROPOSAL";
+
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq, Eq, Hash)]
 pub enum BftStep {
     Propose,
@@ -22,6 +29,404 @@ pub struct BftMessage {
     pub signature: Vec<u8>,
 }
 
+impl BftMessage {
+    /// Canonical bytes a validator signs: the domain tag, the chain id, then the vote itself
+    pub fn signing_bytes(&self, chain_id: u64) -> Vec<u8> {
+        let mut msg = VOTE_DOMAIN_TAG.to_vec();
+        chain_id.encode_to(&mut msg);
+        (self.height, self.round, &self.step, &self.block_hash, &self.validator).encode_to(&mut msg);
+        msg
+    }
+
+    pub fn sign(&mut self, chain_id: u64, key: &ed25519_dalek::SigningKey) {
+        use ed25519_dalek::Signer;
+        self.signature = key.sign(&self.signing_bytes(chain_id)).to_vec();
+    }
+
+    pub fn verify_signature(&self, chain_id: u64, public_key: &[u8]) -> bool {
+        use ed25519_dalek::{VerifyingKey, Signature, Verifier};
+
+        let Ok(key_bytes) = <[u8; 32]>::try_from(public_key) else { return false };
+        let Ok(key) = VerifyingKey::from_bytes(&key_bytes) else { return false };
+        let Ok(signature) = Signature::from_slice(&self.signature) else { return false };
+        key.verify(&self.signing_bytes(chain_id), &signature).is_ok()
+    }
+}
+
+/// Proof that a block was finalized: the signed precommits for it that reached quorum
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
+pub struct CommitCertificate {
+    pub height: u64,
+    pub round: u32,
+    pub block_hash: String,
+    pub precommits: Vec<BftMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CertificateError {
+    BlockMismatch { height: u64, block_hash: String },
+    UnexpectedVote(String),
+    DuplicateSigner(String),
+    UnknownSigner(String),
+    InvalidSignature(String),
+    InsufficientStake { signed: u64, required: u64 },
+    MissingAuthorityApproval,
+}
+
+impl fmt::Display for CertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CertificateError::BlockMismatch { height, block_hash } => write!(f, "certificate is for block {} at height {}", block_hash, height),
+            CertificateError::UnexpectedVote(v) => write!(f, "vote by {} is not a precommit for the certified block", v),
+            CertificateError::DuplicateSigner(v) => write!(f, "validator {} signed twice", v),
+            CertificateError::UnknownSigner(v) => write!(f, "signer {} is not in the validator set", v),
+            CertificateError::InvalidSignature(v) => write!(f, "invalid signature from {}", v),
+            CertificateError::InsufficientStake { signed, required } => write!(f, "signers hold {} stake, {} required", signed, required),
+            CertificateError::MissingAuthorityApproval => write!(f, "no authority node signed"),
+        }
+    }
+}
+
+impl std::error::Error for CertificateError {}
+
+impl CommitCertificate {
+    /// Check that the certificate finalizes `block_hash` at `height` under `validator_set`
+    pub fn verify(&self, chain_id: u64, height: u64, block_hash: &str, validator_set: &ValidatorSet, authority_veto: bool) -> Result<(), CertificateError> {
+        if self.height != height || self.block_hash != block_hash {
+            return Err(CertificateError::BlockMismatch { height: self.height, block_hash: self.block_hash.clone() });
+        }
+
+        let mut signers: Vec<&Validator> = vec![];
+        for vote in &self.precommits {
+            if vote.height != self.height || vote.round != self.round || vote.step != BftStep::Precommit || vote.block_hash.as_deref() != Some(block_hash) {
+                return Err(CertificateError::UnexpectedVote(vote.validator.clone()));
+            }
+            if signers.iter().any(|v| v.address == vote.validator) {
+                return Err(CertificateError::DuplicateSigner(vote.validator.clone()));
+            }
+            let validator = validator_set.active(&vote.validator)
+                .ok_or_else(|| CertificateError::UnknownSigner(vote.validator.clone()))?;
+            if !vote.verify_signature(chain_id, &validator.public_key) {
+                return Err(CertificateError::InvalidSignature(vote.validator.clone()));
+            }
+            signers.push(validator);
+        }
+
+        let signed: u64 = signers.iter().map(|v| v.stake).sum();
+        if !has_quorum(validator_set, signed) {
+            return Err(CertificateError::InsufficientStake { signed, required: quorum_stake(validator_set) });
+        }
+        if !authority_approved(validator_set, &signers, authority_veto) {
+            return Err(CertificateError::MissingAuthorityApproval);
+        }
+        Ok(())
+    }
+
+    /// Addresses of the validators whose precommits are in the certificate
+    pub fn signers(&self) -> Vec<String> {
+        self.precommits.iter().map(|v| v.validator.clone()).collect()
+    }
+}
+
+/// A block offered for a round, signed by that round's proposer
+///
+/// The proposer is named here rather than taken from the block header: a locked block carried
+/// into a later round keeps the header of the validator that built it, who earns its rewards.
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
+pub struct Proposal {
+    pub round: u32,
+    /// Round of the polka that locked the block when it is proposed again, `None` for a fresh block
+    pub pol_round: Option<u32>,
+    pub proposer: String,
+    pub block: Block,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProposalError {
+    UnexpectedRound { height: u64, round: u32 },
+    WrongProposer { expected: String, found: String },
+    InvalidPolRound { round: u32, pol_round: u32 },
+    InvalidSignature(String),
+}
+
+impl ProposalError {
+    /// Whether the proposal proves its sender misbehaved, as opposed to merely being early or late
+    pub fn is_fault(&self) -> bool {
+        !matches!(self, ProposalError::UnexpectedRound { .. })
+    }
+}
+
+impl fmt::Display for ProposalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProposalError::UnexpectedRound { height, round } => write!(f, "proposal is for height {} round {}", height, round),
+            ProposalError::WrongProposer { expected, found } => write!(f, "proposal by {} but {} is the proposer", found, expected),
+            ProposalError::InvalidPolRound { round, pol_round } => write!(f, "proposal for round {} claims a polka in round {}", round, pol_round),
+            ProposalError::InvalidSignature(p) => write!(f, "invalid proposal signature from {}", p),
+        }
+    }
+}
+
+impl std::error::Error for ProposalError {}
+
+impl Proposal {
+    /// Canonical bytes the proposer signs: the domain tag, the chain id, then height, round,
+    /// POL round, proposer and block hash
+    pub fn signing_bytes(&self, chain_id: u64) -> Vec<u8> {
+        let mut msg = PROPOSAL_DOMAIN_TAG.to_vec();
+        chain_id.encode_to(&mut msg);
+        (self.block.header.height, self.round, self.pol_round, &self.proposer, self.block.hash()).encode_to(&mut msg);
+        msg
+    }
+
+    pub fn sign(&mut self, chain_id: u64, key: &ed25519_dalek::SigningKey) {
+        use ed25519_dalek::Signer;
+        self.signature = key.sign(&self.signing_bytes(chain_id)).to_vec();
+    }
+
+    pub fn verify_signature(&self, chain_id: u64, public_key: &[u8]) -> bool {
+        use ed25519_dalek::{VerifyingKey, Signature, Verifier};
+
+        let Ok(key_bytes) = <[u8; 32]>::try_from(public_key) else { return false };
+        let Ok(key) = VerifyingKey::from_bytes(&key_bytes) else { return false };
+        let Ok(signature) = Signature::from_slice(&self.signature) else { return false };
+        key.verify(&self.signing_bytes(chain_id), &signature).is_ok()
+    }
+}
+
+/// Percentage of stake slashed for signing conflicting votes; the validator is also tombstoned
+pub const EQUIVOCATION_SLASH_PERCENT: u64 = 50;
+
+/// How many rounds ahead of the current one votes are still accepted
+pub const MAX_ROUNDS_AHEAD: u32 = 20;
+
+/// AUR minted every block and paid to validators with their share of the fees
+pub const BLOCK_ISSUANCE: u64 = 100;
+
+/// Chain-wide downtime rules, fixed at genesis
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq)]
+pub struct SlashingParams {
+    /// Number of most recent blocks a validator's signatures are judged over
+    pub signed_blocks_window: u64,
+    /// Missed blocks within the window beyond which a validator is slashed and jailed
+    pub max_missed_blocks: u64,
+    pub downtime_slash_percent: u64,
+    pub downtime_jail_blocks: u64,
+    /// Blocks unstaked AUR stays slashable before it is paid back to the validator's balance
+    pub unbonding_blocks: u64,
+}
+
+impl Default for SlashingParams {
+    fn default() -> Self {
+        Self { signed_blocks_window: 100, max_missed_blocks: 50, downtime_slash_percent: 1, downtime_jail_blocks: 1_000, unbonding_blocks: 10_000 }
+    }
+}
+
+/// A validator's signing record over the current window
+#[derive(Debug, Clone, Encode, Decode, PartialEq)]
+pub struct LivenessRecord {
+    /// First height the validator was tracked at; it is only judged once a full window has passed
+    pub since: u64,
+    /// Heights within the window whose commit the validator did not sign
+    pub missed: Vec<u64>,
+}
+
+/// Stake taken out of the validator set by an `Unstake` or `Undelegate`, still slashable for
+/// `validator`'s misbehaviour until it is returned to `owner`'s balance at `release_height`
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq)]
+pub struct UnbondingEntry {
+    pub validator: String,
+    pub owner: String,
+    pub amount: u64,
+    pub release_height: u64,
+}
+
+/// Delegation moved by a `Redelegate`. Until `completion_height` it is still slashable for
+/// misbehaviour of the validator it left, taken from the delegation it moved to.
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq)]
+pub struct Redelegation {
+    pub delegator: String,
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    pub completion_height: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvidenceError {
+    /// The votes are not for different blocks at the same height, round and step by one validator
+    NotConflicting,
+    UnknownValidator(String),
+    InvalidSignature(String),
+    /// The validator was already punished for equivocating at this height
+    AlreadyPunished { validator: String, height: u64 },
+    /// The validator was already removed from consensus for good
+    Tombstoned(String),
+}
+
+impl fmt::Display for EvidenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvidenceError::NotConflicting => write!(f, "votes do not conflict"),
+            EvidenceError::UnknownValidator(v) => write!(f, "validator {} is not in the validator set", v),
+            EvidenceError::InvalidSignature(v) => write!(f, "vote is not signed by {}", v),
+            EvidenceError::AlreadyPunished { validator, height } => write!(f, "{} was already punished for height {}", validator, height),
+            EvidenceError::Tombstoned(v) => write!(f, "{} is already tombstoned", v),
+        }
+    }
+}
+
+impl std::error::Error for EvidenceError {}
+
+/// Check that `first` and `second` prove equivocation: two votes for different blocks at the
+/// same height, round and step, both validly signed by the same validator in `validator_set`
+pub fn verify_equivocation(chain_id: u64, validator_set: &ValidatorSet, first: &BftMessage, second: &BftMessage) -> Result<(), EvidenceError> {
+    let same_slot = first.height == second.height && first.round == second.round && first.step == second.step;
+    if !same_slot || first.validator != second.validator || first.block_hash == second.block_hash {
+        return Err(EvidenceError::NotConflicting);
+    }
+    let validator = validator_set.validators.iter().find(|v| v.address == first.validator)
+        .ok_or_else(|| EvidenceError::UnknownValidator(first.validator.clone()))?;
+    if !first.verify_signature(chain_id, &validator.public_key) || !second.verify_signature(chain_id, &validator.public_key) {
+        return Err(EvidenceError::InvalidSignature(first.validator.clone()));
+    }
+    Ok(())
+}
+
+/// Slash `percent` of `address`'s stake, delegations included, and of any stake still unbonding
+/// from it, and move it to `status` (jailed or tombstoned). Saves the set and returns the total penalty.
+pub fn slash_validator(storage: &crate::storage::ChainStorage, validator_set: &mut ValidatorSet, address: &str, percent: u64, status: ValidatorStatus) -> Option<u64> {
+    let val = validator_set.validators.iter_mut().find(|v| v.address == address)?;
+
+    // Delegators share the penalty in proportion to what they bonded
+    let mut delegations = storage.get_delegations(address);
+    // Slashing must go ahead even if the books are off, so a shortfall counts as no own bond
+    let mut penalty = val.own_stake(&delegations).unwrap_or(0) * percent / 100;
+    for delegation in &mut delegations {
+        let cut = delegation.amount * percent / 100;
+        delegation.amount -= cut;
+        penalty += cut;
+    }
+    if !delegations.is_empty() {
+        storage.save_delegations(address, &delegations);
+    }
+    val.stake = val.stake.saturating_sub(penalty);
+    val.status = status.clone();
+    validator_set.total_stake = validator_set.total_stake.saturating_sub(penalty);
+
+    // Nor does moving the delegation to another validator
+    let mut redelegations = storage.get_redelegations();
+    let mut redelegated_penalty = 0;
+    for entry in redelegations.iter_mut().filter(|r| r.from == address) {
+        let mut delegations = storage.get_delegations(&entry.to);
+        let Some(delegation) = delegations.iter_mut().find(|d| d.delegator == entry.delegator) else { continue };
+        // Whatever was undelegated from the new validator since is cut in the unbonding queue
+        let cut = (entry.amount * percent / 100).min(delegation.amount);
+        delegation.amount -= cut;
+        delegations.retain(|d| d.amount > 0);
+        storage.save_delegations(&entry.to, &delegations);
+        if let Some(target) = validator_set.validators.iter_mut().find(|v| v.address == entry.to) {
+            target.stake -= cut;
+        }
+        validator_set.total_stake = validator_set.total_stake.saturating_sub(cut);
+        entry.amount -= cut;
+        redelegated_penalty += cut;
+    }
+    if redelegated_penalty > 0 {
+        storage.save_redelegations(&redelegations);
+    }
+    storage.save_validator_set(validator_set);
+
+    // Unstaking does not outrun punishment for what the validator did while bonded
+    let mut queue = storage.get_unbonding_queue();
+    let mut unbonding_penalty = 0;
+    for entry in queue.iter_mut().filter(|e| e.validator == address) {
+        let cut = entry.amount * percent / 100;
+        entry.amount -= cut;
+        unbonding_penalty += cut;
+    }
+    if unbonding_penalty > 0 {
+        storage.save_unbonding_queue(&queue);
+    }
+
+    let total = penalty + unbonding_penalty + redelegated_penalty;
+    warn!("Institutional Slash: {} penalized by {}% (-{} AUR), now {:?}", address, percent, total, status);
+    Some(total)
+}
+
+/// Pay stake whose unbonding period ends by `height` back to its validators' balances
+fn release_unbonded(storage: &crate::storage::ChainStorage, height: u64) {
+    let (due, pending): (Vec<_>, Vec<_>) = storage.get_unbonding_queue().into_iter().partition(|e| e.release_height <= height);
+    if due.is_empty() {
+        return;
+    }
+    for entry in &due {
+        let balance = storage.get_balance(&entry.owner);
+        storage.update_balance(&entry.owner, balance + entry.amount);
+        info!("🔓 Unbonded {} AUR returned to {}", entry.amount, entry.owner);
+    }
+    storage.save_unbonding_queue(&pending);
+}
+
+/// Forget redelegations that are no longer slashable for the validator they left
+fn expire_redelegations(storage: &crate::storage::ChainStorage, height: u64) {
+    let mut redelegations = storage.get_redelegations();
+    let before = redelegations.len();
+    redelegations.retain(|r| r.completion_height > height);
+    if redelegations.len() != before {
+        storage.save_redelegations(&redelegations);
+    }
+}
+
+/// Whether `signed` is more than two thirds of the active stake.
+///
+/// Exactly two thirds is not enough: two such quorums may share only a third of the stake, all of it byzantine.
+fn has_quorum(validator_set: &ValidatorSet, signed: u64) -> bool {
+    signed as u128 * 3 > validator_set.active_stake() as u128 * 2
+}
+
+/// Least stake that satisfies `has_quorum`, for error reports
+fn quorum_stake(validator_set: &ValidatorSet) -> u64 {
+    (validator_set.active_stake() as u128 * 2 / 3 + 1) as u64
+}
+
+/// Under the authority veto, a block also needs at least one authority node among its voters
+fn authority_approved(validator_set: &ValidatorSet, voters: &[&Validator], authority_veto: bool) -> bool {
+    if !authority_veto || validator_set.get_authority_nodes().is_empty() {
+        return true;
+    }
+    voters.iter().any(|v| v.role == ValidatorRole::Authority)
+}
+
+/// Base step timeouts; every round waits twice as long as the one before so a slow network catches up
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeoutConfig {
+    pub propose_ms: u64,
+    pub prevote_ms: u64,
+    pub precommit_ms: u64,
+    /// Cap on any backed-off timeout
+    pub max_ms: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self { propose_ms: 5_000, prevote_ms: 2_000, precommit_ms: 2_000, max_ms: 60_000 }
+    }
+}
+
+impl TimeoutConfig {
+    /// How long `step` may last in `round`: its base timeout doubled every round, up to `max_ms`
+    pub fn timeout(&self, step: &BftStep, round: u32) -> u64 {
+        let base = match step {
+            BftStep::Propose => self.propose_ms,
+            BftStep::Prevote => self.prevote_ms,
+            BftStep::Precommit | BftStep::Commit => self.precommit_ms,
+        };
+        base.saturating_mul(1 << round.min(32)).min(self.max_ms)
+    }
+}
+
 pub struct ConsensusEngine {
     pub height: u64,
     pub round: u32,
@@ -31,6 +436,16 @@ pub struct ConsensusEngine {
     pub proposal: Option<Block>,
     pub locked_block: Option<(u32, Block)>, // (round, block)
     pub authority_veto_active: bool,
+    /// Chain id bound into every vote signature
+    pub chain_id: u64,
+    pub timeouts: TimeoutConfig,
+    /// When the current step's timeout clock started, in milliseconds
+    step_started: Option<u64>,
+    /// Where a validator records its votes before releasing them; `None` for followers and tests
+    pub wal: Option<ConsensusWal>,
+    /// Certificate of the last committed block and the set that signed it, topped up with
+    /// precommits that arrive after the commit
+    last_commit: Option<(CommitCertificate, ValidatorSet)>,
 }
 
 impl ConsensusEngine {
@@ -44,35 +459,80 @@ impl ConsensusEngine {
             proposal: None,
             locked_block: None,
             authority_veto_active: true,
+            chain_id: DEFAULT_CHAIN_ID,
+            timeouts: TimeoutConfig::default(),
+            step_started: None,
+            wal: None,
+            last_commit: None,
         }
     }
 
+    /// The validator whose turn it is to propose in the current round.
+    ///
+    /// The persisted priorities give round 0; each later round takes one more turn of the rotation.
     pub fn select_proposer(&self) -> String {
-        if self.validator_set.validators.is_empty() {
-            return "".to_string();
+        let mut set = self.validator_set.clone();
+        let mut proposer = None;
+        for _ in 0..=self.round {
+            proposer = set.rotate_proposer();
         }
+        proposer.unwrap_or_default()
+    }
 
-        let mut sorted_vals = self.validator_set.validators.clone();
-        sorted_vals.sort_by(|a, b| a.address.cmp(&b.address));
-
-        let total_stake = self.validator_set.total_stake;
-        if total_stake == 0 { return sorted_vals[0].address.clone(); }
-
-        let index_seed = (self.height + self.round as u64) % total_stake;
-        let mut current_sum = 0;
+    /// Address `key` signs as, if it belongs to an active validator in the current set
+    pub fn validator_address(&self, key: &ed25519_dalek::SigningKey) -> Option<String> {
+        let public_key = key.verifying_key().to_bytes();
+        self.validator_set.validators.iter().find(|v| v.public_key == public_key && v.is_active()).map(|v| v.address.clone())
+    }
 
-        for val in sorted_vals {
-            current_sum += val.stake;
-            if index_seed < current_sum {
-                return val.address;
+    /// Check that `proposal` is for the current round and signed by the validator whose turn it is.
+    /// A fresh block must also be built by that validator; a re-proposed one names an earlier polka.
+    ///
+    /// Only the signature is checked here; the block itself still has to pass `validate_block`.
+    pub fn verify_proposal(&self, proposal: &Proposal) -> Result<(), ProposalError> {
+        if proposal.block.header.height != self.height || proposal.round != self.round {
+            return Err(ProposalError::UnexpectedRound { height: proposal.block.header.height, round: proposal.round });
+        }
+        let expected = self.select_proposer();
+        if proposal.proposer != expected {
+            return Err(ProposalError::WrongProposer { expected, found: proposal.proposer.clone() });
+        }
+        match proposal.pol_round {
+            // Only a block locked in an earlier round may carry another validator's header
+            Some(pol_round) if pol_round >= proposal.round => {
+                return Err(ProposalError::InvalidPolRound { round: proposal.round, pol_round });
             }
+            Some(_) => {}
+            None if proposal.block.header.proposer != expected => {
+                return Err(ProposalError::WrongProposer { expected, found: proposal.block.header.proposer.clone() });
+            }
+            None => {}
+        }
+        let signed = self.validator_set.active(&expected)
+            .is_some_and(|v| proposal.verify_signature(self.chain_id, &v.public_key));
+        if !signed {
+            return Err(ProposalError::InvalidSignature(expected));
         }
+        Ok(())
+    }
 
-        self.validator_set.validators[0].address.clone()
+    /// Whether `msg` is signed by the key of an active validator in the current set
+    pub fn authenticate(&self, msg: &BftMessage) -> bool {
+        self.validator_set.active(&msg.validator)
+            .is_some_and(|v| msg.verify_signature(self.chain_id, &v.public_key))
     }
 
     pub fn process_message(&mut self, msg: BftMessage) -> bool {
-        if msg.height != self.height || msg.round < self.round {
+        if msg.height + 1 == self.height && msg.step == BftStep::Precommit {
+            self.add_late_precommit(msg);
+            return false;
+        }
+        // Votes too far ahead are dropped, so no validator can make us keep votes for endless rounds
+        if msg.height != self.height || msg.round < self.round || msg.round > self.round.saturating_add(MAX_ROUNDS_AHEAD) {
+            return false;
+        }
+        if !self.authenticate(&msg) {
+            warn!("Rejected unauthenticated {:?} vote from {}", msg.step, msg.validator);
             return false;
         }
 
@@ -85,10 +545,49 @@ impl ConsensusEngine {
             return false;
         }
 
+        let round = msg.round;
         step_votes.push(msg);
+
+        // More than 1/3 of the stake is already in a later round, so at least one honest validator is
+        if round > self.round && self.round_stake(round) * 3 > self.validator_set.active_stake() {
+            self.start_round(round);
+        }
         self.check_quasi_finality(self.round, self.step.clone())
     }
 
+    /// Add a precommit for the last committed block that arrived after the commit, so the next
+    /// proposal credits its signer with liveness
+    fn add_late_precommit(&mut self, msg: BftMessage) {
+        let Some((cert, signing_set)) = &mut self.last_commit else { return };
+        if msg.round != cert.round || msg.block_hash.as_deref() != Some(&cert.block_hash) {
+            return;
+        }
+        if cert.precommits.iter().any(|p| p.validator == msg.validator) {
+            return;
+        }
+        if signing_set.active(&msg.validator).is_some_and(|v| msg.verify_signature(self.chain_id, &v.public_key)) {
+            cert.precommits.push(msg);
+        }
+    }
+
+    /// Certificate for the last committed block, with every precommit received for it so far
+    pub fn last_commit(&self) -> Option<&CommitCertificate> {
+        self.last_commit.as_ref().map(|(cert, _)| cert)
+    }
+
+    /// Stake of the validators that voted in `round` of the current height
+    fn round_stake(&self, round: u32) -> u64 {
+        let voters: HashSet<&String> = [BftStep::Prevote, BftStep::Precommit].into_iter()
+            .filter_map(|step| self.votes.get(&(self.height, round, step)))
+            .flatten()
+            .map(|v| &v.validator)
+            .collect();
+        self.validator_set.validators.iter()
+            .filter(|v| v.is_active() && voters.contains(&v.address))
+            .map(|v| v.stake)
+            .sum()
+    }
+
     pub fn set_proposal(&mut self, block: Block) {
         if block.header.height == self.height {
             self.proposal = Some(block);
@@ -96,88 +595,252 @@ impl ConsensusEngine {
     }
 
     fn check_quasi_finality(&self, round: u32, step: BftStep) -> bool {
-        let key = (self.height, round, step);
-        let votes = match self.votes.get(&key) {
-            Some(v) => v,
-            None => return false,
-        };
+        self.quorum_hash(round, step).is_some()
+    }
 
-        let mut authority_approvals = 0;
-        let authority_nodes = self.validator_set.get_authority_nodes();
-        
-        // Use a set to track unique validators who voted for the SAME hash (or any hash for prevote/precommit skip)
-        // For simplicity, we check if 2/3 voted for the SAME hash if it's Precommit
-        let mut hash_counts: HashMap<Option<String>, u64> = HashMap::new();
+    /// The hash (`None` for nil) that more than 2/3 of the stake, including an authority, voted for
+    fn quorum_hash(&self, round: u32, step: BftStep) -> Option<Option<String>> {
+        let votes = self.votes.get(&(self.height, round, step))?;
+
+        // Group the voters by the hash they voted for; more than 2/3 must agree on the SAME hash
+        let mut voters_by_hash: HashMap<Option<String>, Vec<&Validator>> = HashMap::new();
         let mut unique_validators = HashSet::new();
 
         for vote in votes {
             if unique_validators.contains(&vote.validator) { continue; }
-            
-            if let Some(v) = self.validator_set.validators.iter().find(|val| val.address == vote.validator) {
-                let count = hash_counts.entry(vote.block_hash.clone()).or_insert(0);
-                *count += v.stake;
+
+            if let Some(v) = self.validator_set.active(&vote.validator) {
+                voters_by_hash.entry(vote.block_hash.clone()).or_default().push(v);
                 unique_validators.insert(vote.validator.clone());
-                
-                if v.role == ValidatorRole::Authority {
-                    authority_approvals += 1;
-                }
             }
         }
 
-        for (_hash, stake) in hash_counts {
-            let has_2_3_stake = stake >= (self.validator_set.total_stake * 2 / 3);
-            let authority_veto_pass = if self.authority_veto_active && !authority_nodes.is_empty() {
-                authority_approvals > 0 
-            } else {
-                true
-            };
+        voters_by_hash.into_iter().find(|(_, voters)| {
+            let stake: u64 = voters.iter().map(|v| v.stake).sum();
+            has_quorum(&self.validator_set, stake) && authority_approved(&self.validator_set, voters, self.authority_veto_active)
+        }).map(|(hash, _)| hash)
+    }
+
+    /// Sign and record this validator's vote for the current step, returning it for broadcast.
+    ///
+    /// Prevotes go to the proposal unless the validator is locked on another block, and
+    /// precommits to the proposal once 2/3 prevoted it, locking on it; otherwise the vote is
+    /// nil. Nothing is returned when `key` is not in the set or the vote was already cast.
+    pub fn cast_vote(&mut self, key: &ed25519_dalek::SigningKey) -> Option<BftMessage> {
+        if !matches!(self.step, BftStep::Prevote | BftStep::Precommit) {
+            return None;
+        }
+        let validator = self.validator_address(key)?;
+        let already_voted = self.votes.get(&(self.height, self.round, self.step.clone()))
+            .is_some_and(|votes| votes.iter().any(|v| v.validator == validator));
+        if already_voted {
+            return None;
+        }
+
+        let block_hash = match self.step {
+            BftStep::Prevote => self.prevote_choice(),
+            _ => self.precommit_choice(),
+        };
+        let mut vote = BftMessage { height: self.height, round: self.round, step: self.step.clone(), block_hash, validator, signature: vec![] };
+        vote.sign(self.chain_id, key);
+        if let Some(wal) = &mut self.wal
+            && let Err(e) = wal.record(&vote, self.locked_block.clone())
+        {
+            error!("🛑 Refusing to sign {:?} at height {} round {}: {}", vote.step, vote.height, vote.round, e);
+            return None;
+        }
+        self.process_message(vote.clone());
+        Some(vote)
+    }
+
+    /// Record the proposal `block` in the WAL before its proposer signs it for the current round.
+    ///
+    /// Returns false, and the block must not be signed, if a different block was already
+    /// proposed in this round or the validator has already voted in it.
+    pub fn record_proposal(&mut self, block: &Block) -> bool {
+        let Some(wal) = &mut self.wal else { return true };
+        let entry = BftMessage { height: self.height, round: self.round, step: BftStep::Propose, block_hash: Some(block.hash()), validator: block.header.proposer.clone(), signature: vec![] };
+        if let Err(e) = wal.record(&entry, self.locked_block.clone()) {
+            error!("🛑 Refusing to propose at height {} round {}: {}", self.height, self.round, e);
+            return false;
+        }
+        true
+    }
+
+    /// Resume the round this validator last signed in, with its vote and lock, after a restart
+    pub fn replay_wal(&mut self) {
+        let Some(state) = self.wal.as_ref().and_then(|wal| wal.last_signed()).cloned() else { return };
+        if state.vote.height != self.height {
+            return;
+        }
+        info!("Consensus: Resuming height {} round {} after signing {:?}", self.height, state.vote.round, state.vote.step);
+        self.round = state.vote.round;
+        self.locked_block = state.locked_block;
+        self.proposal = self.locked_block.as_ref().map(|(_, block)| block.clone());
+        self.enter_step(state.vote.step.clone());
+        // A recorded proposal is not a vote to count
+        if state.vote.step != BftStep::Propose {
+            self.process_message(state.vote);
+        }
+    }
+
+    /// Tendermint prevote rule: a validator locked on a block only prevotes a different
+    /// proposal if 2/3 prevoted that proposal in a round after it locked
+    fn prevote_choice(&self) -> Option<String> {
+        let proposal = self.proposal.as_ref()?.hash();
+        match &self.locked_block {
+            Some((locked_round, locked)) if locked.hash() != proposal => {
+                let unlocked = (locked_round + 1..self.round)
+                    .any(|round| self.quorum_hash(round, BftStep::Prevote) == Some(Some(proposal.clone())));
+                unlocked.then_some(proposal)
+            }
+            _ => Some(proposal),
+        }
+    }
+
+    /// Precommit the proposal, and lock on it, only if 2/3 prevoted it in this round
+    fn precommit_choice(&mut self) -> Option<String> {
+        let proposal = self.proposal.clone()?;
+        let hash = proposal.hash();
+        if self.quorum_hash(self.round, BftStep::Prevote) != Some(Some(hash.clone())) {
+            return None;
+        }
+        self.locked_block = Some((self.round, proposal));
+        Some(hash)
+    }
+
+    /// The proposal, once 2/3 precommitted it in the current round
+    pub fn decided_block(&self) -> Option<&Block> {
+        let hash = self.quorum_hash(self.round, BftStep::Precommit)??;
+        self.proposal.as_ref().filter(|b| b.hash() == hash)
+    }
 
-            if has_2_3_stake && authority_veto_pass {
-                return true;
+    /// Package the current round's precommits for `block_hash` into a certificate, if they reach quorum
+    pub fn commit_certificate(&self, block_hash: &str) -> Option<CommitCertificate> {
+        let votes = self.votes.get(&(self.height, self.round, BftStep::Precommit))?;
+        let mut precommits: Vec<BftMessage> = vec![];
+        let mut signers: Vec<&Validator> = vec![];
+        for vote in votes.iter().filter(|v| v.block_hash.as_deref() == Some(block_hash)) {
+            if precommits.iter().any(|p| p.validator == vote.validator) { continue; }
+            if let Some(v) = self.validator_set.active(&vote.validator) {
+                precommits.push(vote.clone());
+                signers.push(v);
             }
         }
 
-        false
+        let stake: u64 = signers.iter().map(|v| v.stake).sum();
+        if !has_quorum(&self.validator_set, stake) || !authority_approved(&self.validator_set, &signers, self.authority_veto_active) {
+            return None;
+        }
+        Some(CommitCertificate { height: self.height, round: self.round, block_hash: block_hash.to_string(), precommits })
     }
 
     pub fn next_step(&mut self, storage: &crate::storage::ChainStorage, block: Option<&Block>) {
-        let is_single_validator = self.validator_set.validators.len() == 1;
-        
+        // 2/3 precommits for a block decide the height whichever step we are in
+        if self.step != BftStep::Commit && matches!(self.quorum_hash(self.round, BftStep::Precommit), Some(Some(_))) {
+            self.enter_step(BftStep::Commit);
+            return;
+        }
+
         match self.step {
             BftStep::Propose => {
-                if is_single_validator || self.proposal.is_some() || self.round > 0 {
-                    self.step = BftStep::Prevote;
+                if self.proposal.is_some() {
+                    self.enter_step(BftStep::Prevote);
                 }
             },
+            // Every step needs authenticated votes from more than 2/3 of the stake, even with a single validator
             BftStep::Prevote => {
-                // Single-validator testnet: auto-approve
-                if is_single_validator || self.check_quasi_finality(self.round, BftStep::Prevote) {
-                    self.step = BftStep::Precommit;
-                } else {
-                    // Timeout or missed prevotes: could stay here or move to next round
+                // 2/3 prevoted the same block or nil; anything short of that waits for the timeout
+                if self.check_quasi_finality(self.round, BftStep::Prevote) {
+                    self.enter_step(BftStep::Precommit);
                 }
             },
             BftStep::Precommit => {
-                // Single-validator testnet: auto-approve
-                if is_single_validator || self.check_quasi_finality(self.round, BftStep::Precommit) {
-                    self.step = BftStep::Commit;
+                if self.quorum_hash(self.round, BftStep::Precommit) == Some(None) {
+                    self.start_round(self.round.saturating_add(1));
                 }
             },
             BftStep::Commit => {
-                if let Some(b) = block {
-                    self.process_block_finalization(storage, b);
+                match block {
+                    Some(b) => self.finalize_block(storage, b),
+                    None => {
+                        warn!("Consensus: decided block for height {} could not be committed", self.height);
+                        self.start_round(self.round.saturating_add(1));
+                    }
                 }
-                
-                self.height += 1;
-                self.round = 0;
-                self.step = BftStep::Propose;
-                self.votes.clear();
-                self.proposal = None;
-                info!("Consensus: Height {} Finalized", self.height - 1);
             }
         }
     }
 
+    /// Move past the current step once its timeout has elapsed, returning whether it did.
+    ///
+    /// The first call in a step starts its clock, so callers simply call this on every tick.
+    pub fn on_timeout(&mut self, now_ms: u64) -> bool {
+        let Some(started) = self.step_started else {
+            self.step_started = Some(now_ms);
+            return false;
+        };
+        if self.step == BftStep::Commit || now_ms < started + self.timeouts.timeout(&self.step, self.round) {
+            return false;
+        }
+
+        warn!("⏱️ Consensus: {:?} timed out at height {} round {}", self.step, self.height, self.round);
+        match self.step {
+            BftStep::Propose => self.enter_step(BftStep::Prevote),
+            BftStep::Prevote => self.enter_step(BftStep::Precommit),
+            _ => self.start_round(self.round.saturating_add(1)),
+        }
+        true
+    }
+
+    fn enter_step(&mut self, step: BftStep) {
+        self.step = step;
+        self.step_started = None;
+    }
+
+    /// Start `round` of the current height; a locked block stays the proposal to vote on
+    fn start_round(&mut self, round: u32) {
+        info!("Consensus: Height {} moving to round {}", self.height, round);
+        self.round = round;
+        // Earlier rounds only matter while a polka after the lock could still release it
+        let keep_from = self.locked_block.as_ref().map_or(round, |(locked, _)| locked.saturating_add(1).min(round));
+        self.votes.retain(|(_, vote_round, _), _| *vote_round >= keep_from);
+        self.proposal = self.locked_block.as_ref().map(|(_, block)| block.clone());
+        self.enter_step(BftStep::Propose);
+    }
+
+    /// Write the block-level changes of finalizing `block` (fee burn, rewards, downtime
+    /// slashing, matured unbonding) into `storage`, usually a block overlay.
+    ///
+    /// Works from the validator set in `storage` and leaves the engine untouched, so it can be
+    /// run speculatively while building or validating a block.
+    pub fn apply_finalization(&self, storage: &crate::storage::ChainStorage, block: &Block) {
+        let validator_set = storage.get_validator_set().unwrap_or_else(|| self.validator_set.clone());
+        let mut scratch = ConsensusEngine::new(validator_set);
+        scratch.height = block.header.height;
+        scratch.process_block_finalization(storage, block);
+    }
+
+    /// Adopt the validator set committed with `block` and continue at the height after it
+    pub fn finalize_block(&mut self, storage: &crate::storage::ChainStorage, block: &Block) {
+        let cert = self.commit_certificate(&block.hash()).or_else(|| storage.get_commit_certificate(block.header.height));
+        self.last_commit = cert.map(|cert| (cert, self.validator_set.clone()));
+        if let Some(set) = storage.get_validator_set() {
+            self.validator_set = set;
+        }
+        self.advance_to(block.header.height + 1);
+    }
+
+    fn advance_to(&mut self, height: u64) {
+        self.height = height;
+        self.round = 0;
+        self.enter_step(BftStep::Propose);
+        self.votes.clear();
+        self.proposal = None;
+        self.locked_block = None;
+        info!("Consensus: Height {} Finalized", self.height - 1);
+    }
+
     fn process_block_finalization(&mut self, storage: &crate::storage::ChainStorage, block: &Block) {
         let total_fees: u64 = block.transactions.iter().map(|tx| tx.fee).sum();
         let burn_amount = total_fees / 2;
@@ -189,90 +852,144 @@ impl ConsensusEngine {
             storage.save_chain_state(&state);
         }
 
+        // Hand the next height to the next proposer in the rotation; saved along with the rewards
+        self.validator_set.rotate_proposer();
         self.distribute_rewards(storage, validator_reward);
-        self.check_downtime_slashing(storage);
+        self.check_downtime_slashing(storage, block);
+        release_unbonded(storage, block.header.height);
+        expire_redelegations(storage, block.header.height);
     }
 
+    /// Split the block reward and the block issuance between active validators in proportion to
+    /// their voting stake. Each pays its delegators their pro rata part of its share, less its
+    /// commission, and keeps the rest.
+    ///
+    /// Rewards go to balances; bonded stake only changes through staking transactions and slashing.
     fn distribute_rewards(&mut self, storage: &crate::storage::ChainStorage, reward: u64) {
-        if self.validator_set.validators.is_empty() { return; }
-        
-        let share = (reward + 100) / self.validator_set.validators.len() as u64; // Base 100 AUR
-        for validator in &mut self.validator_set.validators {
-            validator.stake += share;
-            let bal = storage.get_balance(&validator.address);
-            storage.update_balance(&validator.address, bal + share);
+        // Jailed and tombstoned validators and their delegators earn nothing
+        let active_stake = self.validator_set.active_stake();
+        let pool = reward + BLOCK_ISSUANCE;
+        let mut paid = 0;
+        if active_stake > 0 {
+            for validator in self.validator_set.validators.iter().filter(|v| v.is_active() && v.stake > 0) {
+                let share = (pool as u128 * validator.stake as u128 / active_stake as u128) as u64;
+                let mut kept = share;
+                for delegation in storage.get_delegations(&validator.address) {
+                    let gross = (share as u128 * delegation.amount as u128 / validator.stake as u128) as u64;
+                    let payout = gross - gross * validator.commission_bps / 10_000;
+                    let bal = storage.get_balance(&delegation.delegator);
+                    storage.update_balance(&delegation.delegator, bal + payout);
+                    kept -= payout;
+                }
+                let bal = storage.get_balance(&validator.address);
+                storage.update_balance(&validator.address, bal + kept);
+                paid += share;
+            }
+        }
+
+        // The fee reward was already in circulation; only the paid-out issuance is new supply
+        if let Some(mut state) = storage.get_chain_state() {
+            state.total_supply = (state.total_supply + paid).saturating_sub(reward);
+            storage.save_chain_state(&state);
         }
-        self.validator_set.total_stake += reward + 100;
         storage.save_validator_set(&self.validator_set);
     }
 
-    fn check_downtime_slashing(&mut self, storage: &crate::storage::ChainStorage) {
-        let current_height = self.height;
-        let mut slashed = false;
-        
+    /// Record who signed the parent block from the certificate `block` carries, then slash and
+    /// jail active validators that missed too many of the last `signed_blocks_window` blocks.
+    ///
+    /// Blocks without a last commit leave every record untouched.
+    fn check_downtime_slashing(&mut self, storage: &crate::storage::ChainStorage, block: &Block) {
+        let Some(cert) = &block.last_commit else { return };
+        let params = storage.get_slashing_params();
+        let height = cert.height;
+        let signers: HashSet<String> = cert.signers().into_iter().collect();
+        // Only the set that signed the parent could have signed its commit; validators that
+        // joined or were unjailed since are judged from the next block on
+        let signing_set = storage.get_previous_validator_set();
+
+        let mut offline = vec![];
         for val in &mut self.validator_set.validators {
-            // Simple logic: if validator hasn't been active for 100 blocks, slash 1%
-            if current_height > val.last_active + 100 && val.stake > 1000 {
-                let penalty = val.stake / 100; // 1% slash
-                val.stake -= penalty;
-                self.validator_set.total_stake -= penalty;
-                
-                let current_bal = storage.get_balance(&val.address);
-                storage.update_balance(&val.address, current_bal.saturating_sub(penalty));
-                
-                warn!("Slashing validator {} for downtime: -{} AUR", val.address, penalty);
-                slashed = true;
+            if !val.is_active() || signing_set.as_ref().is_some_and(|set| set.active(&val.address).is_none()) {
+                continue;
             }
-        }
-        
-        if slashed {
-            storage.save_validator_set(&self.validator_set);
-        }
-    }
+            let mut record = storage.get_liveness(&val.address).unwrap_or(LivenessRecord { since: height, missed: vec![] });
+            if signers.contains(&val.address) {
+                val.last_active = height;
+            } else {
+                record.missed.push(height);
+            }
+            record.missed.retain(|missed| missed + params.signed_blocks_window > height);
 
-    pub fn detect_equivocation(&mut self, msg: &BftMessage, storage: &crate::storage::ChainStorage) -> bool {
-        let key = (msg.height, msg.round, msg.step.clone());
-        if let Some(votes) = self.votes.get(&key) {
-            for vote in votes {
-                if vote.validator == msg.validator && vote.block_hash != msg.block_hash {
-                    error!("CRITICAL: Malicious Equivocation detected by {} at height {}", msg.validator, msg.height);
-                    self.slash_validator(&msg.validator, 0.50, storage); // 50% slash for malicious behavior
-                    return true;
-                }
+            let judged = height + 1 >= record.since + params.signed_blocks_window;
+            if judged && record.missed.len() as u64 > params.max_missed_blocks {
+                warn!("Slashing validator {} for downtime: missed {} of the last {} blocks", val.address, record.missed.len(), params.signed_blocks_window);
+                offline.push(val.address.clone());
+                // A jailed validator starts over with a fresh window once it returns
+                storage.delete_liveness(&val.address);
+            } else {
+                storage.save_liveness(&val.address, &record);
             }
         }
-        false
-    }
 
-    fn slash_validator(&mut self, address: &str, percentage: f64, storage: &crate::storage::ChainStorage) {
-        if let Some(val) = self.validator_set.validators.iter_mut().find(|v| v.address == address) {
-            let penalty = (val.stake as f64 * percentage) as u64;
-            val.stake = val.stake.saturating_sub(penalty);
-            self.validator_set.total_stake = self.validator_set.total_stake.saturating_sub(penalty);
-            
-            let current_bal = storage.get_balance(address);
-            storage.update_balance(address, current_bal.saturating_sub(penalty));
-            storage.save_validator_set(&self.validator_set);
-            
-            warn!("Institutional Slash: {} penalized by {}% (-{} AUR)", address, percentage * 100.0, penalty);
+        storage.save_validator_set(&self.validator_set);
+        for address in offline {
+            slash_validator(storage, &mut self.validator_set, &address, params.downtime_slash_percent, ValidatorStatus::Jailed { until: block.header.height + params.downtime_jail_blocks });
         }
     }
+
+    /// A vote `msg`'s validator already cast for a different block at the same height, round
+    /// and step; together the two prove equivocation and go on chain as an `Evidence` transaction
+    pub fn detect_equivocation(&self, msg: &BftMessage) -> Option<BftMessage> {
+        let key = (msg.height, msg.round, msg.step.clone());
+        let earlier = self.votes.get(&key)?.iter()
+            .find(|vote| vote.validator == msg.validator && vote.block_hash != msg.block_hash)?;
+        error!("CRITICAL: Malicious Equivocation detected by {} at height {}", msg.validator, msg.height);
+        Some(earlier.clone())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::core::{Validator, ValidatorRole, ValidatorSet};
+    use crate::storage::ChainStorage;
+    use ed25519_dalek::SigningKey;
+
+    fn key(i: u8) -> SigningKey {
+        SigningKey::from_bytes(&[i; 32])
+    }
+
+    fn validator(i: u8, role: ValidatorRole) -> Validator {
+        Validator { address: format!("val{}", i), public_key: key(i).verifying_key().to_bytes().to_vec(), stake: 100, role, last_active: 0, proposer_priority: 0, status: ValidatorStatus::Active, commission_bps: 0 }
+    }
 
     fn setup_engine() -> ConsensusEngine {
         let vals = vec![
-            Validator { address: "val1".into(), public_key: vec![], stake: 100, role: ValidatorRole::Authority, last_active: 0 },
-            Validator { address: "val2".into(), public_key: vec![], stake: 100, role: ValidatorRole::Standard, last_active: 0 },
-            Validator { address: "val3".into(), public_key: vec![], stake: 100, role: ValidatorRole::Standard, last_active: 0 },
+            validator(1, ValidatorRole::Authority),
+            validator(2, ValidatorRole::Standard),
+            validator(3, ValidatorRole::Standard),
         ];
         ConsensusEngine::new(ValidatorSet { validators: vals, total_stake: 300 })
     }
 
+    /// Like `setup_engine`, but val2 and val3 hold 150 each: without the authority they have 3/4 of the stake
+    fn setup_weighted_engine() -> ConsensusEngine {
+        let vals = vec![
+            validator(1, ValidatorRole::Authority),
+            Validator { stake: 150, ..validator(2, ValidatorRole::Standard) },
+            Validator { stake: 150, ..validator(3, ValidatorRole::Standard) },
+        ];
+        ConsensusEngine::new(ValidatorSet { validators: vals, total_stake: 400 })
+    }
+
+    /// Vote signed by validator `i` of `setup_engine`
+    fn vote(i: u8, step: BftStep, block_hash: Option<String>) -> BftMessage {
+        let mut msg = BftMessage { height: 1, round: 0, step, block_hash, validator: format!("val{}", i), signature: vec![] };
+        msg.sign(DEFAULT_CHAIN_ID, &key(i));
+        msg
+    }
+
     #[test]
     fn test_honest_consensus() {
         let mut engine = setup_engine();
@@ -281,26 +998,690 @@ mod tests {
         let hash = Some("hash1".into());
         
         // Round 0 Prevotes
-        engine.process_message(BftMessage { height: 1, round: 0, step: BftStep::Prevote, block_hash: hash.clone(), validator: "val1".into(), signature: vec![] });
-        engine.process_message(BftMessage { height: 1, round: 0, step: BftStep::Prevote, block_hash: hash.clone(), validator: "val2".into(), signature: vec![] });
-        
+        engine.process_message(vote(1, BftStep::Prevote, hash.clone()));
+        engine.process_message(vote(2, BftStep::Prevote, hash.clone()));
+
+        // Exactly 2/3 of the stake (200/300) is not a quorum
+        assert!(!engine.check_quasi_finality(0, BftStep::Prevote));
+        engine.process_message(vote(3, BftStep::Prevote, hash.clone()));
         assert!(engine.check_quasi_finality(0, BftStep::Prevote));
     }
 
     #[test]
     fn test_authority_veto() {
-        let mut engine = setup_engine();
+        let mut engine = setup_weighted_engine();
         let hash = Some("hash1".into());
 
-        // val2 and val3 has 2/3 stake (200/300)
-        engine.process_message(BftMessage { height: 1, round: 0, step: BftStep::Prevote, block_hash: hash.clone(), validator: "val2".into(), signature: vec![] });
-        engine.process_message(BftMessage { height: 1, round: 0, step: BftStep::Prevote, block_hash: hash.clone(), validator: "val3".into(), signature: vec![] });
+        // val2 and val3 have more than 2/3 of the stake (300/400)
+        engine.process_message(vote(2, BftStep::Prevote, hash.clone()));
+        engine.process_message(vote(3, BftStep::Prevote, hash.clone()));
 
-        // Should FAIL despite 2/3 stake because NO authority (val1) approved
+        // Should FAIL despite the stake because NO authority (val1) approved
         assert!(!engine.check_quasi_finality(0, BftStep::Prevote));
         
         // Now val1 votes
-        engine.process_message(BftMessage { height: 1, round: 0, step: BftStep::Prevote, block_hash: hash.clone(), validator: "val1".into(), signature: vec![] });
+        engine.process_message(vote(1, BftStep::Prevote, hash.clone()));
+        assert!(engine.check_quasi_finality(0, BftStep::Prevote));
+    }
+
+    #[test]
+    fn test_equivocation_is_detected() {
+        let mut engine = setup_engine();
+        let first = vote(2, BftStep::Prevote, Some("hash1".into()));
+        engine.process_message(first.clone());
+        assert!(engine.detect_equivocation(&first).is_none());
+
+        // The conflicting vote is not counted, but the pair proves the double vote
+        let second = vote(2, BftStep::Prevote, Some("hash2".into()));
+        let earlier = engine.detect_equivocation(&second).unwrap();
+        assert!(!engine.process_message(second.clone()));
+        assert_eq!(verify_equivocation(DEFAULT_CHAIN_ID, &engine.validator_set, &earlier, &second), Ok(()));
+        assert_eq!(verify_equivocation(DEFAULT_CHAIN_ID, &engine.validator_set, &earlier, &first), Err(EvidenceError::NotConflicting));
+    }
+
+    #[test]
+    fn test_unauthenticated_votes_are_ignored() {
+        let mut engine = setup_engine();
+        let hash = Some("hash1".into());
+
+        let mut unsigned = vote(1, BftStep::Prevote, hash.clone());
+        unsigned.signature = vec![];
+        let mut impersonated = vote(2, BftStep::Prevote, hash.clone());
+        impersonated.validator = "val1".into();
+        let mut other_chain = BftMessage { height: 1, round: 0, step: BftStep::Prevote, block_hash: hash.clone(), validator: "val1".into(), signature: vec![] };
+        other_chain.sign(DEFAULT_CHAIN_ID + 1, &key(1));
+        let mut outsider = BftMessage { height: 1, round: 0, step: BftStep::Prevote, block_hash: hash.clone(), validator: "val9".into(), signature: vec![] };
+        outsider.sign(DEFAULT_CHAIN_ID, &key(9));
+
+        for msg in [unsigned, impersonated, other_chain, outsider] {
+            assert!(!engine.authenticate(&msg));
+            engine.process_message(msg);
+        }
+        assert!(engine.votes.values().all(|v| v.is_empty()));
+
+        // A forged vote cannot take the real validator's place
+        engine.process_message(vote(2, BftStep::Prevote, hash.clone()));
+        engine.process_message(vote(3, BftStep::Prevote, hash.clone()));
+        assert!(!engine.check_quasi_finality(0, BftStep::Prevote));
+        engine.process_message(vote(1, BftStep::Prevote, hash));
+        assert!(engine.check_quasi_finality(0, BftStep::Prevote));
+    }
+
+    #[test]
+    fn test_single_validator_votes_to_commit() {
+        let storage = ChainStorage::new("test_data/consensus_single_validator_db");
+        let mut engine = ConsensusEngine::new(ValidatorSet { validators: vec![validator(1, ValidatorRole::Authority)], total_stake: 100 });
+        let mut block = Block::new_genesis(DEFAULT_CHAIN_ID);
+        block.header.height = 1;
+
+        // Without a proposal there is nothing to vote for
+        engine.next_step(&storage, None);
+        assert_eq!(engine.step, BftStep::Propose);
+        engine.set_proposal(block.clone());
+        engine.next_step(&storage, None);
+        assert_eq!(engine.step, BftStep::Prevote);
+
+        // No shortcut: the validator's own signed votes carry each step
+        engine.next_step(&storage, None);
+        assert_eq!(engine.step, BftStep::Prevote);
+        let prevote = engine.cast_vote(&key(1)).unwrap();
+        assert_eq!(prevote.block_hash, Some(block.hash()));
+        assert!(engine.cast_vote(&key(1)).is_none());
+        engine.next_step(&storage, None);
+        assert_eq!(engine.step, BftStep::Precommit);
+
+        let precommit = engine.cast_vote(&key(1)).unwrap();
+        assert_eq!(precommit.step, BftStep::Precommit);
+        engine.next_step(&storage, None);
+        assert_eq!(engine.step, BftStep::Commit);
+        let cert = engine.commit_certificate(&block.hash()).unwrap();
+        assert_eq!(cert.verify(DEFAULT_CHAIN_ID, 1, &block.hash(), &engine.validator_set, true), Ok(()));
+
+        // Keys outside the validator set cannot vote
+        assert!(ConsensusEngine::new(engine.validator_set.clone()).cast_vote(&key(2)).is_none());
+    }
+
+    #[test]
+    fn test_commit_certificate() {
+        let keys: Vec<SigningKey> = (1..=3u8).map(key).collect();
+        let mut engine = setup_weighted_engine();
+        engine.chain_id = 7;
+        let set = engine.validator_set.clone();
+        let precommit = |i: usize| {
+            let mut msg = BftMessage { height: 1, round: 0, step: BftStep::Precommit, block_hash: Some("hash1".into()), validator: format!("val{}", i + 1), signature: vec![] };
+            msg.sign(7, &keys[i]);
+            msg
+        };
+
+        // Standard validators alone hold more than 2/3 of the stake but lack authority approval
+        engine.process_message(precommit(1));
+        engine.process_message(precommit(2));
+        assert!(engine.commit_certificate("hash1").is_none());
+
+        engine.process_message(precommit(0));
+        let cert = engine.commit_certificate("hash1").expect("quorum reached");
+        assert_eq!(cert.signers(), vec!["val2", "val3", "val1"]);
+        assert_eq!(cert.verify(7, 1, "hash1", &set, true), Ok(()));
+
+        // Certificates are bound to the chain, the block and valid signatures
+        assert_eq!(cert.verify(8, 1, "hash1", &set, true), Err(CertificateError::InvalidSignature("val2".into())));
+        assert!(matches!(cert.verify(7, 1, "hash2", &set, true), Err(CertificateError::BlockMismatch { .. })));
+        let mut forged = cert.clone();
+        forged.precommits[0].signature = precommit(0).signature;
+        assert_eq!(forged.verify(7, 1, "hash1", &set, true), Err(CertificateError::InvalidSignature("val2".into())));
+
+        let mut partial = cert.clone();
+        partial.precommits.truncate(1);
+        assert_eq!(partial.verify(7, 1, "hash1", &set, true), Err(CertificateError::InsufficientStake { signed: 150, required: 267 }));
+        partial.precommits.push(precommit(2));
+        assert_eq!(partial.verify(7, 1, "hash1", &set, true), Err(CertificateError::MissingAuthorityApproval));
+        assert_eq!(partial.verify(7, 1, "hash1", &set, false), Ok(()));
+        partial.precommits.push(precommit(2));
+        assert_eq!(partial.verify(7, 1, "hash1", &set, false), Err(CertificateError::DuplicateSigner("val3".into())));
+    }
+
+    /// Four equal-stake validators exchanging votes over a lossless, in-order network
+    struct Cluster {
+        engines: Vec<ConsensusEngine>,
+        keys: Vec<SigningKey>,
+        storage: ChainStorage,
+        now_ms: u64,
+    }
+
+    impl Cluster {
+        fn new(db: &str) -> Self {
+            let keys: Vec<SigningKey> = (1..=4u8).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+            let validators = keys.iter().enumerate().map(|(i, key)| Validator {
+                address: format!("val{}", i + 1),
+                public_key: key.verifying_key().to_bytes().to_vec(),
+                stake: 100,
+                role: ValidatorRole::Standard,
+                last_active: 0,
+                proposer_priority: 0,
+                status: ValidatorStatus::Active,
+                commission_bps: 0,
+            }).collect();
+            let set = ValidatorSet { validators, total_stake: 400 };
+            let engines = (0..4).map(|_| ConsensusEngine::new(set.clone())).collect();
+            Self { engines, keys, storage: ChainStorage::new(db), now_ms: 0 }
+        }
+
+        fn propose(&mut self, block: &Block, to: &[usize]) {
+            for &i in to {
+                self.engines[i].set_proposal(block.clone());
+            }
+        }
+
+        /// Let the `online` validators vote and advance until nothing changes
+        fn run(&mut self, online: &[usize]) {
+            loop {
+                let before: Vec<_> = self.engines.iter().map(|e| (e.round, e.step.clone())).collect();
+                for &i in online {
+                    let Some(vote) = self.engines[i].cast_vote(&self.keys[i]) else { continue };
+                    for &j in online.iter().filter(|&&j| j != i) {
+                        self.engines[j].process_message(vote.clone());
+                    }
+                }
+                for &i in online {
+                    if self.engines[i].step != BftStep::Commit {
+                        self.engines[i].next_step(&self.storage, None);
+                    }
+                }
+                let after: Vec<_> = self.engines.iter().map(|e| (e.round, e.step.clone())).collect();
+                if before == after {
+                    return;
+                }
+            }
+        }
+
+        /// Advance the clock by `ms` and fire any expired timeouts
+        fn elapse(&mut self, ms: u64, online: &[usize]) {
+            for &i in online {
+                self.engines[i].on_timeout(self.now_ms);
+            }
+            self.now_ms += ms;
+            for &i in online {
+                self.engines[i].on_timeout(self.now_ms);
+            }
+        }
+    }
+
+    fn block(tag: u64) -> Block {
+        let mut block = Block::new_genesis(DEFAULT_CHAIN_ID);
+        block.header.height = 1;
+        block.header.timestamp = tag;
+        block
+    }
+
+    const ALL: [usize; 4] = [0, 1, 2, 3];
+
+    #[test]
+    fn test_timeouts_back_off_exponentially() {
+        let timeouts = TimeoutConfig { propose_ms: 1_000, prevote_ms: 500, precommit_ms: 500, max_ms: 10_000 };
+        assert_eq!(timeouts.timeout(&BftStep::Propose, 0), 1_000);
+        assert_eq!(timeouts.timeout(&BftStep::Propose, 3), 8_000);
+        assert_eq!(timeouts.timeout(&BftStep::Prevote, 1), 1_000);
+        assert_eq!(timeouts.timeout(&BftStep::Propose, 4), 10_000);
+        assert_eq!(timeouts.timeout(&BftStep::Precommit, u32::MAX), 10_000);
+
+        // The clock starts on the first call in a step
+        let mut engine = Cluster::new("test_data/consensus_backoff_db").engines.remove(0);
+        engine.timeouts = timeouts;
+        assert!(!engine.on_timeout(50_000));
+        assert!(!engine.on_timeout(50_999));
+        assert!(engine.on_timeout(51_000));
+        assert_eq!(engine.step, BftStep::Prevote);
+    }
+
+    #[test]
+    fn test_offline_proposer_moves_to_next_round() {
+        let mut cluster = Cluster::new("test_data/consensus_offline_proposer_db");
+
+        // Nobody receives a proposal: the propose timeout leads to nil prevotes and nil precommits
+        cluster.run(&ALL);
+        assert!(cluster.engines.iter().all(|e| e.step == BftStep::Propose));
+        cluster.elapse(5_000, &ALL);
+        cluster.run(&ALL);
+        for engine in &cluster.engines {
+            assert_eq!((engine.round, engine.step.clone()), (1, BftStep::Propose));
+            // The round 0 nil votes are dropped once the round is over
+            assert!(engine.votes.is_empty());
+            assert!(engine.locked_block.is_none());
+        }
+
+        // Round 1 waits twice as long for its proposal
+        cluster.elapse(9_999, &ALL);
+        assert!(cluster.engines.iter().all(|e| e.step == BftStep::Propose));
+
+        let b = block(1);
+        cluster.propose(&b, &ALL);
+        cluster.run(&ALL);
+        for engine in &cluster.engines {
+            assert_eq!(engine.step, BftStep::Commit);
+            assert_eq!(engine.decided_block().map(|d| d.hash()), Some(b.hash()));
+            let cert = engine.commit_certificate(&b.hash()).unwrap();
+            assert_eq!(cert.round, 1);
+            assert_eq!(cert.verify(DEFAULT_CHAIN_ID, 1, &b.hash(), &engine.validator_set, true), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_split_prevotes_time_out_to_nil() {
+        let mut cluster = Cluster::new("test_data/consensus_split_db");
+
+        // Half the validators see one block, half another: no polka either way
+        let (a, b) = (block(1), block(2));
+        cluster.propose(&a, &[0, 1]);
+        cluster.propose(&b, &[2, 3]);
+        cluster.run(&ALL);
+        assert!(cluster.engines.iter().all(|e| e.step == BftStep::Prevote));
+
+        cluster.elapse(2_000, &ALL);
+        cluster.run(&ALL);
+        for engine in &cluster.engines {
+            assert_eq!((engine.round, engine.step.clone()), (1, BftStep::Propose));
+            assert!(engine.locked_block.is_none());
+            assert!(engine.proposal.is_none());
+        }
+    }
+
+    #[test]
+    fn test_locked_validator_prevotes_nil_for_other_blocks() {
+        let mut cluster = Cluster::new("test_data/consensus_locking_db");
+
+        // Round 0: everyone prevotes A, but only val1 receives enough prevotes to see the polka
+        let a = block(1);
+        cluster.propose(&a, &ALL);
+        for i in ALL {
+            cluster.run(&[i]);
+        }
+        for i in [1, 2] {
+            let prevote = cluster.engines[i].votes[&(1, 0, BftStep::Prevote)][0].clone();
+            cluster.engines[0].process_message(prevote);
+        }
+        cluster.run(&[0]);
+        assert_eq!(cluster.engines[0].votes[&(1, 0, BftStep::Precommit)][0].block_hash, Some(a.hash()));
+        assert_eq!(cluster.engines[0].locked_block.as_ref().map(|(r, b)| (*r, b.hash())), Some((0, a.hash())));
+
+        // The others time out into nil precommits and decide to move on; so does val1
+        cluster.elapse(2_000, &[1, 2, 3]);
+        cluster.run(&[1, 2, 3]);
+        cluster.elapse(2_000, &[0]);
+        assert!(cluster.engines.iter().all(|e| (e.round, e.step.clone()) == (1, BftStep::Propose)));
+        assert!(cluster.engines[1..].iter().all(|e| e.locked_block.is_none()));
+
+        // The locked block stays val1's proposal; against a new proposal B it prevotes nil
+        assert_eq!(cluster.engines[0].proposal.as_ref().map(|p| p.hash()), Some(a.hash()));
+        let b = block(2);
+        cluster.propose(&b, &ALL);
+        cluster.run(&ALL);
+        let prevotes = &cluster.engines[1].votes[&(1, 1, BftStep::Prevote)];
+        assert_eq!(prevotes.iter().find(|v| v.validator == "val1").unwrap().block_hash, None);
+
+        // val2..val4 hold 3/4 of the stake, so B is decided and val1 relocks on the new polka
+        assert!(cluster.engines.iter().all(|e| e.decided_block().map(|d| d.hash()) == Some(b.hash())));
+        assert_eq!(cluster.engines[0].locked_block.as_ref().map(|(r, b)| (*r, b.hash())), Some((1, b.hash())));
+
+        // Committing clears the lock
+        let storage = &cluster.storage;
+        cluster.engines[0].next_step(storage, Some(&b));
+        assert_eq!((cluster.engines[0].height, cluster.engines[0].round), (2, 0));
+        assert!(cluster.engines[0].locked_block.is_none());
+    }
+
+    #[test]
+    fn test_polka_after_lock_round_unlocks() {
+        let cluster = Cluster::new("test_data/consensus_unlock_db");
+        let mut engine = cluster.engines.into_iter().next().unwrap();
+        let (a, b) = (block(1), block(2));
+        let prevote = |i: usize, round: u32| {
+            let mut vote = BftMessage { height: 1, round, step: BftStep::Prevote, block_hash: Some(b.hash()), validator: format!("val{}", i + 1), signature: vec![] };
+            vote.sign(DEFAULT_CHAIN_ID, &cluster.keys[i]);
+            vote
+        };
+
+        engine.locked_block = Some((1, a));
+        engine.round = 1;
+        for i in 1..4 {
+            engine.process_message(prevote(i, 1));
+        }
+        engine.round = 3;
+        engine.proposal = Some(b.clone());
+
+        // A polka in the round the lock was taken does not release it
+        assert_eq!(engine.prevote_choice(), None);
+
+        engine.round = 2;
+        for i in 1..4 {
+            engine.process_message(prevote(i, 2));
+        }
+        engine.round = 3;
+        assert_eq!(engine.prevote_choice(), Some(b.hash()));
+    }
+
+    #[test]
+    fn test_only_the_round_proposer_can_propose() {
+        let mut cluster = Cluster::new("test_data/consensus_proposal_db");
+        let proposer = cluster.engines[0].select_proposer();
+        let index = cluster.engines[0].validator_set.validators.iter().position(|v| v.address == proposer).unwrap();
+        let other = (index + 1) % 4;
+        let signed = |signer: usize, proposer: &str, round: u32| {
+            let mut block = block(1);
+            block.header.proposer = proposer.to_string();
+            let mut proposal = Proposal { round, pol_round: None, proposer: proposer.to_string(), block, signature: vec![] };
+            proposal.sign(DEFAULT_CHAIN_ID, &cluster.keys[signer]);
+            proposal
+        };
+        let engine = &cluster.engines[other];
+
+        assert_eq!(engine.verify_proposal(&signed(index, &proposer, 0)), Ok(()));
+        let impostor = format!("val{}", other + 1);
+        assert_eq!(engine.verify_proposal(&signed(other, &impostor, 0)), Err(ProposalError::WrongProposer { expected: proposer.clone(), found: impostor }));
+        assert_eq!(engine.verify_proposal(&signed(other, &proposer, 0)), Err(ProposalError::InvalidSignature(proposer.clone())));
+        let early = engine.verify_proposal(&signed(index, &proposer, 1)).unwrap_err();
+        assert_eq!(early, ProposalError::UnexpectedRound { height: 1, round: 1 });
+        assert!(!early.is_fault());
+
+        // Without a verified proposal the validators wait, then prevote nil
+        cluster.run(&ALL);
+        cluster.elapse(5_000, &ALL);
+        let prevote = cluster.engines[other].cast_vote(&cluster.keys[other]).unwrap();
+        assert_eq!((prevote.step, prevote.block_hash), (BftStep::Prevote, None));
+    }
+
+    #[test]
+    fn test_locked_block_is_reproposed_by_next_proposer() {
+        let mut cluster = Cluster::new("test_data/consensus_repropose_db");
+        let set = cluster.engines[0].validator_set.clone();
+        let index_of = |address: &str| set.validators.iter().position(|v| v.address == address).unwrap();
+
+        // Round 0: the proposer's block gathers a polka everywhere, but no precommit is delivered
+        let builder = cluster.engines[0].select_proposer();
+        let mut a = block(1);
+        a.header.proposer = builder.clone();
+        let mut proposal = Proposal { round: 0, pol_round: None, proposer: builder.clone(), block: a.clone(), signature: vec![] };
+        proposal.sign(DEFAULT_CHAIN_ID, &cluster.keys[index_of(&builder)]);
+        for engine in &mut cluster.engines {
+            assert_eq!(engine.verify_proposal(&proposal), Ok(()));
+            engine.set_proposal(a.clone());
+        }
+        for i in ALL {
+            cluster.run(&[i]);
+        }
+        let prevotes: Vec<_> = ALL.iter().map(|&i| cluster.engines[i].votes[&(1, 0, BftStep::Prevote)][0].clone()).collect();
+        for i in ALL {
+            for prevote in prevotes.iter().filter(|v| v.validator != format!("val{}", i + 1)) {
+                cluster.engines[i].process_message(prevote.clone());
+            }
+            cluster.run(&[i]);
+            cluster.elapse(2_000, &[i]);
+        }
+        for engine in &cluster.engines {
+            assert_eq!((engine.round, engine.step.clone()), (1, BftStep::Propose));
+            assert_eq!(engine.locked_block.as_ref().map(|(r, b)| (*r, b.hash())), Some((0, a.hash())));
+        }
+
+        // Round 1: a different validator proposes the locked block, whose header still names its builder
+        let proposer = cluster.engines[0].select_proposer();
+        assert_ne!(proposer, builder);
+        let signed = |pol_round: Option<u32>| {
+            let mut proposal = Proposal { round: 1, pol_round, proposer: proposer.clone(), block: a.clone(), signature: vec![] };
+            proposal.sign(DEFAULT_CHAIN_ID, &cluster.keys[index_of(&proposer)]);
+            proposal
+        };
+        let engine = &cluster.engines[index_of(&builder)];
+        assert_eq!(engine.verify_proposal(&signed(None)), Err(ProposalError::WrongProposer { expected: proposer.clone(), found: builder.clone() }));
+        assert_eq!(engine.verify_proposal(&signed(Some(1))), Err(ProposalError::InvalidPolRound { round: 1, pol_round: 1 }));
+
+        let reproposal = signed(Some(0));
+        for engine in &mut cluster.engines {
+            assert_eq!(engine.verify_proposal(&reproposal), Ok(()));
+            engine.set_proposal(reproposal.block.clone());
+        }
+        cluster.run(&ALL);
+        for engine in &cluster.engines {
+            assert_eq!(engine.step, BftStep::Commit);
+            assert_eq!(engine.proposal.as_ref().map(|b| b.header.proposer.clone()), Some(builder.clone()));
+        }
+    }
+
+    #[test]
+    fn test_proposer_rotation_is_stake_weighted() {
+        // Large, uneven stakes: every validator still gets turns in proportion to its stake
+        let stakes = [1_000_000u64, 2_000_000, 3_000_000, 4_000_000];
+        let validators = stakes.iter().enumerate()
+            .map(|(i, stake)| Validator { stake: *stake, ..validator(i as u8 + 1, ValidatorRole::Standard) })
+            .collect();
+        let mut engine = ConsensusEngine::new(ValidatorSet { validators, total_stake: 10_000_000 });
+
+        let mut turns: HashMap<String, u64> = HashMap::new();
+        let mut previous = String::new();
+        let mut longest_run = 0;
+        let mut run = 0;
+        for _ in 0..1_000 {
+            let proposer = engine.select_proposer();
+            run = if proposer == previous { run + 1 } else { 1 };
+            longest_run = longest_run.max(run);
+            *turns.entry(proposer.clone()).or_default() += 1;
+            previous = proposer;
+            engine.validator_set.rotate_proposer();
+        }
+        for (i, stake) in stakes.iter().enumerate() {
+            assert_eq!(turns[&format!("val{}", i + 1)], stake / 10_000);
+        }
+        // Turns are interleaved rather than handed out in long streaks
+        assert!(longest_run <= 2, "longest streak {}", longest_run);
+        assert!(engine.validator_set.validators.iter().all(|v| v.proposer_priority.abs() <= 20_000_000));
+    }
+
+    #[test]
+    fn test_proposer_rotation_is_persisted_and_advances_with_rounds() {
+        let storage = ChainStorage::new("test_data/consensus_rotation_db");
+        let set = ValidatorSet { validators: (1..=3).map(|i| validator(i, ValidatorRole::Standard)).collect(), total_stake: 300 };
+        storage.save_validator_set(&set);
+        let mut engine = ConsensusEngine::new(set);
+
+        // Each round of a height moves on to the next validator
+        let first = engine.select_proposer();
+        engine.round = 1;
+        let second = engine.select_proposer();
+        assert_ne!(first, second);
+        engine.round = 0;
+
+        // Finalizing a block saves the advanced priorities with the validator set
+        let mut block = Block::new_genesis(DEFAULT_CHAIN_ID);
+        block.header.height = 1;
+        engine.apply_finalization(&storage, &block);
+        let stored = storage.get_validator_set().unwrap();
+        assert_ne!(stored.hash(), engine.validator_set.hash());
+        let next = ConsensusEngine::new(stored);
+        assert_eq!(next.select_proposer(), second);
+    }
+
+    #[test]
+    fn test_validators_missing_too_many_blocks_in_the_window_are_jailed() {
+        let _ = std::fs::remove_dir_all("test_data/consensus_liveness_db");
+        let storage = ChainStorage::new("test_data/consensus_liveness_db");
+        storage.save_slashing_params(&SlashingParams { signed_blocks_window: 10, max_missed_blocks: 3, ..SlashingParams::default() });
+        let set = ValidatorSet { validators: (1..=4).map(|i| validator(i, ValidatorRole::Standard)).collect(), total_stake: 400 };
+        storage.save_validator_set(&set);
+        let engine = ConsensusEngine::new(set);
+
+        // val3 misses every fourth commit, val4 never signs
+        let finalize = |height: u64| {
+            let signers = (1..=3u8).filter(|&i| i != 3 || !(height - 1).is_multiple_of(4));
+            let precommits = signers.map(|i| BftMessage { height: height - 1, round: 0, step: BftStep::Precommit, block_hash: Some("parent".into()), validator: format!("val{}", i), signature: vec![] }).collect();
+            let mut block = Block::new_genesis(DEFAULT_CHAIN_ID);
+            block.header.height = height;
+            block.last_commit = Some(CommitCertificate { height: height - 1, round: 0, block_hash: "parent".into(), precommits });
+            engine.apply_finalization(&storage, &block);
+            storage.get_validator_set().unwrap()
+        };
+        let status = |set: &ValidatorSet, i: usize| set.validators[i].status.clone();
+
+        // Missed blocks are only judged once a full window has been observed
+        for height in 2..=10 {
+            let set = finalize(height);
+            assert_eq!(status(&set, 3), ValidatorStatus::Active);
+        }
+        let set = finalize(11);
+        assert_eq!(status(&set, 3), ValidatorStatus::Jailed { until: 11 + 1_000 });
+        assert!(set.validators[3].stake < set.validators[0].stake);
+        assert!(storage.get_liveness("val4").is_none());
+
+        // Old misses slide out of the window, so val3 never exceeds three
+        for height in 12..=30 {
+            let set = finalize(height);
+            assert_eq!(status(&set, 2), ValidatorStatus::Active);
+            assert_eq!(set.validators[0].last_active, height - 1);
+        }
+        assert!(storage.get_liveness("val3").unwrap().missed.len() <= 3);
+
+        // A validator that joined after the parent was signed is not expected in its commit
+        let mut set = storage.get_validator_set().unwrap();
+        storage.save_previous_validator_set(&set);
+        set.validators.push(validator(5, ValidatorRole::Standard));
+        storage.save_validator_set(&set);
+        finalize(31);
+        assert!(storage.get_liveness("val5").is_none());
+        storage.save_previous_validator_set(&storage.get_validator_set().unwrap());
+        finalize(32);
+        assert_eq!(storage.get_liveness("val5").unwrap().missed, vec![31]);
+    }
+
+    #[test]
+    fn test_late_precommits_are_added_to_the_last_commit() {
+        let _ = std::fs::remove_dir_all("test_data/consensus_late_precommit_db");
+        let storage = ChainStorage::new("test_data/consensus_late_precommit_db");
+        let validators = (1..=4).map(|i| validator(i, if i == 1 { ValidatorRole::Authority } else { ValidatorRole::Standard })).collect();
+        let mut engine = ConsensusEngine::new(ValidatorSet { validators, total_stake: 400 });
+        let mut block = Block::new_genesis(DEFAULT_CHAIN_ID);
+        block.header.height = 1;
+        let hash = Some(block.hash());
+
+        for i in 1..=3 {
+            engine.process_message(vote(i, BftStep::Precommit, hash.clone()));
+        }
+        engine.finalize_block(&storage, &block);
+        assert_eq!(engine.last_commit().unwrap().signers(), vec!["val1", "val2", "val3"]);
+
+        // val4's precommit arrives after the commit; nil or forged ones are not added
+        engine.process_message(vote(4, BftStep::Precommit, None));
+        let mut forged = vote(4, BftStep::Precommit, hash.clone());
+        forged.sign(DEFAULT_CHAIN_ID, &key(1));
+        engine.process_message(forged);
+        assert_eq!(engine.last_commit().unwrap().precommits.len(), 3);
+        engine.process_message(vote(4, BftStep::Precommit, hash));
+        assert_eq!(engine.last_commit().unwrap().signers(), vec!["val1", "val2", "val3", "val4"]);
+    }
+
+    #[test]
+    fn test_jailed_validators_are_left_out_of_consensus() {
+        let mut set = ValidatorSet { validators: (1..=4).map(|i| validator(i, ValidatorRole::Standard)).collect(), total_stake: 400 };
+        set.validators[3].status = ValidatorStatus::Jailed { until: 10 };
+        let mut engine = ConsensusEngine::new(set);
+
+        // Never its turn to propose, however many rounds go by
+        for round in 0..8 {
+            engine.round = round;
+            assert_ne!(engine.select_proposer(), "val4");
+        }
+        engine.round = 0;
+
+        // Its votes are refused and the quorum is more than two thirds of the remaining 300
+        assert!(engine.validator_address(&key(4)).is_none());
+        engine.process_message(vote(4, BftStep::Prevote, Some("hash1".into())));
+        engine.process_message(vote(1, BftStep::Prevote, Some("hash1".into())));
+        engine.process_message(vote(2, BftStep::Prevote, Some("hash1".into())));
+        assert!(!engine.check_quasi_finality(0, BftStep::Prevote));
+        engine.process_message(vote(3, BftStep::Prevote, Some("hash1".into())));
         assert!(engine.check_quasi_finality(0, BftStep::Prevote));
     }
+
+    #[test]
+    fn test_restarted_validator_cannot_double_sign() {
+        let dir = std::path::Path::new("test_data/consensus_wal");
+        std::fs::create_dir_all(dir).unwrap();
+        let _ = std::fs::remove_file(dir.join(crate::wal::WAL_FILE));
+        let mut cluster = Cluster::new("test_data/consensus_wal_db");
+        cluster.engines[0].wal = Some(ConsensusWal::open(dir).unwrap());
+
+        // val1 precommits A and locks on it, then crashes
+        let (a, b) = (block(1), block(2));
+        cluster.propose(&a, &ALL);
+        cluster.run(&ALL);
+        assert_eq!(cluster.engines[0].step, BftStep::Commit);
+        let locked = cluster.engines[0].locked_block.as_ref().map(|(r, b)| (*r, b.hash()));
+        let set = cluster.engines[0].validator_set.clone();
+
+        // After the restart it resumes the round with its lock and its own precommit
+        let mut restarted = ConsensusEngine::new(set);
+        restarted.wal = Some(ConsensusWal::open(dir).unwrap());
+        restarted.replay_wal();
+        assert_eq!((restarted.round, restarted.step.clone()), (0, BftStep::Precommit));
+        assert_eq!(restarted.locked_block.as_ref().map(|(r, b)| (*r, b.hash())), locked);
+        assert!(restarted.cast_vote(&cluster.keys[0]).is_none());
+
+        // Even with its state lost, the WAL refuses a conflicting vote
+        let mut amnesiac = ConsensusEngine::new(restarted.validator_set.clone());
+        amnesiac.wal = restarted.wal.take();
+        amnesiac.set_proposal(b);
+        amnesiac.next_step(&cluster.storage, None);
+        assert!(amnesiac.cast_vote(&cluster.keys[0]).is_none());
+        assert!(amnesiac.votes.values().all(|v| v.is_empty()));
+    }
+
+    #[test]
+    fn test_proposer_cannot_sign_conflicting_proposals() {
+        let dir = std::path::Path::new("test_data/consensus_proposal_wal");
+        std::fs::create_dir_all(dir).unwrap();
+        let _ = std::fs::remove_file(dir.join(crate::wal::WAL_FILE));
+        let mut engine = setup_engine();
+        engine.wal = Some(ConsensusWal::open(dir).unwrap());
+        let (a, b) = (block(1), block(2));
+
+        // Proposing the same block again is fine, a different one in the same round is not
+        assert!(engine.record_proposal(&a));
+        assert!(engine.record_proposal(&a));
+        assert!(!engine.record_proposal(&b));
+
+        // Not even after a restart, which resumes the round without counting a vote
+        let mut restarted = ConsensusEngine::new(engine.validator_set.clone());
+        restarted.wal = Some(ConsensusWal::open(dir).unwrap());
+        restarted.replay_wal();
+        assert_eq!((restarted.round, restarted.step.clone()), (0, BftStep::Propose));
+        assert!(restarted.votes.is_empty());
+        assert!(!restarted.record_proposal(&b));
+
+        // A later round takes a new proposal
+        restarted.round = 1;
+        assert!(restarted.record_proposal(&b));
+    }
+
+    #[test]
+    fn test_lagging_validator_skips_to_round_with_one_third() {
+        let mut cluster = Cluster::new("test_data/consensus_round_skip_db");
+        let ahead = |i: usize| {
+            let mut vote = BftMessage { height: 1, round: 3, step: BftStep::Prevote, block_hash: None, validator: format!("val{}", i + 1), signature: vec![] };
+            vote.sign(DEFAULT_CHAIN_ID, &cluster.keys[i]);
+            vote
+        };
+        let (v2, v3) = (ahead(1), ahead(2));
+
+        // One validator in round 3 is only 1/4 of the stake, not enough to follow
+        cluster.engines[0].process_message(v2);
+        assert_eq!(cluster.engines[0].round, 0);
+        cluster.engines[0].process_message(v3);
+        assert_eq!((cluster.engines[0].round, cluster.engines[0].step.clone()), (3, BftStep::Propose));
+        // Votes of the rounds left behind are dropped
+        assert!(cluster.engines[0].votes.keys().all(|(_, round, _)| *round >= 3));
+
+        // Votes beyond the accepted window are not even kept
+        let far = cluster.engines[0].round + MAX_ROUNDS_AHEAD + 1;
+        let mut vote = BftMessage { height: 1, round: far, step: BftStep::Prevote, block_hash: None, validator: "val2".into(), signature: vec![] };
+        vote.sign(DEFAULT_CHAIN_ID, &cluster.keys[1]);
+        cluster.engines[0].process_message(vote);
+        assert!(!cluster.engines[0].votes.contains_key(&(1, far, BftStep::Prevote)));
+        assert_eq!(cluster.engines[0].round, 3);
+    }
 }
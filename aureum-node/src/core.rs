@@ -2,8 +2,29 @@ use serde::{Serialize, Deserialize};
 use sha3::{Digest, Keccak256};
 use parity_scale_codec::{Encode, Decode};
 
+/// Domain tag prefixed to every transaction signing payload
+pub const TX_DOMAIN_TAG: &[u8] = b"AUREUM_TX";
+/// Current transaction envelope version
+pub const TX_VERSION: u8 = 1;
+/// Chain id used when none was configured at genesis
+pub const DEFAULT_CHAIN_ID: u64 = 1;
+/// Current block header version
+pub const BLOCK_VERSION: u8 = 2;
+
+fn default_tx_version() -> u8 {
+    TX_VERSION
+}
+
+fn default_chain_id() -> u64 {
+    DEFAULT_CHAIN_ID
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
 pub struct Transaction {
+    #[serde(default = "default_tx_version")]
+    pub version: u8,
+    #[serde(default = "default_chain_id")]
+    pub chain_id: u64,
     pub sender: String,
     pub receiver: String,
     pub amount: u64,
@@ -32,6 +53,46 @@ pub enum TransactionType {
     EscrowCreate { arbiter: String, conditions: String, property_id: Option<String> },
     EscrowRelease { escrow_id: String },
     EscrowRefund { escrow_id: String },
+    /// Two conflicting votes signed by one validator, submitted to have it slashed and jailed
+    Evidence { first: crate::consensus::BftMessage, second: crate::consensus::BftMessage },
+    /// Return the sending validator to the active set once its jail period is over
+    Unjail,
+    /// Bond AUR from the sender's balance to a validator it does not run
+    Delegate { validator: String, amount: u64 },
+    /// Start unbonding AUR delegated to a validator
+    Undelegate { validator: String, amount: u64 },
+    /// Move delegated AUR from one validator to another without unbonding it
+    Redelegate { from: String, to: String, amount: u64 },
+    /// Set the share of delegators' rewards the sending validator keeps
+    SetCommission { basis_points: u64 },
+}
+
+impl TransactionType {
+    /// Stable variant name, used in receipts, logs and RPC filters
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Transfer => "Transfer",
+            TransactionType::Stake { .. } => "Stake",
+            TransactionType::Unstake { .. } => "Unstake",
+            TransactionType::TokenizeProperty { .. } => "TokenizeProperty",
+            TransactionType::ApplyForVisa { .. } => "ApplyForVisa",
+            TransactionType::ContractCreate { .. } => "ContractCreate",
+            TransactionType::ContractCall { .. } => "ContractCall",
+            TransactionType::RegisterCompliance { .. } => "RegisterCompliance",
+            TransactionType::SubmitOracleReport { .. } => "SubmitOracleReport",
+            TransactionType::TransferFraction { .. } => "TransferFraction",
+            TransactionType::CreateMultiSig { .. } => "CreateMultiSig",
+            TransactionType::EscrowCreate { .. } => "EscrowCreate",
+            TransactionType::EscrowRelease { .. } => "EscrowRelease",
+            TransactionType::EscrowRefund { .. } => "EscrowRefund",
+            TransactionType::Evidence { .. } => "Evidence",
+            TransactionType::Unjail => "Unjail",
+            TransactionType::Delegate { .. } => "Delegate",
+            TransactionType::Undelegate { .. } => "Undelegate",
+            TransactionType::Redelegate { .. } => "Redelegate",
+            TransactionType::SetCommission { .. } => "SetCommission",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq)]
@@ -69,18 +130,39 @@ pub struct VisaApplication {
 }
 
 impl Transaction {
+    /// Canonical payload covered by the signature and the transaction hash.
+    ///
+    /// Layout: domain tag, envelope version, chain id, then the SCALE-encoded body.
+    /// Returns `None` for envelope versions this node does not understand.
+    pub fn signing_bytes(&self) -> Option<Vec<u8>> {
+        match self.version {
+            1 => {
+                let mut msg = TX_DOMAIN_TAG.to_vec();
+                msg.push(self.version);
+                self.chain_id.encode_to(&mut msg);
+                (&self.sender, &self.receiver, self.amount, self.nonce, self.fee, &self.pub_key, &self.tx_type).encode_to(&mut msg);
+                Some(msg)
+            }
+            _ => None,
+        }
+    }
+
     pub fn hash(&self) -> String {
         let mut hasher = Keccak256::new();
-        hasher.update(self.sender.as_bytes());
-        hasher.update(self.receiver.as_bytes());
-        hasher.update(self.amount.to_be_bytes());
-        hasher.update(self.nonce.to_be_bytes());
-        hasher.update(self.fee.to_be_bytes());
-        hasher.update(&self.pub_key);
-        hasher.update(self.tx_type.encode());
+        match self.signing_bytes() {
+            Some(msg) => hasher.update(msg),
+            // Unknown envelopes still need a stable identifier for logs and dedup
+            None => hasher.update(self.encode()),
+        }
         hex::encode(hasher.finalize())
     }
 
+    pub fn sign(&mut self, key: &ed25519_dalek::SigningKey) {
+        use ed25519_dalek::Signer;
+        let msg = self.signing_bytes().expect("unsupported transaction version");
+        self.signature = key.sign(&msg).to_vec();
+    }
+
     pub fn verify_signature(&self) -> bool {
         use ed25519_dalek::{VerifyingKey, Signature, Verifier};
         
@@ -94,14 +176,9 @@ impl Transaction {
             return false;
         };
 
-        let mut msg = Vec::new();
-        msg.extend_from_slice(self.sender.as_bytes());
-        msg.extend_from_slice(self.receiver.as_bytes());
-        msg.extend_from_slice(&self.amount.to_be_bytes());
-        msg.extend_from_slice(&self.nonce.to_be_bytes());
-        msg.extend_from_slice(&self.fee.to_be_bytes());
-        msg.extend_from_slice(&self.pub_key);
-        msg.extend_from_slice(&self.tx_type.encode());
+        let Some(msg) = self.signing_bytes() else {
+            return false;
+        };
 
         let Ok(sig) = Signature::from_slice(&self.signature) else {
             return false;
@@ -123,13 +200,49 @@ pub enum ValidatorRole {
     Authority,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq)]
+pub enum ValidatorStatus {
+    Active,
+    /// Punished for downtime and barred from consensus until it unjails at or after the given height
+    Jailed { until: u64 },
+    /// Proven to have equivocated and permanently barred from consensus
+    Tombstoned,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
 pub struct Validator {
     pub address: String,
     pub public_key: Vec<u8>,
+    /// Bonded AUR, the validator's own plus everything delegated to it
     pub stake: u64,
     pub role: ValidatorRole,
     pub last_active: u64,
+    /// Accumulated proposer priority; grows by stake every height and drops by the total when proposing
+    pub proposer_priority: i64,
+    pub status: ValidatorStatus,
+    /// Share of the rewards on delegated stake the validator keeps, in basis points
+    pub commission_bps: u64,
+}
+
+impl Validator {
+    /// Whether the validator takes part in proposing, voting and rewards
+    pub fn is_active(&self) -> bool {
+        self.status == ValidatorStatus::Active
+    }
+
+    /// The validator's own bond: its stake less what `delegations` bonded to it.
+    /// `None` if the delegations add up to more than the stake.
+    pub fn own_stake(&self, delegations: &[Delegation]) -> Option<u64> {
+        let delegated = delegations.iter().try_fold(0u64, |sum, d| sum.checked_add(d.amount))?;
+        self.stake.checked_sub(delegated)
+    }
+}
+
+/// AUR a delegator has bonded to a validator; it counts towards the validator's `stake`
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq)]
+pub struct Delegation {
+    pub delegator: String,
+    pub amount: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
@@ -139,6 +252,55 @@ pub struct ValidatorSet {
 }
 
 impl ValidatorSet {
+    /// Commitment to the full set, including stakes and roles
+    pub fn hash(&self) -> String {
+        hex::encode(Keccak256::digest(self.encode()))
+    }
+
+    /// The active validator with `address`, if any
+    pub fn active(&self, address: &str) -> Option<&Validator> {
+        self.validators.iter().find(|v| v.address == address && v.is_active())
+    }
+
+    /// Stake of the validators currently taking part in consensus, which quorums are measured against
+    pub fn active_stake(&self) -> u64 {
+        self.validators.iter().filter(|v| v.is_active()).map(|v| v.stake).sum()
+    }
+
+    /// Advance the weighted round-robin by one turn and return whose turn it is.
+    ///
+    /// Every active validator gains its stake in priority and the highest (lowest address on
+    /// ties) proposes, paying back the total stake, so each proposes in proportion to its
+    /// stake. Jailed and tombstoned validators keep their priority but sit the rotation out.
+    pub fn rotate_proposer(&mut self) -> Option<String> {
+        let mut active: Vec<&mut Validator> = self.validators.iter_mut().filter(|v| v.is_active()).collect();
+        let total: i64 = active.iter().map(|v| v.stake as i64).sum();
+        if total == 0 {
+            return active.first().map(|v| v.address.clone());
+        }
+
+        // Keep priorities bounded: squeeze their spread to twice the total stake and center them on zero
+        let max = active.iter().map(|v| v.proposer_priority).max()?;
+        let min = active.iter().map(|v| v.proposer_priority).min()?;
+        let spread = max - min;
+        if spread > 2 * total {
+            let ratio = (spread + 2 * total - 1) / (2 * total);
+            for v in active.iter_mut() {
+                v.proposer_priority /= ratio;
+            }
+        }
+        let sum: i64 = active.iter().map(|v| v.proposer_priority).sum();
+        let average = sum.div_euclid(active.len() as i64);
+        for v in active.iter_mut() {
+            v.proposer_priority += v.stake as i64 - average;
+        }
+
+        let proposer = active.into_iter()
+            .max_by(|a, b| a.proposer_priority.cmp(&b.proposer_priority).then_with(|| b.address.cmp(&a.address)))?;
+        proposer.proposer_priority -= total;
+        Some(proposer.address.clone())
+    }
+
     pub fn get_authority_nodes(&self) -> Vec<Validator> {
         self.validators.iter()
             .filter(|v| v.role == ValidatorRole::Authority)
@@ -151,38 +313,54 @@ impl ValidatorSet {
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>,
+    /// Commit certificate of the parent block, recording which validators signed it
+    pub last_commit: Option<crate::consensus::CommitCertificate>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
 pub struct BlockHeader {
-    pub parent_hash: String,
-    pub timestamp: u64,
+    pub version: u8,
+    pub chain_id: u64,
     pub height: u64,
-    pub state_root: String,
+    pub timestamp: u64,
+    pub parent_hash: String,
     pub tx_merkle_root: String,
+    /// State root after executing the block and its finalization
+    pub state_root: String,
+    pub proposer: String,
+    /// Hash of the validator set the block was produced under
+    pub validator_set_hash: String,
+    /// Hash of `Block::last_commit`, empty when the block carries none
+    pub last_commit_hash: String,
 }
 
 impl Block {
-    pub fn new_genesis() -> Self {
+    pub fn new_genesis(chain_id: u64) -> Self {
         Block {
             header: BlockHeader {
-                parent_hash: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
-                timestamp: 1672531200,
+                version: BLOCK_VERSION,
+                chain_id,
                 height: 0,
-                state_root: "genesis".to_string(),
+                timestamp: 1672531200,
+                parent_hash: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
                 tx_merkle_root: "0".to_string(),
+                state_root: "genesis".to_string(),
+                proposer: "".to_string(),
+                validator_set_hash: "".to_string(),
+                last_commit_hash: "".to_string(),
             },
             transactions: vec![],
+            last_commit: None,
         }
     }
 
+    /// Hash of the whole SCALE-encoded header
     pub fn hash(&self) -> String {
-        let mut hasher = Keccak256::new();
-        hasher.update(self.header.parent_hash.as_bytes());
-        hasher.update(self.header.height.to_be_bytes());
-        hasher.update(self.header.timestamp.to_be_bytes());
-        hasher.update(self.header.state_root.as_bytes());
-        hex::encode(hasher.finalize())
+        hex::encode(Keccak256::digest(self.header.encode()))
+    }
+
+    pub fn calculate_last_commit_hash(&self) -> String {
+        self.last_commit.as_ref().map(|cert| hex::encode(Keccak256::digest(cert.encode()))).unwrap_or_default()
     }
 
     pub fn calculate_merkle_root(&self) -> String {
@@ -197,7 +375,7 @@ impl Block {
         }).collect();
 
         while hashes.len() > 1 {
-            if hashes.len() % 2 != 0 {
+            if !hashes.len().is_multiple_of(2) {
                 hashes.push(hashes.last().unwrap().clone());
             }
             let mut next_level = vec![];
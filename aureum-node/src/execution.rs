@@ -0,0 +1,715 @@
+use serde::{Serialize, Deserialize};
+use parity_scale_codec::{Encode, Decode};
+use crate::core::{
+    Block, Transaction, TransactionType, Property, PropertyStatus, VisaApplication, VisaProgram,
+    ApplicationStatus, Escrow, EscrowStatus, Delegation, Validator, ValidatorRole, ValidatorSet, ValidatorStatus, TX_VERSION,
+};
+use crate::consensus::{self, BftMessage, EvidenceError, Redelegation, UnbondingEntry, EQUIVOCATION_SLASH_PERCENT};
+use crate::storage::ChainStorage;
+use crate::vm::AureumVM;
+use log::{info, warn};
+use revm::primitives::{ExecutionResult, Output};
+use std::fmt;
+use std::sync::Arc;
+
+/// Smallest self-bond a validator may hold, other than withdrawing it entirely
+pub const MIN_VALIDATOR_STAKE: u64 = 1_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecError {
+    // Block-level errors: the block as a whole cannot be applied
+    UnexpectedHeight { expected: u64, found: u64 },
+
+    // Transaction-level errors: recorded in the receipt, the block still applies
+    UnsupportedVersion(u8),
+    WrongChainId { expected: u64, found: u64 },
+    InvalidSignature,
+    NonceTooLow { expected: u64, found: u64 },
+    NonceGap { expected: u64, found: u64 },
+    ComplianceRejected { sender: String },
+    InsufficientBalance { address: String, balance: u64, required: u64 },
+    InsufficientStake { validator: String, stake: u64, required: u64 },
+    StakeBelowMinimum { validator: String, stake: u64, minimum: u64 },
+    StakeOverflow { validator: String },
+    DelegationsExceedStake { validator: String },
+    ZeroAmount,
+    InsufficientDelegation { delegator: String, validator: String, delegated: u64, required: u64 },
+    ValidatorNotFound(String),
+    InvalidCommission(u64),
+    EscrowNotFound(String),
+    EscrowNotPending { escrow_id: String, status: EscrowStatus },
+    Unauthorized { address: String, action: &'static str },
+    InvalidEvidence(EvidenceError),
+    ValidatorNotJailed { validator: String, status: ValidatorStatus },
+    JailPeriodActive { validator: String, until: u64 },
+    Vm(String),
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::UnexpectedHeight { expected, found } => write!(f, "unexpected block height {} (expected {})", found, expected),
+            ExecError::UnsupportedVersion(v) => write!(f, "unsupported transaction version {}", v),
+            ExecError::WrongChainId { expected, found } => write!(f, "wrong chain id: expected {}, got {}", expected, found),
+            ExecError::InvalidSignature => write!(f, "invalid signature"),
+            ExecError::NonceTooLow { expected, found } => write!(f, "nonce too low: expected {}, got {}", expected, found),
+            ExecError::NonceGap { expected, found } => write!(f, "nonce gap: expected {}, got {}", expected, found),
+            ExecError::ComplianceRejected { sender } => write!(f, "compliance check failed for {}", sender),
+            ExecError::InsufficientBalance { address, balance, required } => write!(f, "insufficient balance: {} has {} but needs {}", address, balance, required),
+            ExecError::InsufficientStake { validator, stake, required } => write!(f, "insufficient stake: {} has {} staked but unstakes {}", validator, stake, required),
+            ExecError::StakeBelowMinimum { validator, stake, minimum } => write!(f, "stake below minimum: {} would have {} bonded, at least {} required", validator, stake, minimum),
+            ExecError::StakeOverflow { validator } => write!(f, "stake overflow for {}", validator),
+            ExecError::DelegationsExceedStake { validator } => write!(f, "delegations to {} exceed its stake", validator),
+            ExecError::ZeroAmount => write!(f, "amount must be greater than zero"),
+            ExecError::InsufficientDelegation { delegator, validator, delegated, required } => write!(f, "insufficient delegation: {} has {} delegated to {} but moves {}", delegator, delegated, validator, required),
+            ExecError::ValidatorNotFound(v) => write!(f, "validator {} not found", v),
+            ExecError::InvalidCommission(bps) => write!(f, "commission of {} basis points exceeds 10000", bps),
+            ExecError::EscrowNotFound(id) => write!(f, "escrow {} not found", id),
+            ExecError::EscrowNotPending { escrow_id, status } => write!(f, "escrow {} is {:?}, not Pending", escrow_id, status),
+            ExecError::Unauthorized { address, action } => write!(f, "{} is not authorized to {}", address, action),
+            ExecError::InvalidEvidence(e) => write!(f, "invalid evidence: {}", e),
+            ExecError::ValidatorNotJailed { validator, status } => write!(f, "validator {} is {:?}, not Jailed", validator, status),
+            ExecError::JailPeriodActive { validator, until } => write!(f, "validator {} is jailed until height {}", validator, until),
+            ExecError::Vm(e) => write!(f, "vm error: {}", e),
+            ExecError::Unsupported(kind) => write!(f, "transaction type {} is not supported", kind),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq)]
+pub enum ReceiptStatus {
+    /// Executed successfully
+    Success,
+    /// Fee charged and nonce consumed, but the transaction itself failed
+    Failed,
+    /// Not executed at all (bad envelope, wrong nonce or unpaid fee); no state was touched
+    Rejected,
+}
+
+/// Persisted outcome of a single transaction within an applied block
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
+pub struct Receipt {
+    pub tx_hash: String,
+    pub block_height: u64,
+    pub index: u32,
+    pub status: ReceiptStatus,
+    pub error: Option<String>,
+    pub fee_charged: u64,
+    pub gas_used: u64,
+    pub contract_address: Option<String>,
+    pub escrow_id: Option<String>,
+    pub property_id: Option<String>,
+}
+
+/// Block-level inputs to execution.
+///
+/// Handlers must take time and height from here rather than the wall clock so that every
+/// node replaying a block reaches the same state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionContext {
+    pub height: u64,
+    pub timestamp: u64,
+    pub chain_id: u64,
+}
+
+impl ExecutionContext {
+    pub fn for_block(block: &Block, chain_id: u64) -> Self {
+        Self { height: block.header.height, timestamp: block.header.timestamp, chain_id }
+    }
+}
+
+/// What a successfully executed transaction produced
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TxOutcome {
+    pub gas_used: u64,
+    pub contract_address: Option<String>,
+    pub escrow_id: Option<String>,
+    pub property_id: Option<String>,
+}
+
+impl Receipt {
+    fn rejected(tx: &Transaction, block_height: u64, index: u32, error: &ExecError) -> Self {
+        Self {
+            tx_hash: tx.hash(),
+            block_height,
+            index,
+            status: ReceiptStatus::Rejected,
+            error: Some(error.to_string()),
+            fee_charged: 0,
+            gas_used: 0,
+            contract_address: None,
+            escrow_id: None,
+            property_id: None,
+        }
+    }
+
+    fn executed(tx: &Transaction, block_height: u64, index: u32, result: Result<TxOutcome, ExecError>) -> Self {
+        let (status, error, outcome) = match result {
+            Ok(outcome) => (ReceiptStatus::Success, None, outcome),
+            Err(e) => (ReceiptStatus::Failed, Some(e.to_string()), TxOutcome::default()),
+        };
+        Self {
+            tx_hash: tx.hash(),
+            block_height,
+            index,
+            status,
+            error,
+            fee_charged: tx.fee,
+            gas_used: outcome.gas_used,
+            contract_address: outcome.contract_address,
+            escrow_id: outcome.escrow_id,
+            property_id: outcome.property_id,
+        }
+    }
+}
+
+/// Apply every transaction of `block` on top of the current state and atomically persist
+/// the resulting state together with the block and its receipts.
+///
+/// Individual transaction failures do not abort the block; they are reported in the
+/// returned receipts. An `Err` means the block itself could not be applied and nothing
+/// was written.
+pub fn apply_block(storage: &ChainStorage, vm: &AureumVM, block: &Block) -> Result<Vec<Receipt>, ExecError> {
+    let state = Arc::new(storage.begin_block());
+    let receipts = execute_block(&state, vm, block)?;
+    state.commit_block();
+    Ok(receipts)
+}
+
+/// Execute `block` into a handle from `ChainStorage::begin_block` without committing it,
+/// so the caller can add further block-level changes before `commit_block`.
+pub fn execute_block(state: &Arc<ChainStorage>, vm: &AureumVM, block: &Block) -> Result<Vec<Receipt>, ExecError> {
+    let expected = state.get_latest_height() + 1;
+    if block.header.height != expected {
+        return Err(ExecError::UnexpectedHeight { expected, found: block.header.height });
+    }
+
+    let vm = vm.with_storage(state.clone());
+    let ctx = ExecutionContext::for_block(block, state.get_chain_id());
+    let mut receipts = Vec::with_capacity(block.transactions.len());
+    for (index, tx) in block.transactions.iter().enumerate() {
+        let receipt = apply_transaction(state, &vm, &ctx, tx, index as u32);
+        if let Some(e) = &receipt.error {
+            warn!("⚠️  Tx {} {:?}: {}", receipt.tx_hash, receipt.status, e);
+        }
+        receipts.push(receipt);
+    }
+
+    state.save_block(block);
+    state.index_block(block, &receipts);
+    for receipt in &receipts {
+        // A replayed copy of an executed transaction must not shadow the original receipt
+        let executed = state.get_receipt(&receipt.tx_hash).is_some_and(|r| r.status != ReceiptStatus::Rejected);
+        if !executed {
+            state.save_receipt(receipt);
+        }
+    }
+    state.commit_tx();
+    Ok(receipts)
+}
+
+/// Check the transaction envelope: version, chain id, type and signature
+pub fn check_envelope(chain_id: u64, tx: &Transaction) -> Result<(), ExecError> {
+    if tx.version != TX_VERSION {
+        return Err(ExecError::UnsupportedVersion(tx.version));
+    }
+    // Types without a handler are refused before any fee is charged
+    if matches!(tx.tx_type, TransactionType::RegisterCompliance { .. }
+        | TransactionType::SubmitOracleReport { .. }
+        | TransactionType::TransferFraction { .. }
+        | TransactionType::CreateMultiSig { .. })
+    {
+        return Err(ExecError::Unsupported(tx.tx_type.name()));
+    }
+    if tx.chain_id != chain_id {
+        return Err(ExecError::WrongChainId { expected: chain_id, found: tx.chain_id });
+    }
+    if !tx.verify_signature() {
+        return Err(ExecError::InvalidSignature);
+    }
+    Ok(())
+}
+
+/// Check a transaction nonce against the next nonce expected for its sender
+pub fn check_nonce(expected: u64, tx: &Transaction) -> Result<(), ExecError> {
+    if tx.nonce < expected {
+        return Err(ExecError::NonceTooLow { expected, found: tx.nonce });
+    }
+    if tx.nonce > expected {
+        return Err(ExecError::NonceGap { expected, found: tx.nonce });
+    }
+    Ok(())
+}
+
+/// Apply a single transaction and describe the outcome in a receipt.
+///
+/// The envelope and nonce are checked and the fee charged before the handler runs. Once the
+/// fee is paid the nonce is consumed, so a transaction that fails afterwards can never be replayed.
+/// On an overlay handle, everything else a failed transaction wrote is reverted.
+pub fn apply_transaction(storage: &ChainStorage, vm: &AureumVM, ctx: &ExecutionContext, tx: &Transaction, index: u32) -> Receipt {
+    if let Err(e) = charge_transaction(storage, ctx, tx) {
+        storage.revert_tx();
+        return Receipt::rejected(tx, ctx.height, index, &e);
+    }
+    storage.commit_tx();
+
+    let result = execute_transaction(storage, vm, ctx, tx);
+    if result.is_ok() {
+        storage.commit_tx();
+    } else {
+        storage.revert_tx();
+    }
+    Receipt::executed(tx, ctx.height, index, result)
+}
+
+/// Validate the envelope and nonce, then charge the fee and consume the nonce
+pub fn charge_transaction(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction) -> Result<(), ExecError> {
+    check_envelope(ctx.chain_id, tx)?;
+    check_nonce(storage.get_nonce(&tx.sender), tx)?;
+    debit(storage, &tx.sender, tx.fee)?;
+    storage.increment_nonce(&tx.sender);
+    Ok(())
+}
+
+/// Run the handler for the transaction type against an already charged transaction
+pub fn execute_transaction(storage: &ChainStorage, vm: &AureumVM, ctx: &ExecutionContext, tx: &Transaction) -> Result<TxOutcome, ExecError> {
+    if !vm.verify_compliance(tx, ctx.timestamp) {
+        return Err(ExecError::ComplianceRejected { sender: tx.sender.clone() });
+    }
+
+    match &tx.tx_type {
+        TransactionType::Transfer => apply_transfer(storage, tx),
+        TransactionType::ContractCall { target, data } => apply_contract_call(vm, tx, target, data),
+        TransactionType::ContractCreate { bytecode } => apply_contract_create(vm, tx, bytecode),
+        TransactionType::TokenizeProperty { address, metadata } => apply_tokenize_property(storage, ctx, tx, address, metadata),
+        TransactionType::ApplyForVisa { property_id, program } => apply_visa_application(storage, ctx, tx, property_id, program),
+        TransactionType::EscrowCreate { arbiter, conditions, property_id } => apply_escrow_create(storage, ctx, tx, arbiter, conditions, property_id),
+        TransactionType::EscrowRelease { escrow_id } => apply_escrow_release(storage, tx, escrow_id),
+        TransactionType::EscrowRefund { escrow_id } => apply_escrow_refund(storage, tx, escrow_id),
+        TransactionType::Evidence { first, second } => apply_evidence(storage, ctx, first, second),
+        TransactionType::Unjail => apply_unjail(storage, ctx, tx),
+        TransactionType::Stake { amount } => apply_stake(storage, ctx, tx, *amount),
+        TransactionType::Unstake { amount } => apply_unstake(storage, ctx, tx, *amount),
+        TransactionType::Delegate { validator, amount } => apply_delegate(storage, tx, validator, *amount),
+        TransactionType::Undelegate { validator, amount } => apply_undelegate(storage, ctx, tx, validator, *amount),
+        TransactionType::Redelegate { from, to, amount } => apply_redelegate(storage, ctx, tx, from, to, *amount),
+        TransactionType::SetCommission { basis_points } => apply_set_commission(storage, tx, *basis_points),
+        TransactionType::RegisterCompliance { .. }
+        | TransactionType::SubmitOracleReport { .. }
+        | TransactionType::TransferFraction { .. }
+        // Already refused by `check_envelope`
+        | TransactionType::CreateMultiSig { .. } => Err(ExecError::Unsupported(tx.tx_type.name())),
+    }
+}
+
+fn debit(storage: &ChainStorage, address: &str, amount: u64) -> Result<(), ExecError> {
+    let balance = storage.get_balance(address);
+    if balance < amount {
+        return Err(ExecError::InsufficientBalance { address: address.to_string(), balance, required: amount });
+    }
+    storage.update_balance(address, balance - amount);
+    Ok(())
+}
+
+fn credit(storage: &ChainStorage, address: &str, amount: u64) {
+    let balance = storage.get_balance(address);
+    storage.update_balance(address, balance + amount);
+}
+
+fn set_property_status(storage: &ChainStorage, property_id: &str, status: PropertyStatus) {
+    if let Some(mut prop) = storage.get_property(property_id) {
+        info!("🏠 Property {} status updated to {:?}", property_id, status);
+        prop.status = status;
+        storage.save_property(&prop);
+    }
+}
+
+fn apply_transfer(storage: &ChainStorage, tx: &Transaction) -> Result<TxOutcome, ExecError> {
+    // Simple AUR transfer - direct balance update (no EVM needed)
+    debit(storage, &tx.sender, tx.amount)?;
+    credit(storage, &tx.receiver, tx.amount);
+    info!("✅ Transfer: {} -> {} ({} AUR, fee: {})", tx.sender, tx.receiver, tx.amount, tx.fee);
+    Ok(TxOutcome::default())
+}
+
+fn apply_contract_call(vm: &AureumVM, tx: &Transaction, target: &str, data: &[u8]) -> Result<TxOutcome, ExecError> {
+    let result = vm.execute_transaction(&tx.sender, target, data.to_vec(), tx.amount).map_err(ExecError::Vm)?;
+    info!("✅ Contract Call: {} -> {} ({} AUR)", tx.sender, target, tx.amount);
+    Ok(TxOutcome { gas_used: result.gas_used(), ..Default::default() })
+}
+
+fn apply_contract_create(vm: &AureumVM, tx: &Transaction, bytecode: &[u8]) -> Result<TxOutcome, ExecError> {
+    let result = vm.execute_transaction(&tx.sender, "0", bytecode.to_vec(), tx.amount).map_err(ExecError::Vm)?;
+    let contract_address = match &result {
+        ExecutionResult::Success { output: Output::Create(_, Some(address)), .. } => Some(format!("A{}", hex::encode(address))),
+        _ => None,
+    };
+    info!("✅ Contract Deployed by {} at {:?}", tx.sender, contract_address);
+    Ok(TxOutcome { gas_used: result.gas_used(), contract_address, ..Default::default() })
+}
+
+fn apply_tokenize_property(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction, address: &str, metadata: &str) -> Result<TxOutcome, ExecError> {
+    let prop = Property {
+        id: tx.hash(),
+        owner: tx.sender.clone(),
+        co_owners: vec![],
+        jurisdiction: "Portugal".to_string(), // Default for testnet
+        legal_description: address.to_string(),
+        coordinates: (38.7223, -9.1393), // Lisbon coordinates
+        valuation_eur: tx.amount,
+        valuation_timestamp: ctx.timestamp,
+        valuation_oracle: "AureumCoreOracle".to_string(),
+        title_deed_hash: hex::encode(metadata.as_bytes()),
+        survey_hash: "".to_string(),
+        visa_program_eligible: tx.amount >= 500_000,
+        minimum_investment_met: true,
+        kyc_status: 1,
+        aml_cleared: true,
+        mortgages: vec![],
+        liens: vec![],
+        status: PropertyStatus::Available,
+    };
+    storage.save_property(&prop);
+    info!("🏠 Property Tokenized: {} (Valuation: {} AUR)", prop.id, prop.valuation_eur);
+    Ok(TxOutcome { property_id: Some(prop.id), ..Default::default() })
+}
+
+fn apply_visa_application(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction, property_id: &str, program: &VisaProgram) -> Result<TxOutcome, ExecError> {
+    let app = VisaApplication {
+        applicant: tx.sender.clone(),
+        property_id: property_id.to_string(),
+        investment_amount: tx.amount,
+        program: program.clone(),
+        status: ApplicationStatus::Pending,
+        timestamp: ctx.timestamp,
+    };
+    storage.save_visa_application(&app);
+    info!("🛂 Visa Application Submitted: {} for {:?}", app.applicant, app.program);
+    Ok(TxOutcome::default())
+}
+
+fn apply_escrow_create(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction, arbiter: &str, conditions: &str, property_id: &Option<String>) -> Result<TxOutcome, ExecError> {
+    // 1. Lock funds
+    debit(storage, &tx.sender, tx.amount)?;
+
+    // 2. Create Escrow Record
+    let escrow = Escrow {
+        id: tx.hash(),
+        sender: tx.sender.clone(),
+        receiver: tx.receiver.clone(),
+        arbiter: arbiter.to_string(),
+        amount: tx.amount,
+        conditions: conditions.to_string(),
+        property_id: property_id.clone(),
+        status: EscrowStatus::Pending,
+        created_at: ctx.timestamp,
+    };
+    storage.save_escrow(&escrow);
+
+    // 3. Update Property Status if linked
+    if let Some(prop_id) = property_id {
+        set_property_status(storage, prop_id, PropertyStatus::InEscrow);
+    }
+
+    info!("🔒 Escrow Created: {} ({} AUR locked)", escrow.id, escrow.amount);
+    Ok(TxOutcome { escrow_id: Some(escrow.id), property_id: property_id.clone(), ..Default::default() })
+}
+
+fn pending_escrow(storage: &ChainStorage, escrow_id: &str) -> Result<Escrow, ExecError> {
+    let escrow = storage.get_escrow(escrow_id).ok_or_else(|| ExecError::EscrowNotFound(escrow_id.to_string()))?;
+    if escrow.status != EscrowStatus::Pending {
+        return Err(ExecError::EscrowNotPending { escrow_id: escrow_id.to_string(), status: escrow.status });
+    }
+    Ok(escrow)
+}
+
+fn apply_escrow_release(storage: &ChainStorage, tx: &Transaction, escrow_id: &str) -> Result<TxOutcome, ExecError> {
+    let mut escrow = pending_escrow(storage, escrow_id)?;
+
+    // Only Arbiter or Sender can release
+    if tx.sender != escrow.arbiter && tx.sender != escrow.sender {
+        return Err(ExecError::Unauthorized { address: tx.sender.clone(), action: "release escrow" });
+    }
+
+    escrow.status = EscrowStatus::Released;
+    storage.save_escrow(&escrow);
+    credit(storage, &escrow.receiver, escrow.amount);
+
+    if let Some(ref prop_id) = escrow.property_id {
+        set_property_status(storage, prop_id, PropertyStatus::Sold);
+    }
+
+    info!("🔓 Escrow Released: {} -> {} ({} AUR)", escrow_id, escrow.receiver, escrow.amount);
+    Ok(TxOutcome { escrow_id: Some(escrow.id), property_id: escrow.property_id, ..Default::default() })
+}
+
+fn apply_escrow_refund(storage: &ChainStorage, tx: &Transaction, escrow_id: &str) -> Result<TxOutcome, ExecError> {
+    let mut escrow = pending_escrow(storage, escrow_id)?;
+
+    // Only the Arbiter can refund
+    if tx.sender != escrow.arbiter {
+        return Err(ExecError::Unauthorized { address: tx.sender.clone(), action: "refund escrow" });
+    }
+
+    escrow.status = EscrowStatus::Refunded;
+    storage.save_escrow(&escrow);
+    credit(storage, &escrow.sender, escrow.amount);
+
+    // Linked property goes back on the market
+    if let Some(ref prop_id) = escrow.property_id {
+        set_property_status(storage, prop_id, PropertyStatus::Available);
+    }
+
+    info!("↩️ Escrow Refunded: {} -> {} ({} AUR)", escrow_id, escrow.sender, escrow.amount);
+    Ok(TxOutcome { escrow_id: Some(escrow.id), property_id: escrow.property_id, ..Default::default() })
+}
+
+fn apply_stake(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction, amount: u64) -> Result<TxOutcome, ExecError> {
+    if amount == 0 {
+        return Err(ExecError::ZeroAmount);
+    }
+    let mut validator_set = storage.get_validator_set().unwrap_or(ValidatorSet { validators: vec![], total_stake: 0 });
+    let joins_at = -(validator_set.active_stake() as i64);
+    let index = validator_set.validators.iter().position(|v| v.address == tx.sender);
+    let own = match index {
+        Some(i) if validator_set.validators[i].status == ValidatorStatus::Tombstoned => {
+            return Err(ExecError::Unauthorized { address: tx.sender.clone(), action: "stake as a tombstoned validator" });
+        }
+        Some(i) => own_bond(storage, &validator_set.validators[i])?,
+        None => 0,
+    };
+    let overflow = || ExecError::StakeOverflow { validator: tx.sender.clone() };
+    let bonded = own.checked_add(amount).ok_or_else(overflow)?;
+    if bonded < MIN_VALIDATOR_STAKE {
+        return Err(ExecError::StakeBelowMinimum { validator: tx.sender.clone(), stake: bonded, minimum: MIN_VALIDATOR_STAKE });
+    }
+    let total_stake = validator_set.total_stake.checked_add(amount).ok_or_else(overflow)?;
+
+    // Nothing is bonded unless the AUR actually left the sender's balance
+    debit(storage, &tx.sender, amount)?;
+    match index {
+        Some(i) => validator_set.validators[i].stake += amount,
+        // New validators sign votes with the key they staked from and join at the back of the proposer rotation
+        None => validator_set.validators.push(Validator {
+            address: tx.sender.clone(),
+            public_key: tx.pub_key.clone(),
+            stake: amount,
+            role: ValidatorRole::Standard,
+            last_active: ctx.height,
+            proposer_priority: joins_at,
+            status: ValidatorStatus::Active,
+            commission_bps: 0,
+        }),
+    }
+    validator_set.total_stake = total_stake;
+    storage.save_validator_set(&validator_set);
+    info!("🔒 Stake: {} bonded {} AUR", tx.sender, amount);
+    Ok(TxOutcome::default())
+}
+
+fn apply_unstake(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction, amount: u64) -> Result<TxOutcome, ExecError> {
+    let mut validator_set = storage.get_validator_set()
+        .ok_or_else(|| ExecError::Unauthorized { address: tx.sender.clone(), action: "unstake" })?;
+    let index = validator_set.validators.iter().position(|v| v.address == tx.sender)
+        .ok_or_else(|| ExecError::Unauthorized { address: tx.sender.clone(), action: "unstake" })?;
+    // Only the validator's own bond; delegators take theirs out with `Undelegate`
+    let validator = &mut validator_set.validators[index];
+    let own = own_bond(storage, validator)?;
+    if own < amount {
+        return Err(ExecError::InsufficientStake { validator: tx.sender.clone(), stake: own, required: amount });
+    }
+    // Either leave with the whole bond or keep at least the minimum behind
+    if own > amount && own - amount < MIN_VALIDATOR_STAKE {
+        return Err(ExecError::StakeBelowMinimum { validator: tx.sender.clone(), stake: own - amount, minimum: MIN_VALIDATOR_STAKE });
+    }
+    validator.stake -= amount;
+    // An active validator withdrawing everything leaves the set; a jailed or tombstoned one stays on record
+    if validator.stake == 0 && validator.is_active() {
+        validator_set.validators.remove(index);
+        storage.delete_liveness(&tx.sender);
+    }
+    if validator_set.active_stake() == 0 {
+        return Err(ExecError::Unauthorized { address: tx.sender.clone(), action: "withdraw the last active stake" });
+    }
+    validator_set.total_stake -= amount;
+    storage.save_validator_set(&validator_set);
+
+    let release_height = queue_unbonding(storage, ctx, &tx.sender, &tx.sender, amount);
+    info!("🔓 Unstake: {} unbonding {} AUR until height {}", tx.sender, amount, release_height);
+    Ok(TxOutcome::default())
+}
+
+/// What `validator` bonded itself, as opposed to its delegators
+fn own_bond(storage: &ChainStorage, validator: &Validator) -> Result<u64, ExecError> {
+    validator.own_stake(&storage.get_delegations(&validator.address))
+        .ok_or_else(|| ExecError::DelegationsExceedStake { validator: validator.address.clone() })
+}
+
+/// Start unbonding `amount` for `owner`, slashable for `validator`'s misbehaviour until it is
+/// released. Returns the release height.
+fn queue_unbonding(storage: &ChainStorage, ctx: &ExecutionContext, validator: &str, owner: &str, amount: u64) -> u64 {
+    let release_height = ctx.height + storage.get_slashing_params().unbonding_blocks;
+    let mut queue = storage.get_unbonding_queue();
+    queue.push(UnbondingEntry { validator: validator.to_string(), owner: owner.to_string(), amount, release_height });
+    storage.save_unbonding_queue(&queue);
+    release_height
+}
+
+/// Add `amount` to `validator`'s stake and to `delegator`'s delegation record with it
+fn bond(storage: &ChainStorage, validator_set: &mut ValidatorSet, delegator: &str, validator: &str, amount: u64) -> Result<(), ExecError> {
+    if amount == 0 {
+        return Err(ExecError::ZeroAmount);
+    }
+    if delegator == validator {
+        return Err(ExecError::Unauthorized { address: delegator.to_string(), action: "delegate to itself" });
+    }
+    let target = validator_set.validators.iter_mut().find(|v| v.address == validator)
+        .ok_or_else(|| ExecError::ValidatorNotFound(validator.to_string()))?;
+    if target.status == ValidatorStatus::Tombstoned {
+        return Err(ExecError::Unauthorized { address: delegator.to_string(), action: "delegate to a tombstoned validator" });
+    }
+    let overflow = || ExecError::StakeOverflow { validator: validator.to_string() };
+    let stake = target.stake.checked_add(amount).ok_or_else(overflow)?;
+    let mut delegations = storage.get_delegations(validator);
+    let index = delegations.iter().position(|d| d.delegator == delegator);
+    let delegated = match index {
+        Some(i) => delegations[i].amount.checked_add(amount).ok_or_else(overflow)?,
+        None => amount,
+    };
+
+    target.stake = stake;
+    match index {
+        Some(i) => delegations[i].amount = delegated,
+        None => delegations.push(Delegation { delegator: delegator.to_string(), amount }),
+    }
+    storage.save_delegations(validator, &delegations);
+    Ok(())
+}
+
+/// Take `amount` off `validator`'s stake and off `delegator`'s delegation record with it
+fn unbond(storage: &ChainStorage, validator_set: &mut ValidatorSet, delegator: &str, validator: &str, amount: u64) -> Result<(), ExecError> {
+    let source = validator_set.validators.iter_mut().find(|v| v.address == validator)
+        .ok_or_else(|| ExecError::ValidatorNotFound(validator.to_string()))?;
+    let mut delegations = storage.get_delegations(validator);
+    let index = delegations.iter().position(|d| d.delegator == delegator);
+    let delegated = index.map_or(0, |i| delegations[i].amount);
+    if delegated < amount {
+        return Err(ExecError::InsufficientDelegation { delegator: delegator.to_string(), validator: validator.to_string(), delegated, required: amount });
+    }
+    source.stake -= amount;
+
+    if let Some(i) = index {
+        delegations[i].amount -= amount;
+        if delegations[i].amount == 0 {
+            delegations.remove(i);
+        }
+        storage.save_delegations(validator, &delegations);
+    }
+    Ok(())
+}
+
+fn apply_delegate(storage: &ChainStorage, tx: &Transaction, validator: &str, amount: u64) -> Result<TxOutcome, ExecError> {
+    let mut validator_set = storage.get_validator_set()
+        .ok_or_else(|| ExecError::ValidatorNotFound(validator.to_string()))?;
+    bond(storage, &mut validator_set, &tx.sender, validator, amount)?;
+    validator_set.total_stake = validator_set.total_stake.checked_add(amount)
+        .ok_or_else(|| ExecError::StakeOverflow { validator: validator.to_string() })?;
+    // Nothing is bonded unless the AUR actually left the sender's balance
+    debit(storage, &tx.sender, amount)?;
+    storage.save_validator_set(&validator_set);
+    info!("🤝 Delegate: {} bonded {} AUR to {}", tx.sender, amount, validator);
+    Ok(TxOutcome::default())
+}
+
+fn apply_undelegate(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction, validator: &str, amount: u64) -> Result<TxOutcome, ExecError> {
+    let mut validator_set = storage.get_validator_set()
+        .ok_or_else(|| ExecError::ValidatorNotFound(validator.to_string()))?;
+    unbond(storage, &mut validator_set, &tx.sender, validator, amount)?;
+    if validator_set.active_stake() == 0 {
+        return Err(ExecError::Unauthorized { address: tx.sender.clone(), action: "withdraw the last active stake" });
+    }
+    validator_set.total_stake -= amount;
+    storage.save_validator_set(&validator_set);
+
+    let release_height = queue_unbonding(storage, ctx, validator, &tx.sender, amount);
+    info!("🔓 Undelegate: {} unbonding {} AUR from {} until height {}", tx.sender, amount, validator, release_height);
+    Ok(TxOutcome::default())
+}
+
+fn apply_redelegate(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction, from: &str, to: &str, amount: u64) -> Result<TxOutcome, ExecError> {
+    if from == to {
+        return Err(ExecError::Unauthorized { address: tx.sender.clone(), action: "redelegate to the same validator" });
+    }
+    let mut validator_set = storage.get_validator_set()
+        .ok_or_else(|| ExecError::ValidatorNotFound(from.to_string()))?;
+    unbond(storage, &mut validator_set, &tx.sender, from, amount)?;
+    bond(storage, &mut validator_set, &tx.sender, to, amount)?;
+    if validator_set.active_stake() == 0 {
+        return Err(ExecError::Unauthorized { address: tx.sender.clone(), action: "withdraw the last active stake" });
+    }
+    storage.save_validator_set(&validator_set);
+
+    // The moved stake still answers for `from` for as long as an unbonding would have
+    let completion_height = ctx.height + storage.get_slashing_params().unbonding_blocks;
+    let mut redelegations = storage.get_redelegations();
+    redelegations.push(Redelegation { delegator: tx.sender.clone(), from: from.to_string(), to: to.to_string(), amount, completion_height });
+    storage.save_redelegations(&redelegations);
+    info!("🔁 Redelegate: {} moved {} AUR from {} to {}, slashable for {} until height {}", tx.sender, amount, from, to, from, completion_height);
+    Ok(TxOutcome::default())
+}
+
+fn apply_set_commission(storage: &ChainStorage, tx: &Transaction, basis_points: u64) -> Result<TxOutcome, ExecError> {
+    if basis_points > 10_000 {
+        return Err(ExecError::InvalidCommission(basis_points));
+    }
+    let mut validator_set = storage.get_validator_set()
+        .ok_or_else(|| ExecError::Unauthorized { address: tx.sender.clone(), action: "set a commission" })?;
+    let validator = validator_set.validators.iter_mut().find(|v| v.address == tx.sender)
+        .ok_or_else(|| ExecError::Unauthorized { address: tx.sender.clone(), action: "set a commission" })?;
+    validator.commission_bps = basis_points;
+    storage.save_validator_set(&validator_set);
+    info!("💼 Commission: {} now keeps {} bps of delegator rewards", tx.sender, basis_points);
+    Ok(TxOutcome::default())
+}
+
+fn apply_evidence(storage: &ChainStorage, ctx: &ExecutionContext, first: &BftMessage, second: &BftMessage) -> Result<TxOutcome, ExecError> {
+    let mut validator_set = storage.get_validator_set().unwrap_or(ValidatorSet { validators: vec![], total_stake: 0 });
+    consensus::verify_equivocation(ctx.chain_id, &validator_set, first, second).map_err(ExecError::InvalidEvidence)?;
+    if storage.has_equivocation(&first.validator, first.height) {
+        return Err(ExecError::InvalidEvidence(EvidenceError::AlreadyPunished { validator: first.validator.clone(), height: first.height }));
+    }
+
+    if validator_set.validators.iter().any(|v| v.address == first.validator && v.status == ValidatorStatus::Tombstoned) {
+        return Err(ExecError::InvalidEvidence(EvidenceError::Tombstoned(first.validator.clone())));
+    }
+
+    storage.record_equivocation(&first.validator, first.height);
+    consensus::slash_validator(storage, &mut validator_set, &first.validator, EQUIVOCATION_SLASH_PERCENT, ValidatorStatus::Tombstoned);
+    info!("⚖️ Evidence: {} equivocated at height {} round {}", first.validator, first.height, first.round);
+    Ok(TxOutcome::default())
+}
+
+fn apply_unjail(storage: &ChainStorage, ctx: &ExecutionContext, tx: &Transaction) -> Result<TxOutcome, ExecError> {
+    let mut validator_set = storage.get_validator_set()
+        .ok_or_else(|| ExecError::Unauthorized { address: tx.sender.clone(), action: "unjail" })?;
+    let validator = validator_set.validators.iter_mut().find(|v| v.address == tx.sender)
+        .ok_or_else(|| ExecError::Unauthorized { address: tx.sender.clone(), action: "unjail" })?;
+    match validator.status {
+        ValidatorStatus::Jailed { until } if ctx.height < until => {
+            return Err(ExecError::JailPeriodActive { validator: tx.sender.clone(), until });
+        }
+        ValidatorStatus::Jailed { .. } => {}
+        ref status => return Err(ExecError::ValidatorNotJailed { validator: tx.sender.clone(), status: status.clone() }),
+    }
+
+    // Rejoin the rotation at the average priority, with downtime judged over a fresh window
+    validator.status = ValidatorStatus::Active;
+    validator.proposer_priority = 0;
+    storage.delete_liveness(&tx.sender);
+    storage.save_validator_set(&validator_set);
+    info!("🔓 Unjail: {} rejoins the validator set at height {}", tx.sender, ctx.height);
+    Ok(TxOutcome::default())
+}
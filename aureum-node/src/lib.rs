@@ -5,3 +5,10 @@ pub mod network;
 pub mod vm;
 pub mod oracle;
 pub mod compliance;
+pub mod execution;
+pub mod mempool;
+pub mod state_tree;
+pub mod validation;
+pub mod sync;
+pub mod snapshot;
+pub mod wal;
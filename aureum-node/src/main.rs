@@ -1,10 +1,16 @@
-use aureum_node::core::{Transaction, Block, Validator, ValidatorRole, ValidatorSet, ChainState, BlockHeader, TransactionType, Property, VisaApplication, ApplicationStatus, VisaProgram, Escrow, EscrowStatus};
-use aureum_node::storage::ChainStorage;
-use aureum_node::consensus::{ConsensusEngine, BftStep};
+use aureum_node::core::{Transaction, TransactionType, TX_VERSION, Block, Validator, ValidatorRole, ValidatorSet, ValidatorStatus, ChainState, BlockHeader, DEFAULT_CHAIN_ID, BLOCK_VERSION};
+use aureum_node::storage::{ChainStorage, TxQuery};
+use aureum_node::consensus::{BftMessage, ConsensusEngine, BftStep, Proposal, SlashingParams, TimeoutConfig};
 use aureum_node::vm::AureumVM;
 use aureum_node::compliance::{ComplianceEngine};
+use aureum_node::execution::{self, ReceiptStatus};
+use aureum_node::mempool::{Mempool, MempoolConfig};
 use aureum_node::oracle::{AureumOracle};
-use aureum_node::network::{P2PNetwork, TOPIC_TRANSACTIONS, TOPIC_BLOCKS, TOPIC_CONSENSUS};
+use aureum_node::network::{P2PNetwork, AureumBehaviourEvent, MessageAcceptance, SnapshotRequest, SnapshotResponse, SyncRequest, SyncResponse, SyncedBlock, MAX_SYNC_BATCH, TOPIC_TRANSACTIONS, TOPIC_BLOCKS, TOPIC_CONSENSUS};
+use aureum_node::sync::{self, BlockSync};
+use aureum_node::snapshot::{self, SnapshotSync};
+use aureum_node::validation;
+use aureum_node::wal::ConsensusWal;
 use clap::{Parser, Subcommand};
 use log::{info, warn, error};
 use std::sync::Arc;
@@ -13,7 +19,8 @@ use jsonrpc_http_server::jsonrpc_core::{IoHandler, Value, Params};
 use jsonrpc_http_server::ServerBuilder;
 use parity_scale_codec::{Encode, Decode};
 use futures::StreamExt;
-use libp2p::gossipsub;
+use libp2p::{gossipsub, mdns, request_response};
+use libp2p::swarm::SwarmEvent;
 
 #[derive(Parser)]
 #[command(name = "aureum-node")]
@@ -30,12 +37,69 @@ enum Commands {
         rpc_port: u16,
         #[arg(short, long, default_value = "./data")]
         data_dir: String,
+        /// Sign proposals and votes with `<data_dir>/validator.key`, creating a fresh key if there is none
         #[arg(short, long)]
         validator: bool,
+        /// On an empty chain, restore a state snapshot from a peer instead of replaying every block
+        #[arg(long)]
+        fast_sync: bool,
+        /// Base propose timeout in milliseconds; every round doubles it
+        #[arg(long)]
+        timeout_propose_ms: Option<u64>,
+        /// Base prevote timeout in milliseconds; every round doubles it
+        #[arg(long)]
+        timeout_prevote_ms: Option<u64>,
+        /// Base precommit timeout in milliseconds; every round doubles it
+        #[arg(long)]
+        timeout_precommit_ms: Option<u64>,
     },
     Init {
         #[arg(short, long, default_value = "./data")]
         data_dir: String,
+        /// Chain id committed into every transaction signature
+        #[arg(long, default_value_t = DEFAULT_CHAIN_ID)]
+        chain_id: u64,
+        /// Number of recent blocks a validator's signatures are judged over
+        #[arg(long)]
+        downtime_window: Option<u64>,
+        /// Missed blocks within the window beyond which a validator is slashed and jailed
+        #[arg(long)]
+        max_missed_blocks: Option<u64>,
+        /// Hex ed25519 seed file of the genesis validator; a random key is generated without it
+        #[arg(long)]
+        validator_key: Option<String>,
+        /// Single-node development chain: the genesis validator is the well-known all-zero testnet key
+        #[arg(long, conflicts_with = "validator_key")]
+        dev: bool,
+    },
+    /// Export or import state snapshots
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommands {
+    /// Write the state after block `height` (the latest block by default) to a snapshot directory
+    Export {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: String,
+        #[arg(long)]
+        height: Option<u64>,
+        /// Defaults to `<data_dir>/snapshots/<height>`, where running nodes serve snapshots from
+        #[arg(long)]
+        out: Option<String>,
+    },
+    /// Restore a snapshot directory into an empty data dir
+    Import {
+        #[arg(short, long, default_value = "./data")]
+        data_dir: String,
+        #[arg(long)]
+        from: String,
+        /// Only accept the snapshot if its block has this hash
+        #[arg(long)]
+        block_hash: Option<String>,
     },
 }
 
@@ -45,48 +109,158 @@ async fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Init { data_dir } => {
-            init_node(&data_dir);
+        Commands::Init { data_dir, chain_id, downtime_window, max_missed_blocks, validator_key, dev } => {
+            let defaults = SlashingParams::default();
+            let slashing = SlashingParams {
+                signed_blocks_window: downtime_window.unwrap_or(defaults.signed_blocks_window),
+                max_missed_blocks: max_missed_blocks.unwrap_or(defaults.max_missed_blocks),
+                ..defaults
+            };
+            // Re-initializing keeps the key this data dir already has
+            let existing = Some(validator_key_path(&data_dir)).filter(|path| std::path::Path::new(path).exists());
+            let key = if dev {
+                warn!("⚠️ Using the well-known testnet validator key; never run a public network with it");
+                ed25519_dalek::SigningKey::from_bytes(&[0u8; 32])
+            } else if let Some(path) = validator_key.or(existing) {
+                read_validator_key(&path).unwrap_or_else(|e| {
+                    error!("❌ {}", e);
+                    std::process::exit(1);
+                })
+            } else {
+                ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng)
+            };
+            init_node(&data_dir, chain_id, slashing, &key);
+        }
+        Commands::Run { rpc_port, data_dir, validator, fast_sync, timeout_propose_ms, timeout_prevote_ms, timeout_precommit_ms } => {
+            let defaults = TimeoutConfig::default();
+            let timeouts = TimeoutConfig {
+                propose_ms: timeout_propose_ms.unwrap_or(defaults.propose_ms),
+                prevote_ms: timeout_prevote_ms.unwrap_or(defaults.prevote_ms),
+                precommit_ms: timeout_precommit_ms.unwrap_or(defaults.precommit_ms),
+                max_ms: defaults.max_ms,
+            };
+            run_node(&data_dir, rpc_port, validator, fast_sync, timeouts).await;
+        }
+        Commands::Snapshot { command } => {
+            if let Err(e) = run_snapshot(command) {
+                error!("❌ Snapshot failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn run_snapshot(command: SnapshotCommands) -> Result<(), snapshot::SnapshotError> {
+    match command {
+        SnapshotCommands::Export { data_dir, height, out } => {
+            let storage = ChainStorage::new(&format!("{}/blockchain", data_dir));
+            let height = height.unwrap_or_else(|| storage.get_latest_height());
+            let out = out.unwrap_or_else(|| format!("{}/snapshots/{}", data_dir, height));
+            let manifest = snapshot::export(&storage, height, std::path::Path::new(&out))?;
+            info!("State root {} at block {}", manifest.state_root, manifest.block.hash());
         }
-        Commands::Run { rpc_port, data_dir, validator: _ } => {
-            run_node(&data_dir, rpc_port).await;
+        SnapshotCommands::Import { data_dir, from, block_hash } => {
+            let storage = ChainStorage::new(&format!("{}/blockchain", data_dir));
+            let authority_veto = ConsensusEngine::new(storage.get_validator_set().unwrap_or(ValidatorSet { validators: vec![], total_stake: 0 })).authority_veto_active;
+            snapshot::import(&storage, std::path::Path::new(&from), block_hash.as_deref(), authority_veto)?;
         }
     }
+    Ok(())
+}
+
+/// Path of the ed25519 key a node signs consensus votes with
+fn validator_key_path(data_dir: &str) -> String {
+    format!("{}/validator.key", data_dir)
+}
+
+/// Read a hex-encoded ed25519 seed
+fn read_validator_key(path: &str) -> Result<ed25519_dalek::SigningKey, String> {
+    let encoded = std::fs::read_to_string(path).map_err(|e| format!("Cannot read validator key {}: {}", path, e))?;
+    let mut seed = [0u8; 32];
+    hex::decode_to_slice(encoded.trim(), &mut seed).map_err(|e| format!("Invalid validator key {}: {}", path, e))?;
+    Ok(ed25519_dalek::SigningKey::from_bytes(&seed))
+}
+
+/// Load this node's validator key, generating one on first start
+fn load_validator_key(data_dir: &str) -> Result<ed25519_dalek::SigningKey, String> {
+    let path = validator_key_path(data_dir);
+    if std::path::Path::new(&path).exists() {
+        return read_validator_key(&path);
+    }
+    let key = ed25519_dalek::SigningKey::generate(&mut rand::rngs::OsRng);
+    std::fs::write(&path, hex::encode(key.to_bytes())).map_err(|e| format!("Cannot write validator key {}: {}", path, e))?;
+    info!("🔑 Generated a new validator key at {}", path);
+    Ok(key)
 }
 
-fn init_node(data_dir: &str) {
+/// Transaction reporting that `first` and `second` were signed by the same validator for the same step
+fn evidence_transaction(key: &ed25519_dalek::SigningKey, chain_id: u64, nonce: u64, first: BftMessage, second: BftMessage) -> Transaction {
+    let pub_key = key.verifying_key().to_bytes().to_vec();
+    let mut tx = Transaction {
+        version: TX_VERSION,
+        chain_id,
+        sender: aureum_node::core::generate_address(&pub_key),
+        receiver: first.validator.clone(),
+        amount: 0,
+        nonce,
+        fee: 0,
+        signature: vec![],
+        pub_key,
+        tx_type: TransactionType::Evidence { first, second },
+        hash: None,
+    };
+    tx.sign(key);
+    tx
+}
+
+fn init_node(data_dir: &str, chain_id: u64, slashing: SlashingParams, key: &ed25519_dalek::SigningKey) {
     info!("Initializing Aureum node at {}...", data_dir);
     let storage = ChainStorage::new(&format!("{}/blockchain", data_dir));
     
-    let initial_validator_address = "A1109cd8305ff4145b0b89495431540d1f4faecdc".to_string();
+    let pub_key = key.verifying_key().to_bytes();
+    let initial_validator_address = aureum_node::core::generate_address(&pub_key);
     
     // Always create genesis if it doesn't exist
     if storage.get_block(0).is_none() {
-        let genesis = Block::new_genesis();
+        let genesis = Block::new_genesis(chain_id);
         storage.save_block(&genesis);
-        info!("Genesis block created.");
+        storage.save_chain_id(chain_id);
+        storage.save_slashing_params(&slashing);
+        info!("Genesis block created (chain id {}).", chain_id);
     }
     
     // Always set up validator if balance is 0 (fresh init or reset)
     if storage.get_balance(&initial_validator_address) == 0 {
-        let mut pub_key = [0u8; 32];
-        hex::decode_to_slice("3b6a27bcceb6a42d62a3a8d02a6f0d73653215771de243a63ac048a18b59da29", &mut pub_key).expect("Invalid hex");
-        
         let initial_validator = Validator {
             address: initial_validator_address.clone(),
             public_key: pub_key.to_vec(),
             stake: 1_000_000,
             role: ValidatorRole::Authority,
             last_active: 0,
+            proposer_priority: 0,
+            status: ValidatorStatus::Active,
+            commission_bps: 0,
         };
         let set = ValidatorSet {
             validators: vec![initial_validator.clone()],
             total_stake: 1_000_000,
         };
         storage.save_validator_set(&set);
+
+        // The genesis validator signs with this data dir's key; an existing different key is never replaced
+        let key_path = validator_key_path(data_dir);
+        match read_validator_key(&key_path) {
+            Ok(existing) if existing != *key => warn!("⚠️ {} holds a different key; the genesis validator is {}", key_path, initial_validator_address),
+            Ok(_) => {}
+            Err(_) => {
+                std::fs::write(&key_path, hex::encode(key.to_bytes())).expect("Failed to write validator key");
+                info!("🔑 Genesis validator {} key written to {}", initial_validator_address, key_path);
+            }
+        }
         info!("Updating validator balance to 1B AUR...");
         storage.update_balance(&initial_validator.address, 1_000_000_000); // 1B AUR
         storage.save_chain_state(&ChainState { total_supply: 21_000_000_000, burned_fees: 0 });
+        storage.record_state_root(0);
         storage.flush(); // Ensure all data is written to disk
         info!("Verification: Validator balance is now {}", storage.get_balance(&initial_validator.address));
         info!("Initial validator funded with 1B AUR.");
@@ -97,18 +271,50 @@ fn init_node(data_dir: &str) {
     info!("� Node initialization complete.");
 }
 
-async fn run_node(data_dir: &str, rpc_port: u16) {
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+async fn run_node(data_dir: &str, rpc_port: u16, validator: bool, fast_sync: bool, timeouts: TimeoutConfig) {
     info!("🚀 Aureum Node starting...");
     let start_time = std::time::Instant::now();
     let storage = Arc::new(ChainStorage::new(&format!("{}/blockchain", data_dir)));
-    let (_tx_sender, mut _tx_receiver) = mpsc::channel::<Vec<u8>>(1000);
+    // Transactions admitted over RPC, encoded, waiting to be gossiped by the event loop
+    let (tx_sender, mut tx_receiver) = mpsc::channel::<Vec<u8>>(1000);
     
     // Core Engines
     let validator_set = storage.get_validator_set().expect("Validator set missing. Run init first.");
     let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
     let vm = Arc::new(AureumVM::new(storage.clone(), compliance.clone()));
-    let engine = Arc::new(Mutex::new(ConsensusEngine::new(validator_set)));
-    let mempool = Arc::new(Mutex::new(Vec::<Transaction>::new()));
+    let mut consensus = ConsensusEngine::new(validator_set);
+    consensus.height = storage.get_latest_height() + 1;
+    let chain_id = storage.get_chain_id();
+    consensus.chain_id = chain_id;
+    consensus.timeouts = timeouts;
+    info!("Chain id: {}", chain_id);
+    // Only a node started with --validator signs anything
+    let validator_key = validator.then(|| load_validator_key(data_dir).unwrap_or_else(|e| {
+        error!("❌ {}", e);
+        std::process::exit(1);
+    }));
+    match &validator_key {
+        Some(key) => {
+            info!("🔑 Signing votes as {}", aureum_node::core::generate_address(key.verifying_key().as_bytes()));
+            // A validator that cannot tell what it signed before must not sign again
+            let wal = ConsensusWal::open(std::path::Path::new(data_dir)).expect("Consensus WAL unreadable; refusing to start a validator that could double-sign");
+            consensus.wal = Some(wal);
+            consensus.replay_wal();
+        }
+        None => info!("Not a validator; following consensus without voting"),
+    }
+    let engine = Arc::new(Mutex::new(consensus));
+    let now = now_secs();
+    let mempool = Mempool::restore(MempoolConfig::default(), storage.clone(), now, |tx| vm.verify_compliance(tx, now));
+    let mempool = Arc::new(Mutex::new(mempool));
     let _oracle = Arc::new(Mutex::new(AureumOracle::new(storage.clone(), vec![])));
 
     // Network Setup
@@ -117,6 +323,8 @@ async fn run_node(data_dir: &str, rpc_port: u16) {
     network.subscribe(TOPIC_BLOCKS);
     network.subscribe(TOPIC_CONSENSUS);
 
+    let snapshot_dir = std::path::PathBuf::from(format!("{}/snapshots", data_dir));
+
     // Node Event Loop
     let engine_loop = engine.clone();
     let mempool_loop = mempool.clone();
@@ -124,292 +332,364 @@ async fn run_node(data_dir: &str, rpc_port: u16) {
     let vm_loop = vm.clone();
     
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+        let mut sync = BlockSync::new(now_secs());
+        let mut snapshot_sync = if fast_sync && storage_loop.get_latest_height() == 0 {
+            SnapshotSync::new(now_secs())
+        } else {
+            SnapshotSync::Done
+        };
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
         loop {
             tokio::select! {
                 // Handle P2P Network Events
-                event = network.swarm.next() => {
-                    if let Some(event) = event {
-                        match event {
-                            libp2p::swarm::SwarmEvent::Behaviour(aureum_node::network::AureumBehaviourEvent::Gossipsub(gossipsub::Event::Message {
-                                message, ..
-                            })) => {
-                                let topic = message.topic.as_str();
-                                if topic == TOPIC_TRANSACTIONS {
-                                    if let Ok(tx) = Transaction::decode(&mut &message.data[..]) {
-                                        let mut mempool = mempool_loop.lock().await;
-                                        if !mempool.iter().any(|existing| existing.hash() == tx.hash()) {
-                                            if vm_loop.verify_compliance(&tx) {
-                                                mempool.push(tx);
+                event = network.swarm.next() => match event {
+                    Some(SwarmEvent::Behaviour(AureumBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                        propagation_source, message_id, message
+                    }))) => {
+                        let topic = message.topic.as_str();
+                        let acceptance = if topic == TOPIC_TRANSACTIONS {
+                            match Transaction::decode(&mut &message.data[..]) {
+                                Ok(tx) if execution::check_envelope(chain_id, &tx).is_ok() => {
+                                    // Compliance or nonce mismatches can be honest races, so they are not penalized
+                                    let nonce = storage_loop.get_nonce(&tx.sender);
+                                    if vm_loop.verify_compliance(&tx, now_secs()) && mempool_loop.lock().await.insert(tx, nonce, now_secs()).is_ok() {
+                                        MessageAcceptance::Accept
+                                    } else {
+                                        MessageAcceptance::Ignore
+                                    }
+                                }
+                                _ => MessageAcceptance::Reject,
+                            }
+                        } else if topic == TOPIC_BLOCKS {
+                            match Proposal::decode(&mut &message.data[..]) {
+                                Ok(proposal) => {
+                                    let mut engine = engine_loop.lock().await;
+                                    let height = proposal.block.header.height;
+                                    match engine.verify_proposal(&proposal) {
+                                        Ok(()) => {
+                                            // Dry-run only: the block is committed once 2/3 precommit it
+                                            let state = Arc::new(storage_loop.begin_block());
+                                            match validation::validate_block(&state, &vm_loop, &engine, &proposal.block) {
+                                                Ok(receipts) => {
+                                                    info!("📥 Proposal for block {} round {} from {} verified ({} txs)", height, proposal.round, proposal.proposer, receipts.len());
+                                                    engine.set_proposal(proposal.block);
+                                                    MessageAcceptance::Accept
+                                                }
+                                                Err(e) if e.is_fault() => {
+                                                    network.penalize(&propagation_source, &format!("invalid block {}: {}", height, e));
+                                                    MessageAcceptance::Reject
+                                                }
+                                                Err(_) => MessageAcceptance::Ignore,
                                             }
                                         }
+                                        Err(e) if e.is_fault() => {
+                                            network.penalize(&propagation_source, &format!("invalid proposal {}: {}", height, e));
+                                            MessageAcceptance::Reject
+                                        }
+                                        // A proposal from further ahead means we fell behind: catch up through sync
+                                        Err(_) if height > engine.height => {
+                                            sync.on_peer_height(propagation_source, height - 1, storage_loop.get_latest_height());
+                                            MessageAcceptance::Ignore
+                                        }
+                                        Err(_) => MessageAcceptance::Ignore,
+                                    }
+                                }
+                                Err(_) => {
+                                    network.penalize(&propagation_source, "undecodable proposal");
+                                    MessageAcceptance::Reject
+                                }
+                            }
+                        } else if topic == TOPIC_CONSENSUS {
+                            match BftMessage::decode(&mut &message.data[..]) {
+                                Ok(vote) => {
+                                    let mut engine = engine_loop.lock().await;
+                                    if vote.height + 1 == engine.height && vote.step == BftStep::Precommit {
+                                        // A late precommit for the block we just committed still counts for its signer's liveness
+                                        engine.process_message(vote);
+                                        MessageAcceptance::Ignore
+                                    } else if vote.height != engine.height {
+                                        // Votes for other heights cannot be checked against our validator set
+                                        MessageAcceptance::Ignore
+                                    } else if !engine.authenticate(&vote) {
+                                        network.penalize(&propagation_source, &format!("unauthenticated vote from {}", vote.validator));
+                                        MessageAcceptance::Reject
+                                    } else {
+                                        if let Some(earlier) = engine.detect_equivocation(&vote)
+                                            && let Some(key) = &validator_key
+                                        {
+                                            // Put the proof on chain so every node slashes the offender identically
+                                            let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+                                            let account_nonce = storage_loop.get_nonce(&sender);
+                                            let mut mempool = mempool_loop.lock().await;
+                                            let tx = evidence_transaction(key, chain_id, mempool.pending_nonce(&sender, account_nonce), earlier, vote.clone());
+                                            match mempool.insert(tx.clone(), account_nonce, now_secs()) {
+                                                Ok(_) => {
+                                                    info!("⚖️ Submitting equivocation evidence against {} ({})", vote.validator, tx.hash());
+                                                    network.broadcast(TOPIC_TRANSACTIONS, tx.encode());
+                                                }
+                                                Err(e) => warn!("⚠️ Could not queue evidence against {}: {}", vote.validator, e),
+                                            }
+                                        }
+                                        engine.process_message(vote);
+                                        MessageAcceptance::Accept
+                                    }
+                                }
+                                Err(_) => {
+                                    network.penalize(&propagation_source, "undecodable vote");
+                                    MessageAcceptance::Reject
+                                }
+                            }
+                        } else {
+                            MessageAcceptance::Accept
+                        };
+                        network.report_validation(&message_id, &propagation_source, acceptance);
+                    }
+                    Some(SwarmEvent::Behaviour(AureumBehaviourEvent::Sync(request_response::Event::Message { peer, message }))) => match message {
+                        request_response::Message::Request { request, channel, .. } => {
+                            let response = match request {
+                                SyncRequest::Status => {
+                                    let height = storage_loop.get_latest_height();
+                                    SyncResponse::Status { height, head: storage_loop.get_block(height).map(|b| b.hash()).unwrap_or_default() }
+                                }
+                                SyncRequest::Blocks { from, count } => SyncResponse::Blocks(
+                                    (from..from.saturating_add(count.min(MAX_SYNC_BATCH) as u64))
+                                        .map_while(|h| storage_loop.get_block(h).map(|block| SyncedBlock { block, certificate: storage_loop.get_commit_certificate(h) }))
+                                        .collect()
+                                ),
+                            };
+                            network.respond_sync(channel, response);
+                        }
+                        request_response::Message::Response { response: SyncResponse::Status { height, .. }, .. } => {
+                            sync.on_peer_height(peer, height, storage_loop.get_latest_height());
+                        }
+                        request_response::Message::Response { response: SyncResponse::Blocks(blocks), .. } => {
+                            if blocks.is_empty() {
+                                sync.on_failure(&peer);
+                            }
+                            let mut engine = engine_loop.lock().await;
+                            for synced in &blocks {
+                                match sync::apply_synced_block(&storage_loop, &vm_loop, &mut engine, synced) {
+                                    Ok(receipts) => {
+                                        info!("📥 Synced block {} from {} ({} txs)", synced.block.header.height, peer, receipts.len());
+                                        let mut mempool = mempool_loop.lock().await;
+                                        for tx in &synced.block.transactions {
+                                            mempool.prune_sender(&tx.sender, storage_loop.get_nonce(&tx.sender));
+                                        }
+                                    }
+                                    Err(e) => {
+                                        if e.is_fault() {
+                                            network.penalize(&peer, &format!("invalid synced block {}: {}", synced.block.header.height, e));
+                                            sync.on_failure(&peer);
+                                        }
+                                        break;
+                                    }
+                                }
+                            }
+                            let local_height = storage_loop.get_latest_height();
+                            sync.on_blocks_applied(&peer, local_height);
+                            if let Some((peer, request)) = sync.next_request(local_height, now_secs()) {
+                                network.request_sync(&peer, request);
+                            }
+                        }
+                    },
+                    Some(SwarmEvent::Behaviour(AureumBehaviourEvent::Sync(request_response::Event::OutboundFailure { peer, error, .. }))) => {
+                        warn!("P2P Sync: request to {} failed: {}", peer, error);
+                        sync.on_failure(&peer);
+                    }
+                    Some(SwarmEvent::Behaviour(AureumBehaviourEvent::Mdns(mdns::Event::Discovered(found)))) => {
+                        for (peer, addr) in found {
+                            network.add_peer(peer, addr);
+                        }
+                    }
+                    Some(SwarmEvent::Behaviour(AureumBehaviourEvent::Snapshot(request_response::Event::Message { peer, message }))) => match message {
+                        request_response::Message::Request { request, channel, .. } => {
+                            let response = match request {
+                                SnapshotRequest::Manifest => SnapshotResponse::Manifest(snapshot::latest_snapshot(&snapshot_dir).map(|(m, _)| Box::new(m))),
+                                SnapshotRequest::Chunk { height, index } => SnapshotResponse::Chunk(
+                                    snapshot::read_chunk(&snapshot_dir.join(height.to_string()), index as usize).ok()
+                                ),
+                            };
+                            network.respond_snapshot(channel, response);
+                        }
+                        request_response::Message::Response { response: SnapshotResponse::Manifest(Some(manifest)), .. } => {
+                            let height = manifest.height;
+                            if let Some(index) = snapshot_sync.on_manifest(peer, manifest, chain_id, storage_loop.get_latest_height()) {
+                                network.request_snapshot(&peer, SnapshotRequest::Chunk { height, index });
+                            }
+                        }
+                        request_response::Message::Response { response: SnapshotResponse::Manifest(None), .. } => {}
+                        request_response::Message::Response { response: SnapshotResponse::Chunk(data), .. } => {
+                            let Some(data) = data else {
+                                snapshot_sync.on_failure(&peer);
+                                continue;
+                            };
+                            match snapshot_sync.on_chunk(&peer, data) {
+                                Ok(Some(index)) => {
+                                    if let snapshot::SnapshotSync::Downloading { manifest, .. } = &snapshot_sync {
+                                        network.request_snapshot(&peer, SnapshotRequest::Chunk { height: manifest.height, index });
                                     }
-                                } else if topic == TOPIC_BLOCKS {
-                                    if let Ok(block) = Block::decode(&mut &message.data[..]) {
-                                        // Simple block acceptance (Real: verify consensus)
-                                        storage_loop.save_block(&block);
+                                }
+                                Ok(None) => {
+                                    if let Some((manifest, chunks)) = snapshot_sync.take_complete() {
+                                        let authority_veto = engine_loop.lock().await.authority_veto_active;
+                                        match snapshot::restore(&storage_loop, &manifest, &chunks, None, authority_veto) {
+                                            Ok(()) => engine_loop.lock().await.finalize_block(&storage_loop, &manifest.block),
+                                            Err(e) => error!("❌ Snapshot from {} rejected: {}", peer, e),
+                                        }
                                     }
                                 }
+                                Err(e) => network.penalize(&peer, &format!("bad snapshot chunk: {}", e)),
                             }
-                            _ => {}
                         }
+                    },
+                    Some(SwarmEvent::Behaviour(AureumBehaviourEvent::Snapshot(request_response::Event::OutboundFailure { peer, .. }))) => {
+                        snapshot_sync.on_failure(&peer);
                     }
+                    Some(SwarmEvent::ConnectionEstablished { peer_id, .. }) => {
+                        network.request_sync(&peer_id, SyncRequest::Status);
+                        if snapshot_sync.is_pending(now_secs()) {
+                            network.request_snapshot(&peer_id, SnapshotRequest::Manifest);
+                        }
+                    }
+                    _ => {}
+                },
+
+                // Relay transactions submitted over RPC to the other mempools
+                Some(encoded) = tx_receiver.recv() => {
+                    network.broadcast(TOPIC_TRANSACTIONS, encoded);
                 }
                 
                 // Handle Consensus Ticks
                 _ = interval.tick() => {
+                    // A snapshot being fetched replaces replaying the blocks before it
+                    if snapshot_sync.is_pending(now_secs()) {
+                        continue;
+                    }
+                    // Blocks are replayed from peers until we reach their tip; only then take part in consensus
+                    if let Some((peer, request)) = sync.next_request(storage_loop.get_latest_height(), now_secs()) {
+                        network.request_sync(&peer, request);
+                    }
+                    if !sync.is_synced() {
+                        continue;
+                    }
+
                     let mut engine = engine_loop.lock().await;
 
+                    // Idle chains make no empty blocks, so round 0 only times out once there is something to propose
+                    let idle = engine.step == BftStep::Propose && engine.round == 0 && engine.proposal.is_none() && mempool_loop.lock().await.is_empty();
+                    if !idle {
+                        engine.on_timeout(now_millis());
+                    }
+
                     match engine.step {
                         BftStep::Propose => {
                             let proposer = engine.select_proposer();
-                            info!("Consensus: Proposer {} for height {}", proposer, engine.height);
-                            
-                            let mut txs = mempool_loop.lock().await;
-                            if !txs.is_empty() {
-                                let mut compliant_txs = Vec::new();
-                                for tx in txs.drain(..) {
-                                    if vm_loop.verify_compliance(&tx) {
-                                        compliant_txs.push(tx);
-                                    }
-                                }
+                            let signing_key = validator_key.as_ref().filter(|key| engine.validator_address(key).as_ref() == Some(&proposer));
+                            if let Some(key) = signing_key {
+                                // A locked block carried into a later round is proposed again as is
+                                let block = match engine.proposal.clone() {
+                                    Some(block) => Some(block),
+                                    None => {
+                                        // Block time must move forward even if the parent was stamped within this second
+                                        let parent = storage_loop.get_block(engine.height - 1);
+                                        let timestamp = now_secs().max(parent.as_ref().map(|b| b.header.timestamp + 1).unwrap_or_default());
+                                        // Compliance is evaluated at the block time the transactions will execute under
+                                        let mut mempool = mempool_loop.lock().await;
+                                        mempool.prune_expired(timestamp);
+                                        let selected = mempool.select_for_block(|sender| storage_loop.get_nonce(sender), |tx| vm_loop.verify_compliance(tx, timestamp));
+                                        drop(mempool);
+                                        if selected.is_empty() {
+                                            None
+                                        } else {
+                                            let mut block = Block {
+                                                header: BlockHeader {
+                                                    version: BLOCK_VERSION,
+                                                    chain_id,
+                                                    height: engine.height,
+                                                    timestamp,
+                                                    parent_hash: parent.map(|b| b.hash()).unwrap_or_default(),
+                                                    tx_merkle_root: "".into(),
+                                                    state_root: "".into(),
+                                                    proposer: proposer.clone(),
+                                                    validator_set_hash: storage_loop.get_validator_set().map(|set| set.hash()).unwrap_or_default(),
+                                                    last_commit_hash: "".into(),
+                                                },
+                                                transactions: selected,
+                                                // The parent's signers, which drive liveness tracking, including
+                                                // precommits that arrived after it was committed
+                                                last_commit: engine.last_commit().filter(|cert| cert.height + 1 == engine.height).cloned()
+                                                    .or_else(|| storage_loop.get_commit_certificate(engine.height - 1)),
+                                            };
+                                            block.header.tx_merkle_root = block.calculate_merkle_root();
+                                            block.header.last_commit_hash = block.calculate_last_commit_hash();
 
-                                if !compliant_txs.is_empty() {
-                                    let mut block = Block {
-                                        header: BlockHeader {
-                                            parent_hash: storage_loop.get_block(engine.height - 1).map(|b| b.hash()).unwrap_or_default(),
-                                            timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                                            height: engine.height,
-                                            state_root: storage_loop.calculate_state_root(),
-                                            tx_merkle_root: "".into(),
-                                        },
-                                        transactions: compliant_txs,
-                                    };
-                                    block.header.tx_merkle_root = block.calculate_merkle_root();
-                                    
-                                    // Broadcast block
-                                    network.broadcast(TOPIC_BLOCKS, block.encode());
-                                    engine.set_proposal(block);
+                                            // Dry-run the block to commit to its post-execution state root
+                                            let preview = Arc::new(storage_loop.begin_block());
+                                            match validation::execute_and_finalize(&preview, &vm_loop, &engine, &block) {
+                                                Ok((_, state_root)) => {
+                                                    block.header.state_root = state_root;
+                                                    Some(block)
+                                                }
+                                                Err(e) => {
+                                                    error!("❌ Could not build block {}: {}", block.header.height, e);
+                                                    None
+                                                }
+                                            }
+                                        }
+                                    }
+                                };
+                                // Like votes, the proposal is on disk before it is signed
+                                if let Some(block) = block
+                                    && engine.record_proposal(&block)
+                                {
+                                    info!("Consensus: Proposing block {} round {} with {} txs", block.header.height, engine.round, block.transactions.len());
+                                    // A re-proposed lock names the polka behind it; the header keeps its builder
+                                    let pol_round = engine.locked_block.as_ref()
+                                        .filter(|(_, locked)| locked.hash() == block.hash())
+                                        .map(|(round, _)| *round);
+                                    let mut proposal = Proposal { round: engine.round, pol_round, proposer, block, signature: vec![] };
+                                    proposal.sign(chain_id, key);
+                                    network.broadcast(TOPIC_BLOCKS, proposal.encode());
+                                    engine.set_proposal(proposal.block);
                                 }
                             }
+                            // Everyone else waits for the proposer's signed block or the propose timeout
                             engine.next_step(&storage_loop, None);
                         }
                         BftStep::Prevote | BftStep::Precommit => {
+                            if let Some(key) = &validator_key
+                                && let Some(vote) = engine.cast_vote(key)
+                            {
+                                network.broadcast(TOPIC_CONSENSUS, vote.encode());
+                            }
                             engine.next_step(&storage_loop, None);
                         }
                         BftStep::Commit => {
-                            if let Some(block) = engine.proposal.clone() {
+                            if let Some(block) = engine.decided_block().cloned() {
                                 info!("🔗 Committing block {} with {} transactions", block.header.height, block.transactions.len());
-                                
-                                for tx in &block.transactions {
-                                    if !vm_loop.verify_compliance(tx) {
-                                        warn!("⚠️  Tx Failed Compliance: {} -> {}", tx.sender, tx.receiver);
-                                        continue;
-                                    }
 
-                                    match &tx.tx_type {
-                                        TransactionType::Transfer => {
-                                            // Simple AUR transfer - direct balance update (no EVM needed)
-                                            let sender_balance = storage_loop.get_balance(&tx.sender);
-                                            let total_cost = tx.amount + tx.fee;
-                                            
-                                            if sender_balance >= total_cost {
-                                                storage_loop.update_balance(&tx.sender, sender_balance - total_cost);
-                                                let receiver_balance = storage_loop.get_balance(&tx.receiver);
-                                                storage_loop.update_balance(&tx.receiver, receiver_balance + tx.amount);
-                                                storage_loop.increment_nonce(&tx.sender);
-                                                info!("✅ Transfer: {} -> {} ({} AUR, fee: {})", tx.sender, tx.receiver, tx.amount, tx.fee);
-                                            } else {
-                                                error!("❌ Insufficient balance: {} has {} but needs {}", tx.sender, sender_balance, total_cost);
-                                            }
-                                        }
-                                        
-                                        TransactionType::ContractCall { data, target } => {
-                                            // EVM contract call
-                                            match vm_loop.execute_transaction(&tx.sender, target, data.clone(), tx.amount) {
-                                                Ok(_) => {
-                                                    info!("✅ Contract Call: {} -> {} ({} AUR)", tx.sender, target, tx.amount);
-                                                    storage_loop.increment_nonce(&tx.sender);
-                                                }
-                                                Err(e) => error!("❌ Contract Call Failed: {}", e),
-                                            }
-                                        }
-                                        
-                                        TransactionType::ContractCreate { bytecode } => {
-                                            // EVM contract deployment
-                                            match vm_loop.execute_transaction(&tx.sender, "0", bytecode.clone(), tx.amount) {
-                                                Ok(_) => {
-                                                    info!("✅ Contract Deployed by {}", tx.sender);
-                                                    storage_loop.increment_nonce(&tx.sender);
-                                                }
-                                                Err(e) => error!("❌ Contract Deploy Failed: {}", e),
-                                            }
-                                        }
-                                        
-                                        TransactionType::TokenizeProperty { address, metadata } => {
-                                            let prop = Property {
-                                                id: tx.hash(),
-                                                owner: tx.sender.clone(),
-                                                co_owners: vec![],
-                                                jurisdiction: "Portugal".to_string(), // Default for testnet
-                                                legal_description: address.clone(),
-                                                coordinates: (38.7223, -9.1393), // Lisbon coordinates
-                                                valuation_eur: tx.amount,
-                                                valuation_timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                                                valuation_oracle: "AureumCoreOracle".to_string(),
-                                                title_deed_hash: hex::encode(metadata.as_bytes()),
-                                                survey_hash: "".to_string(),
-                                                visa_program_eligible: tx.amount >= 500_000,
-                                                minimum_investment_met: true,
-                                                kyc_status: 1,
-                                                aml_cleared: true,
-                                                mortgages: vec![],
-                                                liens: vec![],
-                                                status: PropertyStatus::Available,
-                                            };
-                                            storage_loop.save_property(&prop);
-                                            storage_loop.increment_nonce(&tx.sender);
-                                            info!("🏠 Property Tokenized: {} (Valuation: {} AUR)", prop.id, prop.valuation_eur);
+                                // Transactions, rewards and the block itself land in one atomic write
+                                let state = Arc::new(storage_loop.begin_block());
+                                match validation::validate_block(&state, &vm_loop, &engine, &block) {
+                                    Ok(receipts) => {
+                                        match engine.commit_certificate(&block.hash()) {
+                                            Some(cert) => state.save_commit_certificate(&cert),
+                                            None => warn!("⚠️ Block {} committed without signed precommits", block.header.height),
                                         }
-
-                                        TransactionType::ApplyForVisa { property_id, program } => {
-                                            let app = VisaApplication {
-                                                applicant: tx.sender.clone(),
-                                                property_id: property_id.clone(),
-                                                investment_amount: tx.amount,
-                                                program: program.clone(),
-                                                status: ApplicationStatus::Pending,
-                                                timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                                            };
-                                            storage_loop.save_visa_application(&app);
-                                            storage_loop.increment_nonce(&tx.sender);
-                                            info!("🛂 Visa Application Submitted: {} for {:?}", app.applicant, app.program);
-                                        }
-
-                                        TransactionType::EscrowCreate { arbiter, conditions, property_id } => {
-                                            let sender_balance = storage_loop.get_balance(&tx.sender);
-                                            let total_cost = tx.amount + tx.fee;
-                                            
-                                            if sender_balance >= total_cost {
-                                                // 1. Deduct funds
-                                                storage_loop.update_balance(&tx.sender, sender_balance - total_cost);
-                                                storage_loop.increment_nonce(&tx.sender);
-
-                                                // 2. Create Escrow Record
-                                                let escrow = Escrow {
-                                                    id: tx.hash(),
-                                                    sender: tx.sender.clone(),
-                                                    receiver: tx.receiver.clone(),
-                                                    arbiter: arbiter.clone(),
-                                                    amount: tx.amount,
-                                                    conditions: conditions.clone(),
-                                                    property_id: property_id.clone(),
-                                                    status: EscrowStatus::Pending,
-                                                    created_at: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
-                                                };
-                                                storage_loop.save_escrow(&escrow);
-
-                                                // 3. Update Property Status if linked
-                                                if let Some(prop_id) = property_id {
-                                                    if let Some(mut prop) = storage_loop.get_property(prop_id) {
-                                                        prop.status = PropertyStatus::InEscrow;
-                                                        storage_loop.save_property(&prop);
-                                                        info!("🏠 Property {} status updated to InEscrow", prop_id);
-                                                    }
-                                                }
-
-                                                info!("🔒 Escrow Created: {} ({} AUR locked)", escrow.id, escrow.amount);
-                                            } else {
-                                                error!("❌ Escrow Create Failed: Insufficient Fund {}", tx.sender);
-                                            }
-                                        }
-
-                                        TransactionType::EscrowRelease { escrow_id } => {
-                                            if let Some(mut escrow) = storage_loop.get_escrow(escrow_id) {
-                                                if escrow.status == EscrowStatus::Pending {
-                                                    // Only Arbiter or Sender can release
-                                                    if tx.sender == escrow.arbiter || tx.sender == escrow.sender {
-                                                        // Update Escrow State
-                                                        escrow.status = EscrowStatus::Released;
-                                                        storage_loop.save_escrow(&escrow);
-                                                        
-                                                        // Transfer Funds to Receiver
-                                                        let receiver_balance = storage_loop.get_balance(&escrow.receiver);
-                                                        storage_loop.update_balance(&escrow.receiver, receiver_balance + escrow.amount);
-                                                        
-                                                        // Pay Fee
-                                                        let sender_balance = storage_loop.get_balance(&tx.sender);
-                                                        if sender_balance >= tx.fee {
-                                                             storage_loop.update_balance(&tx.sender, sender_balance - tx.fee);
-                                                        }
-
-                                                        // Update Property Status if linked
-                                                        if let Some(ref prop_id) = escrow.property_id {
-                                                            if let Some(mut prop) = storage_loop.get_property(prop_id) {
-                                                                prop.status = PropertyStatus::Sold;
-                                                                storage_loop.save_property(&prop);
-                                                                info!("🏠 Property {} status updated to Sold (Released)", prop_id);
-                                                            }
-                                                        }
-
-                                                        storage_loop.increment_nonce(&tx.sender);
-                                                        info!("🔓 Escrow Released: {} -> {} ({} AUR)", escrow_id, escrow.receiver, escrow.amount);
-                                                    } else {
-                                                        error!("❌ Escrow Release Failed: Unauthorized {}", tx.sender);
-                                                    }
-                                                } else {
-                                                     error!("❌ Escrow Release Failed: Status is {:?}", escrow.status);
-                                                }
-                                            }
-                                        }
-
-                                        TransactionType::EscrowRefund { escrow_id } => {
-                                            if let Some(mut escrow) = storage_loop.get_escrow(escrow_id) {
-                                                if escrow.status == EscrowStatus::Pending {
-                                                    // Only Arbiter can refund (or maybe receiver if they decline?) - Let's stick to Arbiter
-                                                    if tx.sender == escrow.arbiter {
-                                                        // Update Escrow State
-                                                        escrow.status = EscrowStatus::Refunded;
-                                                        storage_loop.save_escrow(&escrow);
-                                                        
-                                                        // Refund Funds to Sender
-                                                        let sender_balance = storage_loop.get_balance(&escrow.sender);
-                                                        storage_loop.update_balance(&escrow.sender, sender_balance + escrow.amount);
-                                                        
-                                                        // Pay Fee
-                                                        let arbiter_balance = storage_loop.get_balance(&tx.sender);
-                                                        if arbiter_balance >= tx.fee {
-                                                             storage_loop.update_balance(&tx.sender, arbiter_balance - tx.fee);
-                                                        }
-
-                                                        // Update Property Status if linked (back to Available)
-                                                        if let Some(ref prop_id) = escrow.property_id {
-                                                            if let Some(mut prop) = storage_loop.get_property(prop_id) {
-                                                                prop.status = PropertyStatus::Available;
-                                                                storage_loop.save_property(&prop);
-                                                                info!("🏠 Property {} status reverted to Available (Refunded)", prop_id);
-                                                            }
-                                                        }
-
-                                                        storage_loop.increment_nonce(&tx.sender);
-                                                        info!("↩️ Escrow Refunded: {} -> {} ({} AUR)", escrow_id, escrow.sender, escrow.amount);
-                                                    } else {
-                                                        error!("❌ Escrow Refund Failed: Unauthorized {}", tx.sender);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        
-                                        _ => {
-                                            // Other transaction types (Stake, etc.)
+                                        state.commit_block();
+                                        engine.next_step(&storage_loop, Some(&block));
+                                        let failed = receipts.iter().filter(|r| r.status != ReceiptStatus::Success).count();
+                                        info!("💾 Block {} finalized with {} txs ({} failed)", block.header.height, receipts.len(), failed);
+                                        let mut mempool = mempool_loop.lock().await;
+                                        for tx in &block.transactions {
+                                            mempool.prune_sender(&tx.sender, storage_loop.get_nonce(&tx.sender));
                                         }
                                     }
+                                    Err(e) => {
+                                        error!("❌ Block {} rejected: {}", block.header.height, e);
+                                        engine.next_step(&storage_loop, None);
+                                    }
                                 }
-                                
-                                storage_loop.save_block(&block);
-                                storage_loop.flush();
-                                info!("💾 Block {} finalized with {} txs", block.header.height, block.transactions.len());
-                                engine.next_step(&storage_loop, Some(&block));
                             } else {
                                 engine.next_step(&storage_loop, None);
                             }
@@ -460,7 +740,7 @@ async fn run_node(data_dir: &str, rpc_port: u16) {
     });
 
     let s_clone = storage.clone();
-    io.add_method("aureum_getChainState", move |params: Params| {
+    io.add_method("aureum_getChainState", move |_| {
         let s = s_clone.clone();
         async move {
             let state = s.get_chain_state().unwrap_or(ChainState { total_supply: 0, burned_fees: 0 });
@@ -491,6 +771,19 @@ async fn run_node(data_dir: &str, rpc_port: u16) {
         }
     });
 
+    let s_clone = storage.clone();
+    io.add_method("aureum_getCommitCertificate", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            let height: Vec<u64> = params.parse().map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Expected [height]"))?;
+            if height.is_empty() {
+                return Err(jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Height missing"));
+            }
+            let cert = s.get_commit_certificate(height[0]);
+            Ok(serde_json::to_value(cert).unwrap_or(Value::Null))
+        }
+    });
+
     let vm_clone = vm.clone();
     io.add_method("aureum_estimateGas", move |params: Params| {
         let _vm = vm_clone.clone();
@@ -505,9 +798,13 @@ async fn run_node(data_dir: &str, rpc_port: u16) {
 
     let m_clone = mempool.clone();
     let v_clone = vm.clone();
+    let s_clone = storage.clone();
+    let gossip = tx_sender.clone();
     io.add_method("aureum_submitTransaction", move |params: Params| {
         let m = m_clone.clone();
         let v = v_clone.clone();
+        let s = s_clone.clone();
+        let gossip = gossip.clone();
         async move {
             let txs: Vec<Transaction> = params.parse().map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Invalid Transaction JSON array"))?;
             if txs.is_empty() {
@@ -515,46 +812,174 @@ async fn run_node(data_dir: &str, rpc_port: u16) {
             }
             let tx = &txs[0];
             
-            if !tx.verify_signature() {
-                return Ok(Value::String("Invalid Signature".into()));
+            if let Err(e) = execution::check_envelope(s.get_chain_id(), tx) {
+                return Ok(Value::String(e.to_string()));
             }
 
-            if !v.verify_compliance(&tx) {
+            if !v.verify_compliance(tx, now_secs()) {
                 return Ok(Value::String("Compliance Check Failed".into()));
             }
 
             let hash = tx.hash();
             let mut tx_with_hash = tx.clone();
             tx_with_hash.hash = Some(hash.clone());
-            m.lock().await.push(tx_with_hash);
+            if let Err(e) = m.lock().await.insert(tx_with_hash, s.get_nonce(&tx.sender), now_secs()) {
+                return Ok(Value::String(e.to_string()));
+            }
+            if gossip.send(tx.encode()).await.is_err() {
+                warn!("⚠️ Transaction {} admitted but not gossiped: network loop stopped", hash);
+            }
             Ok(Value::String(hash))
         }
     });
 
     let m_clone = mempool.clone();
     let vm_clone = vm.clone();
+    let s_clone = storage.clone();
+    let gossip = tx_sender.clone();
     io.add_method("aureum_sendTransaction", move |params: Params| {
         let m = m_clone.clone();
         let v = vm_clone.clone();
+        let s = s_clone.clone();
+        let gossip = gossip.clone();
         async move {
             let tx_hex: String = params.parse().unwrap();
             let bytes = hex::decode(tx_hex.replace("0x", "")).map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Invalid hex"))?;
             let tx = Transaction::decode(&mut &bytes[..]).map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Decode Failed"))?;
             
-            if !tx.verify_signature() {
-                return Ok(Value::String("Invalid Signature".into()));
+            if let Err(e) = execution::check_envelope(s.get_chain_id(), &tx) {
+                return Ok(Value::String(e.to_string()));
             }
 
-            if !v.verify_compliance(&tx) {
+            if !v.verify_compliance(&tx, now_secs()) {
                 return Ok(Value::String("Compliance Check Failed".into()));
             }
 
             let hash = tx.hash();
-            m.lock().await.push(tx);
+            let nonce = s.get_nonce(&tx.sender);
+            if let Err(e) = m.lock().await.insert(tx, nonce, now_secs()) {
+                return Ok(Value::String(e.to_string()));
+            }
+            if gossip.send(bytes).await.is_err() {
+                warn!("⚠️ Transaction {} admitted but not gossiped: network loop stopped", hash);
+            }
             Ok(Value::String(format!("0x{}", hash)))
         }
     });
 
+    let s_clone = storage.clone();
+    io.add_method("aureum_getTransactionByHash", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            let hashes: Vec<String> = params.parse().map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Expected [hash]"))?;
+            let hash = hashes.first().ok_or_else(|| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Hash missing"))?;
+            match s.get_transaction(hash.trim_start_matches("0x")) {
+                Some((mut tx, receipt)) => {
+                    tx.hash = Some(receipt.tx_hash.clone());
+                    let mut value = serde_json::to_value(tx).unwrap();
+                    value["block_height"] = receipt.block_height.into();
+                    value["index"] = receipt.index.into();
+                    Ok(value)
+                }
+                None => Ok(Value::Null),
+            }
+        }
+    });
+
+    let s_clone = storage.clone();
+    io.add_method("aureum_getTransactionReceipt", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            let hashes: Vec<String> = params.parse().map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Expected [hash]"))?;
+            let hash = hashes.first().ok_or_else(|| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Hash missing"))?;
+            let receipt = s.get_receipt(hash.trim_start_matches("0x"));
+            Ok(serde_json::to_value(receipt).unwrap_or(Value::Null))
+        }
+    });
+
+    let s_clone = storage.clone();
+    io.add_method("aureum_getTransactionsByAddress", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            // [address, { direction, tx_type, limit, cursor, newest_first }?]
+            let args: Vec<Value> = params.parse().map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Expected [address, options?]"))?;
+            let address = args.first().and_then(|v| v.as_str()).ok_or_else(|| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Address missing"))?;
+            let query: TxQuery = match args.get(1) {
+                Some(opts) => serde_json::from_value(opts.clone()).map_err(|e| jsonrpc_http_server::jsonrpc_core::Error::invalid_params(format!("Invalid options: {}", e)))?,
+                None => TxQuery::default(),
+            };
+
+            let (entries, next_cursor) = s.get_transactions_by_address(address, &query);
+            let transactions: Vec<Value> = entries.into_iter().map(|entry| {
+                let tx = s.get_transaction(&entry.tx_hash).map(|(tx, _)| tx);
+                let mut value = serde_json::to_value(entry).unwrap();
+                value["transaction"] = serde_json::to_value(tx).unwrap_or(Value::Null);
+                value
+            }).collect();
+            Ok(serde_json::json!({ "transactions": transactions, "next_cursor": next_cursor }))
+        }
+    });
+
+    let s_clone = storage.clone();
+    io.add_method("aureum_getProof", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            // [key, height?]: without a height the proof is against the current state root
+            let args: Vec<Value> = params.parse().map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Expected [key, height?]"))?;
+            let key = args.first().and_then(|v| v.as_str()).ok_or_else(|| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Key missing"))?;
+            let height = args.get(1).and_then(|v| v.as_u64());
+
+            // Values are only kept for the latest state; historical proofs carry the value hash
+            let value = if height.is_none() { s.get_raw_value(key) } else { None };
+            let (state_root, proof) = s.get_state_proof(key, height)
+                .ok_or_else(|| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("No state root recorded for height"))?;
+            Ok(serde_json::json!({
+                "height": height.unwrap_or_else(|| s.get_latest_height()),
+                "state_root": state_root,
+                "value": value.map(hex::encode),
+                "proof": proof,
+            }))
+        }
+    });
+
+    let s_clone = storage.clone();
+    io.add_method("aureum_getPropertyTransactions", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            let params: Vec<String> = params.parse().unwrap_or_default();
+            if params.is_empty() { return Ok(Value::Null); }
+            Ok(serde_json::to_value(s.get_transactions_by_property(&params[0])).unwrap())
+        }
+    });
+
+    let s_clone = storage.clone();
+    io.add_method("aureum_getEscrowTransactions", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            let params: Vec<String> = params.parse().unwrap_or_default();
+            if params.is_empty() { return Ok(Value::Null); }
+            Ok(serde_json::to_value(s.get_transactions_by_escrow(&params[0])).unwrap())
+        }
+    });
+
+    let s_clone = storage.clone();
+    io.add_method("aureum_chainId", move |_| {
+        let s = s_clone.clone();
+        async move {
+            Ok(Value::String(s.get_chain_id().to_string()))
+        }
+    });
+
+    // Lets clients sign without re-implementing the canonical payload layout
+    io.add_method("aureum_getSigningPayload", move |params: Params| {
+        async move {
+            let txs: Vec<Transaction> = params.parse().map_err(|_| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Invalid Transaction JSON array"))?;
+            let tx = txs.first().ok_or_else(|| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Transaction missing"))?;
+            let payload = tx.signing_bytes().ok_or_else(|| jsonrpc_http_server::jsonrpc_core::Error::invalid_params("Unsupported transaction version"))?;
+            Ok(Value::String(format!("0x{}", hex::encode(payload))))
+        }
+    });
+
     let s_clone = storage.clone();
     io.add_method("aureum_getProperty", move |params: Params| {
         let s = s_clone.clone();
@@ -574,6 +999,27 @@ async fn run_node(data_dir: &str, rpc_port: u16) {
         }
     });
 
+    let s_clone = storage.clone();
+    io.add_method("aureum_getDelegations", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            let params: Vec<String> = params.parse().unwrap_or_default();
+            if params.is_empty() { return Ok(Value::Null); }
+            Ok(serde_json::to_value(s.get_delegations(&params[0])).unwrap_or(Value::Null))
+        }
+    });
+
+    let s_clone = storage.clone();
+    io.add_method("aureum_getUnbonding", move |params: Params| {
+        let s = s_clone.clone();
+        async move {
+            let params: Vec<String> = params.parse().unwrap_or_default();
+            if params.is_empty() { return Ok(Value::Null); }
+            let entries: Vec<_> = s.get_unbonding_queue().into_iter().filter(|e| e.owner == params[0]).collect();
+            Ok(serde_json::to_value(entries).unwrap_or(Value::Null))
+        }
+    });
+
     let s_clone = storage.clone();
     io.add_method("aureum_getVisaStatus", move |params: Params| {
         let s = s_clone.clone();
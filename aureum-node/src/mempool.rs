@@ -0,0 +1,425 @@
+use crate::core::Transaction;
+use crate::execution;
+use crate::storage::ChainStorage;
+use parity_scale_codec::Encode;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+use log::{debug, info};
+
+#[derive(Debug, Clone)]
+pub struct MempoolConfig {
+    /// Maximum number of pending transactions across all senders
+    pub max_size: usize,
+    /// Maximum number of queued nonces per sender
+    pub max_per_sender: usize,
+    /// Pending transactions older than this are dropped
+    pub tx_ttl_secs: u64,
+    /// Minimum fee increase, in percent, for a same-nonce replacement
+    pub replacement_bump_pct: u64,
+    /// Per-block transaction count limit
+    pub max_block_txs: usize,
+    /// Per-block limit on the SCALE-encoded size of all transactions
+    pub max_block_bytes: usize,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10_000,
+            max_per_sender: 64,
+            tx_ttl_secs: 3 * 60 * 60,
+            replacement_bump_pct: 10,
+            max_block_txs: 500,
+            max_block_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MempoolError {
+    AlreadyKnown,
+    NonceTooLow { expected: u64, found: u64 },
+    NonceGap { expected: u64, found: u64 },
+    ReplacementUnderpriced { existing_fee: u64, required_fee: u64 },
+    SenderQueueFull,
+    PoolFull,
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::AlreadyKnown => write!(f, "transaction already known"),
+            MempoolError::NonceTooLow { expected, found } => write!(f, "nonce too low: expected {}, got {}", expected, found),
+            MempoolError::NonceGap { expected, found } => write!(f, "nonce gap: expected {}, got {}", expected, found),
+            MempoolError::ReplacementUnderpriced { existing_fee, required_fee } => write!(f, "replacement underpriced: pending fee {}, need at least {}", existing_fee, required_fee),
+            MempoolError::SenderQueueFull => write!(f, "too many pending transactions for sender"),
+            MempoolError::PoolFull => write!(f, "mempool full"),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+#[derive(Debug, Clone)]
+struct PoolEntry {
+    tx: Transaction,
+    hash: String,
+    size: usize,
+    inserted_at: u64,
+}
+
+/// Head of one sender's queue, ordered by fee (highest first) then hash for determinism
+struct Candidate<'a> {
+    entry: &'a PoolEntry,
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.entry.tx.fee.cmp(&other.entry.tx.fee)
+            .then_with(|| other.entry.hash.cmp(&self.entry.hash))
+    }
+}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entry.hash == other.entry.hash
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+/// Pending transactions, queued per sender in nonce order
+pub struct Mempool {
+    config: MempoolConfig,
+    queues: HashMap<String, BTreeMap<u64, PoolEntry>>,
+    by_hash: HashMap<String, (String, u64)>,
+    journal: Option<Arc<ChainStorage>>,
+}
+
+impl Mempool {
+    pub fn new(config: MempoolConfig) -> Self {
+        Self { config, queues: HashMap::new(), by_hash: HashMap::new(), journal: None }
+    }
+
+    /// Pool journaled to storage, reloaded with the transactions pending from a previous run.
+    ///
+    /// Each journaled transaction must still pass the envelope and nonce checks against
+    /// current chain state, plus `accept` (compliance); the rest are dropped from the journal.
+    pub fn restore(config: MempoolConfig, storage: Arc<ChainStorage>, now: u64, accept: impl Fn(&Transaction) -> bool) -> Self {
+        let mut pool = Self::new(config);
+        pool.journal = Some(storage.clone());
+
+        let mut pending = storage.list_pending_txs();
+        pending.sort_by(|(_, a), (_, b)| a.sender.cmp(&b.sender).then(a.nonce.cmp(&b.nonce)));
+        let chain_id = storage.get_chain_id();
+        let total = pending.len();
+
+        for (inserted_at, tx) in pending {
+            let hash = tx.hash();
+            let admitted = execution::check_envelope(chain_id, &tx).is_ok()
+                && accept(&tx)
+                && pool.insert(tx.clone(), storage.get_nonce(&tx.sender), inserted_at).is_ok();
+            if !admitted {
+                storage.remove_pending_tx(&hash);
+            }
+        }
+        pool.prune_expired(now);
+
+        if total > 0 {
+            info!("Mempool: restored {} of {} journaled transactions", pool.len(), total);
+        }
+        pool
+    }
+
+    pub fn config(&self) -> &MempoolConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&Transaction> {
+        let (sender, nonce) = self.by_hash.get(hash)?;
+        self.queues.get(sender)?.get(nonce).map(|e| &e.tx)
+    }
+
+    /// Next nonce the sender can submit, given the nonce committed on chain
+    pub fn pending_nonce(&self, sender: &str, account_nonce: u64) -> u64 {
+        let mut next = account_nonce;
+        if let Some(queue) = self.queues.get(sender) {
+            while queue.contains_key(&next) {
+                next += 1;
+            }
+        }
+        next
+    }
+
+    /// All pending transactions in deterministic (sender, nonce) order
+    pub fn transactions(&self) -> Vec<Transaction> {
+        let mut senders: Vec<&String> = self.queues.keys().collect();
+        senders.sort();
+        senders.into_iter()
+            .flat_map(|s| self.queues[s].values().map(|e| e.tx.clone()))
+            .collect()
+    }
+
+    /// Admit a transaction whose envelope has already been validated.
+    ///
+    /// `account_nonce` is the sender's committed nonce. A transaction with the nonce of a
+    /// pending one replaces it if it pays a sufficiently higher fee. Returns the hashes
+    /// of any transactions that were replaced or evicted to make room.
+    pub fn insert(&mut self, tx: Transaction, account_nonce: u64, now: u64) -> Result<Vec<String>, MempoolError> {
+        let hash = tx.hash();
+        if self.by_hash.contains_key(&hash) {
+            return Err(MempoolError::AlreadyKnown);
+        }
+        if tx.nonce < account_nonce {
+            return Err(MempoolError::NonceTooLow { expected: account_nonce, found: tx.nonce });
+        }
+
+        let entry = PoolEntry { size: tx.encode().len(), hash: hash.clone(), inserted_at: now, tx };
+
+        // Replace-by-fee for an already pending nonce
+        if let Some(existing) = self.queues.get(&entry.tx.sender).and_then(|q| q.get(&entry.tx.nonce)) {
+            let required_fee = existing.tx.fee + (existing.tx.fee * self.config.replacement_bump_pct).div_ceil(100).max(1);
+            if entry.tx.fee < required_fee {
+                return Err(MempoolError::ReplacementUnderpriced { existing_fee: existing.tx.fee, required_fee });
+            }
+            let replaced = existing.hash.clone();
+            self.by_hash.remove(&replaced);
+            info!("Mempool: {} replaced by {} (nonce {})", replaced, hash, entry.tx.nonce);
+            if let Some(journal) = &self.journal {
+                journal.remove_pending_tx(&replaced);
+                journal.journal_pending_tx(&entry.tx, entry.inserted_at);
+            }
+            self.by_hash.insert(hash, (entry.tx.sender.clone(), entry.tx.nonce));
+            self.queues.get_mut(&entry.tx.sender).unwrap().insert(entry.tx.nonce, entry);
+            return Ok(vec![replaced]);
+        }
+
+        let expected = self.pending_nonce(&entry.tx.sender, account_nonce);
+        if entry.tx.nonce > expected {
+            return Err(MempoolError::NonceGap { expected, found: entry.tx.nonce });
+        }
+        if self.queues.get(&entry.tx.sender).is_some_and(|q| q.len() >= self.config.max_per_sender) {
+            return Err(MempoolError::SenderQueueFull);
+        }
+
+        let mut evicted = vec![];
+        if self.len() >= self.config.max_size {
+            evicted.push(self.evict_for(entry.tx.fee)?);
+        }
+
+        if let Some(journal) = &self.journal {
+            journal.journal_pending_tx(&entry.tx, entry.inserted_at);
+        }
+        self.by_hash.insert(hash, (entry.tx.sender.clone(), entry.tx.nonce));
+        self.queues.entry(entry.tx.sender.clone()).or_default().insert(entry.tx.nonce, entry);
+        Ok(evicted)
+    }
+
+    /// Evict the cheapest queue tail if it pays less than `fee`.
+    /// Only tails are evicted so no sender is left with a nonce gap.
+    fn evict_for(&mut self, fee: u64) -> Result<String, MempoolError> {
+        let victim = self.queues.iter()
+            .filter_map(|(sender, q)| q.values().next_back().map(|e| (e.tx.fee, e.hash.clone(), sender.clone(), e.tx.nonce)))
+            .min_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)));
+
+        match victim {
+            Some((victim_fee, hash, sender, nonce)) if victim_fee < fee => {
+                self.remove_entry(&sender, nonce);
+                debug!("Mempool: evicted {} (fee {})", hash, victim_fee);
+                Ok(hash)
+            }
+            _ => Err(MempoolError::PoolFull),
+        }
+    }
+
+    fn remove_entry(&mut self, sender: &str, nonce: u64) -> Option<PoolEntry> {
+        let queue = self.queues.get_mut(sender)?;
+        let entry = queue.remove(&nonce)?;
+        if queue.is_empty() {
+            self.queues.remove(sender);
+        }
+        self.by_hash.remove(&entry.hash);
+        if let Some(journal) = &self.journal {
+            journal.remove_pending_tx(&entry.hash);
+        }
+        Some(entry)
+    }
+
+    /// Drop a sender's transactions that are no longer executable after a commit
+    pub fn prune_sender(&mut self, sender: &str, account_nonce: u64) -> Vec<String> {
+        let stale: Vec<u64> = match self.queues.get(sender) {
+            Some(q) => q.range(..account_nonce).map(|(n, _)| *n).collect(),
+            None => return vec![],
+        };
+        stale.into_iter()
+            .filter_map(|n| self.remove_entry(sender, n).map(|e| e.hash))
+            .collect()
+    }
+
+    /// Drop transactions older than the configured TTL, along with the later nonces that depend on them
+    pub fn prune_expired(&mut self, now: u64) -> Vec<String> {
+        let ttl = self.config.tx_ttl_secs;
+        let mut expired = vec![];
+        for (sender, queue) in &self.queues {
+            if let Some((nonce, _)) = queue.iter().find(|(_, e)| now.saturating_sub(e.inserted_at) > ttl) {
+                expired.extend(queue.range(*nonce..).map(|(n, _)| (sender.clone(), *n)));
+            }
+        }
+        let removed: Vec<String> = expired.into_iter()
+            .filter_map(|(sender, nonce)| self.remove_entry(&sender, nonce).map(|e| e.hash))
+            .collect();
+        if !removed.is_empty() {
+            info!("Mempool: {} transactions expired", removed.len());
+        }
+        removed
+    }
+
+    /// Choose the transactions for the next block without removing them.
+    ///
+    /// Senders are served in fee order, each strictly in nonce order starting at their
+    /// committed nonce. The result is bounded by the per-block limits and depends only on
+    /// pool contents and chain state, never on insertion order.
+    pub fn select_for_block(&self, account_nonce: impl Fn(&str) -> u64, include: impl Fn(&Transaction) -> bool) -> Vec<Transaction> {
+        let mut heads = BinaryHeap::new();
+        for (sender, queue) in &self.queues {
+            if let Some(entry) = queue.get(&account_nonce(sender)) {
+                heads.push(Candidate { entry });
+            }
+        }
+
+        let mut selected = vec![];
+        let mut bytes = 0;
+        while let Some(Candidate { entry }) = heads.pop() {
+            if selected.len() >= self.config.max_block_txs {
+                break;
+            }
+            // A skipped transaction would leave a gap, so the sender is done for this block
+            if bytes + entry.size > self.config.max_block_bytes || !include(&entry.tx) {
+                continue;
+            }
+            bytes += entry.size;
+            selected.push(entry.tx.clone());
+
+            if let Some(next) = self.queues[&entry.tx.sender].get(&(entry.tx.nonce + 1)) {
+                heads.push(Candidate { entry: next });
+            }
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{TransactionType, TX_VERSION, DEFAULT_CHAIN_ID};
+
+    fn tx(sender: &str, nonce: u64, fee: u64) -> Transaction {
+        Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: sender.into(),
+            receiver: "Abob".into(),
+            amount: 1,
+            nonce,
+            fee,
+            signature: vec![],
+            pub_key: vec![],
+            tx_type: TransactionType::Transfer,
+            hash: None,
+        }
+    }
+
+    fn pool(max_size: usize) -> Mempool {
+        Mempool::new(MempoolConfig { max_size, ..Default::default() })
+    }
+
+    #[test]
+    fn test_nonce_sequencing() {
+        let mut pool = pool(100);
+        assert_eq!(pool.insert(tx("a", 3, 1), 5, 0), Err(MempoolError::NonceTooLow { expected: 5, found: 3 }));
+        pool.insert(tx("a", 5, 1), 5, 0).unwrap();
+        assert_eq!(pool.insert(tx("a", 7, 1), 5, 0), Err(MempoolError::NonceGap { expected: 6, found: 7 }));
+        pool.insert(tx("a", 6, 1), 5, 0).unwrap();
+        assert_eq!(pool.pending_nonce("a", 5), 7);
+        assert_eq!(pool.insert(tx("a", 6, 1), 5, 0), Err(MempoolError::AlreadyKnown));
+
+        // Committing nonce 5 prunes it
+        assert_eq!(pool.prune_sender("a", 6).len(), 1);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let mut pool = pool(100);
+        let original = tx("a", 0, 100);
+        pool.insert(original.clone(), 0, 0).unwrap();
+
+        assert_eq!(pool.insert(tx("a", 0, 105), 0, 0), Err(MempoolError::ReplacementUnderpriced { existing_fee: 100, required_fee: 110 }));
+        assert_eq!(pool.insert(tx("a", 0, 110), 0, 0), Ok(vec![original.hash()]));
+        assert_eq!(pool.len(), 1);
+        assert!(!pool.contains(&original.hash()));
+    }
+
+    #[test]
+    fn test_eviction_when_full() {
+        let mut pool = pool(2);
+        pool.insert(tx("a", 0, 10), 0, 0).unwrap();
+        pool.insert(tx("b", 0, 20), 0, 0).unwrap();
+
+        assert_eq!(pool.insert(tx("c", 0, 5), 0, 0), Err(MempoolError::PoolFull));
+        let evicted = pool.insert(tx("c", 0, 15), 0, 0).unwrap();
+        assert_eq!(evicted, vec![tx("a", 0, 10).hash()]);
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_expiry_drops_dependent_nonces() {
+        let mut pool = pool(100);
+        pool.insert(tx("a", 0, 1), 0, 0).unwrap();
+        pool.insert(tx("a", 1, 1), 0, 5_000).unwrap();
+        pool.insert(tx("b", 0, 1), 0, 5_000).unwrap();
+
+        let ttl = pool.config().tx_ttl_secs;
+        assert_eq!(pool.prune_expired(ttl + 1).len(), 2);
+        assert_eq!(pool.transactions().iter().map(|t| t.sender.as_str()).collect::<Vec<_>>(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_block_selection_is_fee_ordered_and_bounded() {
+        let mut pool = Mempool::new(MempoolConfig { max_block_txs: 3, ..Default::default() });
+        pool.insert(tx("a", 0, 1), 0, 0).unwrap();
+        pool.insert(tx("a", 1, 50), 0, 0).unwrap();
+        pool.insert(tx("b", 0, 10), 0, 0).unwrap();
+        pool.insert(tx("c", 0, 5), 0, 0).unwrap();
+
+        let picked = pool.select_for_block(|_| 0, |_| true);
+        let order: Vec<(&str, u64)> = picked.iter().map(|t| (t.sender.as_str(), t.nonce)).collect();
+        // b pays most at the head; a's cheap nonce 0 must precede its expensive nonce 1
+        assert_eq!(order, vec![("b", 0), ("c", 0), ("a", 0)]);
+
+        // Byte limit admits only as many transactions as fit
+        let size = tx("a", 0, 1).encode().len();
+        let tight = Mempool { config: MempoolConfig { max_block_bytes: size * 2, ..Default::default() }, ..pool };
+        assert_eq!(tight.select_for_block(|_| 0, |_| true).len(), 2);
+    }
+}
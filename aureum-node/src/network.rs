@@ -4,6 +4,7 @@ use libp2p::{
     identify,
     mdns,
     noise,
+    request_response,
     tcp,
     yamux,
     PeerId,
@@ -13,8 +14,15 @@ use libp2p::{
     Multiaddr,
 };
 use libp2p::swarm::NetworkBehaviour;
+use crate::core::Block;
+use crate::consensus::CommitCertificate;
+use crate::snapshot::SnapshotManifest;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use parity_scale_codec::{Encode, Decode};
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::marker::PhantomData;
 use std::time::Duration;
 use log::{info, warn};
 
@@ -23,12 +31,124 @@ pub const TOPIC_TRANSACTIONS: &str = "aureum_tx";
 pub const TOPIC_BLOCKS: &str = "aureum_blocks";
 pub const TOPIC_CONSENSUS: &str = "aureum_consensus";
 
+/// Invalid messages tolerated from a peer before it is disconnected and blacklisted
+pub const MAX_PEER_VIOLATIONS: u32 = 3;
+
+pub use gossipsub::MessageAcceptance;
+
+/// Request-response protocol for fetching past blocks from peers
+pub const SYNC_PROTOCOL: &str = "/aureum/sync/1.0.0";
+/// Most blocks served in a single sync response
+pub const MAX_SYNC_BATCH: u32 = 64;
+/// Upper bound on an encoded sync request or response
+pub const MAX_SYNC_MESSAGE_BYTES: u64 = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub enum SyncRequest {
+    /// Ask for the peer's tip
+    Status,
+    /// Ask for up to `count` consecutive blocks starting at `from`
+    Blocks { from: u64, count: u32 },
+}
+
+/// A committed block with the certificate proving it was finalized
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct SyncedBlock {
+    pub block: Block,
+    pub certificate: Option<CommitCertificate>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum SyncResponse {
+    Status { height: u64, head: String },
+    Blocks(Vec<SyncedBlock>),
+}
+
+/// Request-response protocol for downloading state snapshots
+pub const SNAPSHOT_PROTOCOL: &str = "/aureum/snapshot/1.0.0";
+
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub enum SnapshotRequest {
+    /// Ask for the manifest of the peer's latest snapshot
+    Manifest,
+    Chunk { height: u64, index: u32 },
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub enum SnapshotResponse {
+    Manifest(Option<Box<SnapshotManifest>>),
+    Chunk(Option<Vec<u8>>),
+}
+
+/// SCALE codec for request-response protocols; each stream carries exactly one message
+pub struct ScaleCodec<Req, Res>(PhantomData<(Req, Res)>);
+
+impl<Req, Res> Default for ScaleCodec<Req, Res> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<Req, Res> Clone for ScaleCodec<Req, Res> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+pub type SyncCodec = ScaleCodec<SyncRequest, SyncResponse>;
+pub type SnapshotCodec = ScaleCodec<SnapshotRequest, SnapshotResponse>;
+
+async fn read_scale<T: Decode, R: AsyncRead + Unpin + Send>(io: &mut R) -> io::Result<T> {
+    let mut buf = vec![];
+    io.take(MAX_SYNC_MESSAGE_BYTES).read_to_end(&mut buf).await?;
+    T::decode(&mut &buf[..]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+#[async_trait::async_trait]
+impl<Req: Encode + Decode + Send + 'static, Res: Encode + Decode + Send + 'static> request_response::Codec for ScaleCodec<Req, Res> {
+    type Protocol = StreamProtocol;
+    type Request = Req;
+    type Response = Res;
+
+    async fn read_request<T: AsyncRead + Unpin + Send>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Req> {
+        read_scale(io).await
+    }
+
+    async fn read_response<T: AsyncRead + Unpin + Send>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Res> {
+        read_scale(io).await
+    }
+
+    async fn write_request<T: AsyncWrite + Unpin + Send>(&mut self, _: &StreamProtocol, io: &mut T, req: Req) -> io::Result<()> {
+        io.write_all(&req.encode()).await
+    }
+
+    async fn write_response<T: AsyncWrite + Unpin + Send>(&mut self, _: &StreamProtocol, io: &mut T, res: Res) -> io::Result<()> {
+        io.write_all(&res.encode()).await
+    }
+}
+
+/// Peer scoring: invalid messages on a topic weigh heavily, silence is not penalized
+fn peer_score_params() -> gossipsub::PeerScoreParams {
+    let mut params = gossipsub::PeerScoreParams::default();
+    for topic in [TOPIC_TRANSACTIONS, TOPIC_BLOCKS, TOPIC_CONSENSUS] {
+        params.topics.insert(gossipsub::IdentTopic::new(topic).hash(), gossipsub::TopicScoreParams {
+            mesh_message_deliveries_weight: 0.0,
+            mesh_failure_penalty_weight: 0.0,
+            invalid_message_deliveries_weight: -100.0,
+            ..Default::default()
+        });
+    }
+    params
+}
+
 #[derive(NetworkBehaviour)]
 pub struct AureumBehaviour {
     pub gossipsub: gossipsub::Behaviour,
     pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
     pub mdns: mdns::tokio::Behaviour,
     pub identify: identify::Behaviour,
+    pub sync: request_response::Behaviour<SyncCodec>,
+    pub snapshot: request_response::Behaviour<SnapshotCodec>,
 }
 
 pub struct P2PNetwork {
@@ -36,6 +156,7 @@ pub struct P2PNetwork {
     pub local_peer_id: PeerId,
     pub peers: HashMap<PeerId, Vec<Multiaddr>>,
     pub known_validators: HashSet<PeerId>,
+    pub violations: HashMap<PeerId, u32>,
 }
 
 impl P2PNetwork {
@@ -62,15 +183,18 @@ impl P2PNetwork {
                 let gossipsub_config = gossipsub::ConfigBuilder::default()
                     .heartbeat_interval(Duration::from_secs(1))
                     .validation_mode(gossipsub::ValidationMode::Strict)
+                    // Messages are only forwarded once the node has validated them
+                    .validate_messages()
                     .message_id_fn(message_id_fn)
                     .max_transmit_size(10 * 1024 * 1024)
                     .build()
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+                    .map_err(std::io::Error::other)?;
 
-                let gossipsub = gossipsub::Behaviour::new(
+                let mut gossipsub = gossipsub::Behaviour::new(
                     gossipsub::MessageAuthenticity::Signed(key.clone()),
                     gossipsub_config,
                 )?;
+                gossipsub.with_peer_score(peer_score_params(), gossipsub::PeerScoreThresholds::default())?;
 
                 let mut kad_config = kad::Config::default();
                 kad_config.set_protocol_names(vec![StreamProtocol::new("/aureum/kad/1.0.0")]);
@@ -87,7 +211,17 @@ impl P2PNetwork {
                     key.public(),
                 ));
 
-                Ok(AureumBehaviour { gossipsub, kademlia, mdns, identify })
+                let sync = request_response::Behaviour::new(
+                    [(StreamProtocol::new(SYNC_PROTOCOL), request_response::ProtocolSupport::Full)],
+                    request_response::Config::default().with_request_timeout(Duration::from_secs(30)),
+                );
+
+                let snapshot = request_response::Behaviour::new(
+                    [(StreamProtocol::new(SNAPSHOT_PROTOCOL), request_response::ProtocolSupport::Full)],
+                    request_response::Config::default().with_request_timeout(Duration::from_secs(60)),
+                );
+
+                Ok(AureumBehaviour { gossipsub, kademlia, mdns, identify, sync, snapshot })
             })?
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
@@ -97,6 +231,7 @@ impl P2PNetwork {
             local_peer_id,
             peers: HashMap::new(),
             known_validators: HashSet::new(),
+            violations: HashMap::new(),
         })
     }
 
@@ -112,11 +247,51 @@ impl P2PNetwork {
         }
     }
 
+    /// Report the outcome of validating a gossip message; only accepted messages are forwarded
+    pub fn report_validation(&mut self, message_id: &gossipsub::MessageId, source: &PeerId, acceptance: MessageAcceptance) {
+        let _ = self.swarm.behaviour_mut().gossipsub.report_message_validation_result(message_id, source, acceptance);
+    }
+
+    /// Record misbehaviour by a peer and cut it off once it exceeds `MAX_PEER_VIOLATIONS`
+    pub fn penalize(&mut self, peer: &PeerId, reason: &str) {
+        let count = self.violations.entry(*peer).or_insert(0);
+        *count += 1;
+        warn!("Peer {} misbehaved ({}/{}): {}", peer, count, MAX_PEER_VIOLATIONS, reason);
+        if *count >= MAX_PEER_VIOLATIONS {
+            warn!("Blacklisting peer {}", peer);
+            self.swarm.behaviour_mut().gossipsub.blacklist_peer(peer);
+            let _ = self.swarm.disconnect_peer_id(*peer);
+        }
+    }
+
     pub fn add_peer(&mut self, peer_id: PeerId, addr: Multiaddr) {
         self.swarm.behaviour_mut().kademlia.add_address(&peer_id, addr.clone());
+        if !self.swarm.is_connected(&peer_id) {
+            let _ = self.swarm.dial(addr.clone());
+        }
         self.peers.entry(peer_id).or_insert(vec![]).push(addr);
     }
 
+    pub fn request_sync(&mut self, peer: &PeerId, request: SyncRequest) {
+        self.swarm.behaviour_mut().sync.send_request(peer, request);
+    }
+
+    pub fn request_snapshot(&mut self, peer: &PeerId, request: SnapshotRequest) {
+        self.swarm.behaviour_mut().snapshot.send_request(peer, request);
+    }
+
+    pub fn respond_snapshot(&mut self, channel: request_response::ResponseChannel<SnapshotResponse>, response: SnapshotResponse) {
+        if self.swarm.behaviour_mut().snapshot.send_response(channel, response).is_err() {
+            warn!("P2P Snapshot: peer went away before the response was sent");
+        }
+    }
+
+    pub fn respond_sync(&mut self, channel: request_response::ResponseChannel<SyncResponse>, response: SyncResponse) {
+        if self.swarm.behaviour_mut().sync.send_response(channel, response).is_err() {
+            warn!("P2P Sync: peer went away before the response was sent");
+        }
+    }
+
     pub fn get_connected_peers(&self) -> usize {
         self.peers.len()
     }
@@ -0,0 +1,350 @@
+use crate::core::Block;
+use crate::consensus::{CertificateError, CommitCertificate};
+use crate::state_tree::keccak;
+use crate::storage::ChainStorage;
+use serde::{Serialize, Deserialize};
+use parity_scale_codec::{Encode, Decode};
+use libp2p::PeerId;
+use std::fmt;
+use std::path::Path;
+use log::info;
+
+pub const SNAPSHOT_VERSION: u8 = 1;
+/// Target size of one chunk; a chunk is closed once its entries exceed it
+pub const CHUNK_TARGET_BYTES: usize = 1024 * 1024;
+/// How long a fast-syncing node waits for a peer to offer a snapshot before replaying from genesis
+pub const MANIFEST_WAIT_SECS: u64 = 10;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Describes a snapshot of the state after block `height`.
+///
+/// The state is split into chunks of sorted key/value pairs; each chunk is identified by
+/// the keccak hash of its SCALE encoding, and together they rebuild `state_root`.
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
+pub struct SnapshotManifest {
+    pub version: u8,
+    pub chain_id: u64,
+    pub height: u64,
+    pub state_root: String,
+    pub block: Block,
+    pub certificate: Option<CommitCertificate>,
+    pub chunks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct SnapshotChunk {
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    Io(String),
+    Corrupt(String),
+    UnsupportedVersion(u8),
+    HeightUnavailable { requested: u64, latest: u64 },
+    NotEmpty(u64),
+    ChunkMismatch(usize),
+    MissingChunks { expected: usize, found: usize },
+    BlockMismatch { expected: String, found: String },
+    StateRootMismatch { expected: String, found: String },
+    /// Neither a trusted block hash nor a commit certificate vouches for the snapshot block
+    Untrusted,
+    InvalidCertificate(CertificateError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "i/o error: {}", e),
+            SnapshotError::Corrupt(e) => write!(f, "corrupt snapshot: {}", e),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+            SnapshotError::HeightUnavailable { requested, latest } => write!(f, "state at height {} is not available, only the latest height {} can be exported", requested, latest),
+            SnapshotError::NotEmpty(h) => write!(f, "data dir already holds blocks up to height {}", h),
+            SnapshotError::ChunkMismatch(i) => write!(f, "chunk {} does not match its hash in the manifest", i),
+            SnapshotError::MissingChunks { expected, found } => write!(f, "expected {} chunks, got {}", expected, found),
+            SnapshotError::BlockMismatch { expected, found } => write!(f, "snapshot block {} does not match trusted hash {}", found, expected),
+            SnapshotError::StateRootMismatch { expected, found } => write!(f, "rebuilt state root {} does not match {}", found, expected),
+            SnapshotError::Untrusted => write!(f, "snapshot block has neither a trusted hash nor a commit certificate"),
+            SnapshotError::InvalidCertificate(e) => write!(f, "invalid snapshot certificate: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e.to_string())
+    }
+}
+
+impl SnapshotManifest {
+    pub fn verify_chunk(&self, index: usize, data: &[u8]) -> bool {
+        self.chunks.get(index).is_some_and(|hash| *hash == hex::encode(keccak(data)))
+    }
+}
+
+/// Capture the current state, which must be the state after block `height`, as a manifest and encoded chunks
+pub fn build(storage: &ChainStorage, height: u64) -> Result<(SnapshotManifest, Vec<Vec<u8>>), SnapshotError> {
+    let latest = storage.get_latest_height();
+    if height != latest {
+        return Err(SnapshotError::HeightUnavailable { requested: height, latest });
+    }
+    let block = storage.get_block(height).ok_or(SnapshotError::HeightUnavailable { requested: height, latest })?;
+
+    let mut chunks = vec![];
+    let mut current = SnapshotChunk { entries: vec![] };
+    let mut size = 0;
+    for (key, value) in storage.state_entries() {
+        size += key.len() + value.len();
+        current.entries.push((key, value));
+        if size >= CHUNK_TARGET_BYTES {
+            chunks.push(std::mem::replace(&mut current, SnapshotChunk { entries: vec![] }).encode());
+            size = 0;
+        }
+    }
+    if !current.entries.is_empty() {
+        chunks.push(current.encode());
+    }
+
+    let manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        chain_id: storage.get_chain_id(),
+        height,
+        state_root: storage.calculate_state_root(),
+        block,
+        certificate: storage.get_commit_certificate(height),
+        chunks: chunks.iter().map(|c| hex::encode(keccak(c))).collect(),
+    };
+    Ok((manifest, chunks))
+}
+
+fn chunk_path(dir: &Path, index: usize) -> std::path::PathBuf {
+    dir.join(format!("chunk_{:05}.bin", index))
+}
+
+/// Write a snapshot of the state after block `height` into `dir`
+pub fn export(storage: &ChainStorage, height: u64, dir: &Path) -> Result<SnapshotManifest, SnapshotError> {
+    let (manifest, chunks) = build(storage, height)?;
+    std::fs::create_dir_all(dir)?;
+    for (index, chunk) in chunks.iter().enumerate() {
+        std::fs::write(chunk_path(dir, index), chunk)?;
+    }
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+    std::fs::write(dir.join(MANIFEST_FILE), json)?;
+    info!("📦 Exported snapshot at height {} ({} chunks) to {}", height, chunks.len(), dir.display());
+    Ok(manifest)
+}
+
+pub fn read_manifest(dir: &Path) -> Result<SnapshotManifest, SnapshotError> {
+    let data = std::fs::read(dir.join(MANIFEST_FILE))?;
+    serde_json::from_slice(&data).map_err(|e| SnapshotError::Corrupt(e.to_string()))
+}
+
+pub fn read_chunk(dir: &Path, index: usize) -> Result<Vec<u8>, SnapshotError> {
+    Ok(std::fs::read(chunk_path(dir, index))?)
+}
+
+/// Most recent snapshot exported under `root`, one directory per height
+pub fn latest_snapshot(root: &Path) -> Option<(SnapshotManifest, std::path::PathBuf)> {
+    std::fs::read_dir(root).ok()?
+        .flatten()
+        .filter_map(|entry| read_manifest(&entry.path()).ok().map(|m| (m, entry.path())))
+        .max_by_key(|(m, _)| m.height)
+}
+
+/// Rebuild the state of `manifest` into `storage` from its chunks and make block
+/// `manifest.height` the local head. Nothing is written unless every check passes.
+///
+/// The snapshot block must either match `trusted_block_hash` or carry a commit certificate
+/// signed by the validator set `storage` was initialized with.
+pub fn restore(storage: &ChainStorage, manifest: &SnapshotManifest, chunks: &[Vec<u8>], trusted_block_hash: Option<&str>, authority_veto: bool) -> Result<(), SnapshotError> {
+    if manifest.version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(manifest.version));
+    }
+    let latest = storage.get_latest_height();
+    if latest > 0 {
+        return Err(SnapshotError::NotEmpty(latest));
+    }
+    let block_hash = manifest.block.hash();
+    match (trusted_block_hash, &manifest.certificate) {
+        (Some(trusted), _) if trusted.trim_start_matches("0x") != block_hash => {
+            return Err(SnapshotError::BlockMismatch { expected: trusted.to_string(), found: block_hash });
+        }
+        (Some(_), _) => {}
+        (None, Some(cert)) => {
+            let validator_set = storage.get_validator_set().ok_or(SnapshotError::Untrusted)?;
+            cert.verify(manifest.chain_id, manifest.height, &block_hash, &validator_set, authority_veto)
+                .map_err(SnapshotError::InvalidCertificate)?;
+        }
+        (None, None) => return Err(SnapshotError::Untrusted),
+    }
+    // The block commits to the state after it; genesis has no such commitment
+    if manifest.block.header.height != manifest.height || (manifest.height > 0 && manifest.block.header.state_root != manifest.state_root) {
+        return Err(SnapshotError::StateRootMismatch { expected: manifest.block.header.state_root.clone(), found: manifest.state_root.clone() });
+    }
+    if chunks.len() != manifest.chunks.len() {
+        return Err(SnapshotError::MissingChunks { expected: manifest.chunks.len(), found: chunks.len() });
+    }
+
+    let mut entries = vec![];
+    for (index, data) in chunks.iter().enumerate() {
+        if !manifest.verify_chunk(index, data) {
+            return Err(SnapshotError::ChunkMismatch(index));
+        }
+        let chunk = SnapshotChunk::decode(&mut &data[..]).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+        entries.extend(chunk.entries);
+    }
+
+    let state = storage.begin_block();
+    state.replace_state(&entries);
+    let root = state.calculate_state_root();
+    if root != manifest.state_root {
+        return Err(SnapshotError::StateRootMismatch { expected: manifest.state_root.clone(), found: root });
+    }
+    state.save_chain_id(manifest.chain_id);
+    state.save_block(&manifest.block);
+    if let Some(cert) = &manifest.certificate {
+        state.save_commit_certificate(cert);
+    }
+    state.commit_block();
+    info!("📦 Restored snapshot at height {} (state root {})", manifest.height, root);
+    Ok(())
+}
+
+/// Read a snapshot exported to `dir` and restore it into `storage`
+pub fn import(storage: &ChainStorage, dir: &Path, trusted_block_hash: Option<&str>, authority_veto: bool) -> Result<SnapshotManifest, SnapshotError> {
+    let manifest = read_manifest(dir)?;
+    let chunks = (0..manifest.chunks.len()).map(|i| read_chunk(dir, i)).collect::<Result<Vec<_>, _>>()?;
+    restore(storage, &manifest, &chunks, trusted_block_hash, authority_veto)?;
+    Ok(manifest)
+}
+
+/// Progress of downloading a snapshot from a peer at startup
+#[derive(Debug)]
+pub enum SnapshotSync {
+    /// Waiting for a peer to offer a snapshot ahead of us
+    Waiting { since: u64 },
+    Downloading { peer: PeerId, manifest: Box<SnapshotManifest>, chunks: Vec<Vec<u8>> },
+    /// Restored, or given up in favour of replaying blocks
+    Done,
+}
+
+impl SnapshotSync {
+    pub fn new(now: u64) -> Self {
+        SnapshotSync::Waiting { since: now }
+    }
+
+    /// Whether block sync should hold off because a snapshot may still arrive
+    pub fn is_pending(&mut self, now: u64) -> bool {
+        match self {
+            SnapshotSync::Waiting { since } if now >= *since + MANIFEST_WAIT_SECS => {
+                *self = SnapshotSync::Done;
+                false
+            }
+            SnapshotSync::Done => false,
+            _ => true,
+        }
+    }
+
+    /// Start downloading `manifest` from `peer` if it is usable; returns the index of the first chunk to fetch.
+    ///
+    /// Uncertified snapshots are never downloaded, since nothing else vouches for a peer's block.
+    pub fn on_manifest(&mut self, peer: PeerId, manifest: Box<SnapshotManifest>, chain_id: u64, local_height: u64) -> Option<u32> {
+        if !matches!(self, SnapshotSync::Waiting { .. }) || manifest.chain_id != chain_id || manifest.height <= local_height || manifest.version != SNAPSHOT_VERSION || manifest.certificate.is_none() {
+            return None;
+        }
+        info!("📦 Downloading snapshot at height {} ({} chunks) from {}", manifest.height, manifest.chunks.len(), peer);
+        *self = SnapshotSync::Downloading { peer, manifest, chunks: vec![] };
+        self.next_chunk()
+    }
+
+    /// Accept the next chunk from `peer`; returns the next chunk index to fetch, if any
+    pub fn on_chunk(&mut self, peer: &PeerId, data: Vec<u8>) -> Result<Option<u32>, SnapshotError> {
+        let SnapshotSync::Downloading { peer: source, manifest, chunks } = self else { return Ok(None) };
+        if source != peer {
+            return Ok(None);
+        }
+        if !manifest.verify_chunk(chunks.len(), &data) {
+            let index = chunks.len();
+            *self = SnapshotSync::Done;
+            return Err(SnapshotError::ChunkMismatch(index));
+        }
+        chunks.push(data);
+        Ok(self.next_chunk())
+    }
+
+    fn next_chunk(&self) -> Option<u32> {
+        match self {
+            SnapshotSync::Downloading { manifest, chunks, .. } if chunks.len() < manifest.chunks.len() => Some(chunks.len() as u32),
+            _ => None,
+        }
+    }
+
+    /// Take the fully downloaded snapshot, leaving `Done`
+    pub fn take_complete(&mut self) -> Option<(Box<SnapshotManifest>, Vec<Vec<u8>>)> {
+        if !matches!(self, SnapshotSync::Downloading { manifest, chunks, .. } if chunks.len() == manifest.chunks.len()) {
+            return None;
+        }
+        match std::mem::replace(self, SnapshotSync::Done) {
+            SnapshotSync::Downloading { manifest, chunks, .. } => Some((manifest, chunks)),
+            _ => None,
+        }
+    }
+
+    /// `peer` failed to serve the snapshot; fall back to replaying blocks
+    pub fn on_failure(&mut self, peer: &PeerId) {
+        if matches!(self, SnapshotSync::Downloading { peer: source, .. } if source == peer) {
+            *self = SnapshotSync::Done;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_download() {
+        let chunks = vec![b"first".to_vec(), b"second".to_vec()];
+        let manifest = Box::new(SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            chain_id: 7,
+            height: 5,
+            state_root: "".into(),
+            block: Block::new_genesis(7),
+            certificate: Some(CommitCertificate { height: 5, round: 0, block_hash: "".into(), precommits: vec![] }),
+            chunks: chunks.iter().map(|c| hex::encode(keccak(c))).collect(),
+        });
+        let (peer, other) = (PeerId::random(), PeerId::random());
+
+        let mut sync = SnapshotSync::new(100);
+        assert!(sync.is_pending(105));
+        // Snapshots of another chain, behind us or without a certificate are ignored
+        assert_eq!(sync.on_manifest(peer, manifest.clone(), 8, 0), None);
+        assert_eq!(sync.on_manifest(peer, manifest.clone(), 7, 5), None);
+        let mut uncertified = manifest.clone();
+        uncertified.certificate = None;
+        assert_eq!(sync.on_manifest(peer, uncertified, 7, 0), None);
+
+        assert_eq!(sync.on_manifest(peer, manifest.clone(), 7, 0), Some(0));
+        assert_eq!(sync.on_chunk(&other, chunks[0].clone()), Ok(None));
+        assert_eq!(sync.on_chunk(&peer, chunks[0].clone()), Ok(Some(1)));
+        assert!(sync.take_complete().is_none());
+        assert_eq!(sync.on_chunk(&peer, chunks[1].clone()), Ok(None));
+        let (_, downloaded) = sync.take_complete().unwrap();
+        assert_eq!(downloaded, chunks);
+        assert!(!sync.is_pending(105));
+
+        // A corrupt chunk ends the download
+        let mut sync = SnapshotSync::new(100);
+        sync.on_manifest(peer, manifest, 7, 0);
+        assert_eq!(sync.on_chunk(&peer, b"forged".to_vec()), Err(SnapshotError::ChunkMismatch(0)));
+        assert!(!sync.is_pending(105));
+
+        // Without an offer, block replay takes over after the wait
+        let mut sync = SnapshotSync::new(100);
+        assert!(!sync.is_pending(100 + MANIFEST_WAIT_SECS));
+    }
+}
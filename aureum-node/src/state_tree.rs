@@ -0,0 +1,280 @@
+use serde::{Serialize, Deserialize};
+use sha3::{Digest, Keccak256};
+use parity_scale_codec::{Encode, Decode};
+use std::collections::HashSet;
+
+pub type Hash = [u8; 32];
+
+/// Root of a tree with no entries
+pub const EMPTY_ROOT: Hash = [0u8; 32];
+
+/// Node of a compact sparse Merkle tree keyed by `keccak(key)`.
+///
+/// A subtree holding a single entry is stored as that entry's leaf, so the depth of the
+/// tree grows with log2 of the number of entries rather than with the key length.
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub enum Node {
+    Leaf { key: Hash, value: Hash },
+    Internal { left: Hash, right: Hash },
+}
+
+impl Node {
+    pub fn hash(&self) -> Hash {
+        let mut hasher = Keccak256::new();
+        match self {
+            Node::Leaf { key, value } => {
+                hasher.update([0u8]);
+                hasher.update(key);
+                hasher.update(value);
+            }
+            Node::Internal { left, right } => {
+                hasher.update([1u8]);
+                hasher.update(left);
+                hasher.update(right);
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// Content-addressed node storage backing the tree
+pub trait NodeStore {
+    fn get_node(&self, hash: &Hash) -> Option<Node>;
+    fn put_node(&self, node: &Node) -> Hash;
+}
+
+pub fn keccak(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+fn bit(key: &Hash, depth: usize) -> u8 {
+    (key[depth / 8] >> (7 - depth % 8)) & 1
+}
+
+/// Set (`Some`) or remove (`None`) the entry for `key`, returning the new root.
+/// Nodes of the previous root are left in place so older roots stay provable.
+pub fn update(store: &impl NodeStore, root: Hash, key: &[u8], value: Option<&[u8]>) -> Hash {
+    update_at(store, root, 0, &keccak(key), value.map(keccak))
+}
+
+fn update_at(store: &impl NodeStore, node: Hash, depth: usize, key: &Hash, value: Option<Hash>) -> Hash {
+    if node == EMPTY_ROOT {
+        return match value {
+            Some(value) => store.put_node(&Node::Leaf { key: *key, value }),
+            None => EMPTY_ROOT,
+        };
+    }
+
+    match store.get_node(&node).expect("State tree node missing") {
+        Node::Leaf { key: existing, value: old } => {
+            if existing == *key {
+                return match value {
+                    Some(value) if value == old => node,
+                    Some(value) => store.put_node(&Node::Leaf { key: *key, value }),
+                    None => EMPTY_ROOT,
+                };
+            }
+            match value {
+                Some(value) => {
+                    let leaf = store.put_node(&Node::Leaf { key: *key, value });
+                    split(store, (node, &existing), (leaf, key), depth)
+                }
+                // Removing a key that is not present
+                None => node,
+            }
+        }
+        Node::Internal { left, right } => {
+            let (left, right) = if bit(key, depth) == 0 {
+                (update_at(store, left, depth + 1, key, value), right)
+            } else {
+                (left, update_at(store, right, depth + 1, key, value))
+            };
+            join(store, left, right)
+        }
+    }
+}
+
+/// Add the hash of every node reachable from `root` to `live`.
+/// Subtrees already in `live` are shared with an earlier root and not walked again.
+pub fn mark_reachable(store: &impl NodeStore, root: Hash, live: &mut HashSet<Hash>) {
+    let mut pending = vec![root];
+    while let Some(node) = pending.pop() {
+        if node == EMPTY_ROOT || !live.insert(node) {
+            continue;
+        }
+        if let Some(Node::Internal { left, right }) = store.get_node(&node) {
+            pending.extend([left, right]);
+        }
+    }
+}
+
+/// Internal nodes separating two leaves down to the first bit where their keys differ
+fn split(store: &impl NodeStore, a: (Hash, &Hash), b: (Hash, &Hash), depth: usize) -> Hash {
+    match (bit(a.1, depth), bit(b.1, depth)) {
+        (0, 0) => {
+            let left = split(store, a, b, depth + 1);
+            store.put_node(&Node::Internal { left, right: EMPTY_ROOT })
+        }
+        (1, 1) => {
+            let right = split(store, a, b, depth + 1);
+            store.put_node(&Node::Internal { left: EMPTY_ROOT, right })
+        }
+        (0, _) => store.put_node(&Node::Internal { left: a.0, right: b.0 }),
+        _ => store.put_node(&Node::Internal { left: b.0, right: a.0 }),
+    }
+}
+
+/// Rebuild a parent after a child changed, keeping the tree canonical: a lone leaf moves up
+fn join(store: &impl NodeStore, left: Hash, right: Hash) -> Hash {
+    let is_leaf = |h: &Hash| matches!(store.get_node(h), Some(Node::Leaf { .. }));
+    match (left == EMPTY_ROOT, right == EMPTY_ROOT) {
+        (true, true) => EMPTY_ROOT,
+        (true, false) if is_leaf(&right) => right,
+        (false, true) if is_leaf(&left) => left,
+        _ => store.put_node(&Node::Internal { left, right }),
+    }
+}
+
+/// Leaf reached at the end of a proof path
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProofLeaf {
+    pub key_hash: String,
+    pub value_hash: String,
+}
+
+/// Merkle path for `key`, from the root down to where the key is or would be
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StateProof {
+    pub key: String,
+    pub leaf: Option<ProofLeaf>,
+    /// Sibling hashes, ordered from the root downwards
+    pub siblings: Vec<String>,
+}
+
+pub fn prove(store: &impl NodeStore, root: Hash, key: &str) -> StateProof {
+    let key_hash = keccak(key.as_bytes());
+    let mut siblings = vec![];
+    let mut node = root;
+    let mut depth = 0;
+
+    let leaf = loop {
+        if node == EMPTY_ROOT {
+            break None;
+        }
+        match store.get_node(&node).expect("State tree node missing") {
+            Node::Leaf { key, value } => break Some(ProofLeaf { key_hash: hex::encode(key), value_hash: hex::encode(value) }),
+            Node::Internal { left, right } => {
+                let (next, sibling) = if bit(&key_hash, depth) == 0 { (left, right) } else { (right, left) };
+                siblings.push(hex::encode(sibling));
+                node = next;
+                depth += 1;
+            }
+        }
+    };
+    StateProof { key: key.to_string(), leaf, siblings }
+}
+
+fn decode_hash(s: &str) -> Option<Hash> {
+    hex::decode(s.trim_start_matches("0x")).ok()?.try_into().ok()
+}
+
+impl StateProof {
+    /// Check that under `root` the key holds `value`, or is absent when `value` is `None`
+    pub fn verify(&self, root: &str, value: Option<&[u8]>) -> bool {
+        let (Some(root), Some(siblings)) = (decode_hash(root), self.siblings.iter().map(|s| decode_hash(s)).collect::<Option<Vec<_>>>()) else {
+            return false;
+        };
+        let key_hash = keccak(self.key.as_bytes());
+
+        let leaf = match &self.leaf {
+            Some(leaf) => match (decode_hash(&leaf.key_hash), decode_hash(&leaf.value_hash)) {
+                (Some(key), Some(value)) => Some((key, value)),
+                _ => return false,
+            },
+            None => None,
+        };
+        let mut current = match (leaf, value) {
+            (Some((key, hash)), Some(value)) if key == key_hash && hash == keccak(value) => Node::Leaf { key, value: hash }.hash(),
+            // Absence: the path ends at an empty subtree or at another key's leaf
+            (Some((key, hash)), None) if key != key_hash => Node::Leaf { key, value: hash }.hash(),
+            (None, None) => EMPTY_ROOT,
+            _ => return false,
+        };
+
+        for (depth, sibling) in siblings.iter().enumerate().rev() {
+            current = if bit(&key_hash, depth) == 0 {
+                Node::Internal { left: current, right: *sibling }.hash()
+            } else {
+                Node::Internal { left: *sibling, right: current }.hash()
+            };
+        }
+        current == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemStore(RefCell<HashMap<Hash, Node>>);
+
+    impl NodeStore for MemStore {
+        fn get_node(&self, hash: &Hash) -> Option<Node> {
+            self.0.borrow().get(hash).cloned()
+        }
+
+        fn put_node(&self, node: &Node) -> Hash {
+            let hash = node.hash();
+            self.0.borrow_mut().insert(hash, node.clone());
+            hash
+        }
+    }
+
+    fn build(store: &MemStore, entries: &[(&str, &str)]) -> Hash {
+        entries.iter().fold(EMPTY_ROOT, |root, (k, v)| update(store, root, k.as_bytes(), Some(v.as_bytes())))
+    }
+
+    #[test]
+    fn test_root_is_order_independent() {
+        let entries = [("balance:a", "1"), ("balance:b", "2"), ("nonce:a", "3"), ("escrow:x", "4")];
+        let store = MemStore::default();
+        let forward = build(&store, &entries);
+        let mut reversed = entries;
+        reversed.reverse();
+        assert_eq!(forward, build(&store, &reversed));
+
+        // Removing an entry gives the root of the tree that never had it
+        let removed = update(&store, forward, b"nonce:a", None);
+        assert_eq!(removed, build(&store, &[("balance:a", "1"), ("balance:b", "2"), ("escrow:x", "4")]));
+        let all_removed = entries.iter().fold(forward, |root, (k, _)| update(&store, root, k.as_bytes(), None));
+        assert_eq!(all_removed, EMPTY_ROOT);
+    }
+
+    #[test]
+    fn test_inclusion_and_absence_proofs() {
+        let store = MemStore::default();
+        let entries: Vec<(String, String)> = (0..50).map(|i| (format!("balance:{}", i), i.to_string())).collect();
+        let root = entries.iter().fold(EMPTY_ROOT, |root, (k, v)| update(&store, root, k.as_bytes(), Some(v.as_bytes())));
+        let root_hex = hex::encode(root);
+
+        let proof = prove(&store, root, "balance:7");
+        assert!(proof.verify(&root_hex, Some(b"7")));
+        assert!(!proof.verify(&root_hex, Some(b"8")));
+        assert!(!proof.verify(&root_hex, None));
+        // Path length stays logarithmic in the number of entries
+        assert!(proof.siblings.len() < 16);
+
+        let missing = prove(&store, root, "balance:missing");
+        assert!(missing.verify(&root_hex, None));
+        assert!(!missing.verify(&root_hex, Some(b"0")));
+
+        // Proofs are bound to the root they were produced for
+        let updated = update(&store, root, b"balance:7", Some(b"70"));
+        assert!(!proof.verify(&hex::encode(updated), Some(b"7")));
+        assert!(prove(&store, updated, "balance:7").verify(&hex::encode(updated), Some(b"70")));
+        assert!(prove(&store, root, "balance:7").verify(&root_hex, Some(b"7")));
+    }
+}
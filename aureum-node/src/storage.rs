@@ -1,34 +1,235 @@
 use sled::Db;
-use crate::core::Block;
+use crate::core::{Block, TransactionType};
 use crate::compliance::ComplianceProfile;
+use crate::execution::{Receipt, ReceiptStatus};
+use crate::state_tree::{self, Hash, Node, NodeStore, StateProof, EMPTY_ROOT};
+use serde::{Serialize, Deserialize};
 use parity_scale_codec::{Encode, Decode};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashSet};
+use log::info;
+use std::sync::{Arc, Mutex};
+
+/// Secondary index entry pointing at an included transaction
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
+pub struct TxIndexEntry {
+    pub tx_hash: String,
+    pub block_height: u64,
+    pub index: u32,
+    pub tx_type: String,
+    pub sent: bool,
+    pub received: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TxDirection {
+    #[default]
+    All,
+    Sent,
+    Received,
+}
+
+/// Page request for an address history query
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TxQuery {
+    pub direction: TxDirection,
+    pub tx_type: Option<String>,
+    pub limit: usize,
+    /// Position `(block_height, index)` of the last entry of the previous page
+    pub cursor: Option<(u64, u32)>,
+    pub newest_first: bool,
+}
+
+impl Default for TxQuery {
+    fn default() -> Self {
+        Self { direction: TxDirection::All, tx_type: None, limit: 20, cursor: None, newest_first: true }
+    }
+}
+
+fn index_key(prefix: &str, id: &str, height: u64, index: u32) -> String {
+    // Fixed-width hex keeps the keys of one id sorted by (height, index)
+    format!("{}:{}:{:016x}:{:08x}", prefix, id, height, index)
+}
+
+/// Key prefixes committed to by the state root
+const STATE_PREFIXES: [&str; 12] = ["balance:", "nonce:", "property:", "visa:", "compliance:", "oracle_price:", "escrow:", "multisig:", "code:", "storage:", "validators:", "state:"];
+
+/// Recent blocks whose state roots stay provable. Every this many blocks, state tree nodes
+/// only reachable from older roots are deleted along with those roots.
+pub const STATE_HISTORY_BLOCKS: u64 = 1_000;
+
+/// Bumped whenever `STATE_PREFIXES` changes, so existing data dirs rebuild their state tree
+const STATE_TREE_VERSION: u8 = 2;
+
+fn is_state_key(key: &[u8]) -> bool {
+    STATE_PREFIXES.iter().any(|p| key.starts_with(p.as_bytes()))
+}
+
+type Writes = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Writes buffered on top of the database while a block executes.
+///
+/// Changes of the running transaction live in their own layer so they can be reverted on
+/// failure; everything is written to sled in one batch when the block commits.
+#[derive(Default)]
+pub struct StateOverlay {
+    block: Writes,
+    tx: Writes,
+}
+
+impl StateOverlay {
+    /// `Some(None)` means the key was deleted in the overlay
+    fn get(&self, key: &[u8]) -> Option<Option<Vec<u8>>> {
+        self.tx.get(key).or_else(|| self.block.get(key)).cloned()
+    }
+
+    fn put(&mut self, key: &[u8], value: Option<Vec<u8>>) {
+        self.tx.insert(key.to_vec(), value);
+    }
+
+    fn commit_tx(&mut self) {
+        let tx = std::mem::take(&mut self.tx);
+        self.block.extend(tx);
+    }
+
+    fn revert_tx(&mut self) {
+        self.tx.clear();
+    }
+
+    fn into_batch(mut self) -> sled::Batch {
+        self.commit_tx();
+        let mut batch = sled::Batch::default();
+        for (key, value) in self.block {
+            match value {
+                Some(v) => batch.insert(key, v),
+                None => batch.remove(key),
+            }
+        }
+        batch
+    }
+}
 
 pub struct ChainStorage {
     db: Arc<Db>,
+    /// Journal of pending mempool transactions, kept apart from chain state
+    mempool: sled::Tree,
+    /// Set on handles returned by `begin_block`
+    overlay: Option<Arc<Mutex<StateOverlay>>>,
 }
 
 impl ChainStorage {
     pub fn new(path: &str) -> Self {
         let db = sled::open(path).expect("Failed to open Sled DB");
-        Self { db: Arc::new(db) }
+        let mempool = db.open_tree("mempool").expect("Failed to open mempool tree");
+        let storage = Self { db: Arc::new(db), mempool, overlay: None };
+        storage.migrate_state_tree();
+        storage
+    }
+
+    // --- Block Overlay ---
+
+    /// Handle on the same database whose writes are buffered until `commit_block`.
+    ///
+    /// Reads through the handle see the buffered writes; other handles keep seeing the
+    /// last committed state. Dropping the handle without committing discards the block.
+    pub fn begin_block(&self) -> ChainStorage {
+        Self { db: self.db.clone(), mempool: self.mempool.clone(), overlay: Some(Arc::new(Mutex::new(StateOverlay::default()))) }
+    }
+
+    /// Keep the writes of the current transaction as part of the block
+    pub fn commit_tx(&self) {
+        if let Some(overlay) = &self.overlay {
+            overlay.lock().unwrap().commit_tx();
+        }
+    }
+
+    /// Drop the writes of the current transaction
+    pub fn revert_tx(&self) {
+        if let Some(overlay) = &self.overlay {
+            overlay.lock().unwrap().revert_tx();
+        }
+    }
+
+    /// Atomically write every buffered change to disk
+    pub fn commit_block(&self) {
+        let Some(overlay) = &self.overlay else { return };
+        let height = self.get_latest_height();
+        self.record_state_root(height);
+        let batch = std::mem::take(&mut *overlay.lock().unwrap()).into_batch();
+        self.db.apply_batch(batch).expect("Failed to commit block");
+        if height > 0 && height.is_multiple_of(STATE_HISTORY_BLOCKS) {
+            let keep_from = height + 1 - STATE_HISTORY_BLOCKS;
+            let pruned = self.prune_state_tree(keep_from);
+            info!("🧹 Pruned {} state tree nodes only reachable before block {}", pruned, keep_from);
+        }
+        self.flush();
+    }
+
+    fn get_raw(&self, key: impl AsRef<[u8]>) -> sled::Result<Option<sled::IVec>> {
+        if let Some(overlay) = &self.overlay
+            && let Some(value) = overlay.lock().unwrap().get(key.as_ref())
+        {
+            return Ok(value.map(sled::IVec::from));
+        }
+        self.db.get(key)
+    }
+
+    fn put_raw(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> sled::Result<()> {
+        match &self.overlay {
+            Some(overlay) => overlay.lock().unwrap().put(key.as_ref(), Some(value.as_ref().to_vec())),
+            None => { self.db.insert(key.as_ref(), value.as_ref())?; }
+        }
+        if is_state_key(key.as_ref()) {
+            self.update_state_tree(key.as_ref(), Some(value.as_ref()));
+        }
+        Ok(())
+    }
+
+    fn delete_raw(&self, key: impl AsRef<[u8]>) -> sled::Result<()> {
+        match &self.overlay {
+            Some(overlay) => overlay.lock().unwrap().put(key.as_ref(), None),
+            None => { self.db.remove(key.as_ref())?; }
+        }
+        if is_state_key(key.as_ref()) {
+            self.update_state_tree(key.as_ref(), None);
+        }
+        Ok(())
+    }
+
+    fn scan_raw(&self, prefix: impl AsRef<[u8]>) -> Box<dyn Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>>> {
+        let Some(overlay) = &self.overlay else {
+            return Box::new(self.db.scan_prefix(prefix));
+        };
+        let prefix = prefix.as_ref();
+        let mut merged: BTreeMap<Vec<u8>, Option<Vec<u8>>> = self.db.scan_prefix(prefix)
+            .flatten()
+            .map(|(k, v)| (k.to_vec(), Some(v.to_vec())))
+            .collect();
+        let overlay = overlay.lock().unwrap();
+        for layer in [&overlay.block, &overlay.tx] {
+            for (k, v) in layer.range(prefix.to_vec()..).take_while(|(k, _)| k.starts_with(prefix)) {
+                merged.insert(k.clone(), v.clone());
+            }
+        }
+        Box::new(merged.into_iter().filter_map(|(k, v)| v.map(|v| Ok((k.into(), v.into())))))
     }
 
     pub fn save_block(&self, block: &Block) {
         let key = format!("block:{}", block.header.height);
         let encoded = block.encode();
-        self.db.insert(key.as_bytes(), encoded).expect("Failed to save block");
+        self.put_raw(key.as_bytes(), encoded).expect("Failed to save block");
         
         // Save block hash for lookup
         let hash = block.hash();
-        self.db.insert(format!("hash:{}", hash).as_bytes(), &block.header.height.to_be_bytes()).expect("Failed to save hash index");
+        self.put_raw(format!("hash:{}", hash).as_bytes(), block.header.height.to_be_bytes()).expect("Failed to save hash index");
         
         // Update latest height
-        self.db.insert(b"height:latest", &block.header.height.to_be_bytes()).expect("Failed to update latest height");
+        self.put_raw(b"height:latest", block.header.height.to_be_bytes()).expect("Failed to update latest height");
     }
 
     pub fn get_latest_height(&self) -> u64 {
-        match self.db.get(b"height:latest").ok().flatten() {
+        match self.get_raw(b"height:latest").ok().flatten() {
             Some(data) => {
                 let mut bytes = [0u8; 8];
                 bytes.copy_from_slice(&data);
@@ -40,17 +241,27 @@ impl ChainStorage {
 
     pub fn get_block(&self, height: u64) -> Option<Block> {
         let key = format!("block:{}", height);
-        self.db.get(key.as_bytes()).ok()?.and_then(|data| Block::decode(&mut &data[..]).ok())
+        self.get_raw(key.as_bytes()).ok()?.and_then(|data| Block::decode(&mut &data[..]).ok())
+    }
+
+    /// Store the certificate finalizing the block at its height; written in the same batch as the block
+    pub fn save_commit_certificate(&self, cert: &crate::consensus::CommitCertificate) {
+        let encoded = cert.encode();
+        self.put_raw(format!("commit:{}", cert.height).as_bytes(), encoded).expect("Failed to save commit certificate");
+    }
+
+    pub fn get_commit_certificate(&self, height: u64) -> Option<crate::consensus::CommitCertificate> {
+        self.get_raw(format!("commit:{}", height).as_bytes()).ok()?.and_then(|data| crate::consensus::CommitCertificate::decode(&mut &data[..]).ok())
     }
 
     pub fn update_balance(&self, address: &str, amount: u64) {
         let key = format!("balance:{}", address);
-        self.db.insert(key.as_bytes(), &amount.to_be_bytes()).expect("Failed to update balance");
+        self.put_raw(key.as_bytes(), amount.to_be_bytes()).expect("Failed to update balance");
     }
 
     pub fn get_balance(&self, address: &str) -> u64 {
         let key = format!("balance:{}", address);
-        match self.db.get(key.as_bytes()).ok().flatten() {
+        match self.get_raw(key.as_bytes()).ok().flatten() {
             Some(data) => {
                 let mut bytes = [0u8; 8];
                 bytes.copy_from_slice(&data);
@@ -62,38 +273,128 @@ impl ChainStorage {
 
     pub fn save_validator_set(&self, set: &crate::core::ValidatorSet) {
         let encoded = set.encode();
-        self.db.insert(b"validators:current", encoded).expect("Failed to save validator set");
+        self.put_raw(b"validators:current", encoded).expect("Failed to save validator set");
     }
 
     pub fn get_validator_set(&self) -> Option<crate::core::ValidatorSet> {
-        self.db.get(b"validators:current").ok()?.and_then(|data| crate::core::ValidatorSet::decode(&mut &data[..]).ok())
+        self.get_raw(b"validators:current").ok()?.and_then(|data| crate::core::ValidatorSet::decode(&mut &data[..]).ok())
+    }
+
+    /// Remember that `validator` was punished for equivocating at `height`, so the same evidence cannot slash twice
+    pub fn record_equivocation(&self, validator: &str, height: u64) {
+        self.put_raw(format!("validators:evidence:{}:{}", validator, height).as_bytes(), [1u8]).expect("Failed to save evidence");
+    }
+
+    pub fn has_equivocation(&self, validator: &str, height: u64) -> bool {
+        self.get_raw(format!("validators:evidence:{}:{}", validator, height).as_bytes()).ok().flatten().is_some()
+    }
+
+    pub fn save_liveness(&self, validator: &str, record: &crate::consensus::LivenessRecord) {
+        self.put_raw(format!("validators:liveness:{}", validator).as_bytes(), record.encode()).expect("Failed to save liveness record");
+    }
+
+    pub fn get_liveness(&self, validator: &str) -> Option<crate::consensus::LivenessRecord> {
+        self.get_raw(format!("validators:liveness:{}", validator).as_bytes()).ok()?.and_then(|data| crate::consensus::LivenessRecord::decode(&mut &data[..]).ok())
+    }
+
+    pub fn delete_liveness(&self, validator: &str) {
+        self.delete_raw(format!("validators:liveness:{}", validator).as_bytes()).expect("Failed to delete liveness record");
+    }
+
+    /// Validator set the latest block was produced under, which signed its commit certificate
+    pub fn save_previous_validator_set(&self, set: &crate::core::ValidatorSet) {
+        self.put_raw(b"validators:previous", set.encode()).expect("Failed to save validator set");
+    }
+
+    pub fn get_previous_validator_set(&self) -> Option<crate::core::ValidatorSet> {
+        self.get_raw(b"validators:previous").ok()?.and_then(|data| crate::core::ValidatorSet::decode(&mut &data[..]).ok())
+    }
+
+    /// Delegations bonded to `validator`, in the order they were first made
+    pub fn get_delegations(&self, validator: &str) -> Vec<crate::core::Delegation> {
+        self.get_raw(format!("validators:delegations:{}", validator).as_bytes()).ok().flatten()
+            .and_then(|data| Vec::<crate::core::Delegation>::decode(&mut &data[..]).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save_delegations(&self, validator: &str, delegations: &[crate::core::Delegation]) {
+        self.put_raw(format!("validators:delegations:{}", validator).as_bytes(), delegations.encode()).expect("Failed to save delegations");
+    }
+
+    /// Unstaked AUR waiting out the unbonding period, in the order it was unstaked
+    pub fn get_unbonding_queue(&self) -> Vec<crate::consensus::UnbondingEntry> {
+        self.get_raw(b"validators:unbonding").ok().flatten()
+            .and_then(|data| Vec::<crate::consensus::UnbondingEntry>::decode(&mut &data[..]).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save_unbonding_queue(&self, queue: &[crate::consensus::UnbondingEntry]) {
+        self.put_raw(b"validators:unbonding", queue.encode()).expect("Failed to save unbonding queue");
+    }
+
+    /// Redelegations still slashable for the validator they left, in the order they were made
+    pub fn get_redelegations(&self) -> Vec<crate::consensus::Redelegation> {
+        self.get_raw(b"validators:redelegations").ok().flatten()
+            .and_then(|data| Vec::<crate::consensus::Redelegation>::decode(&mut &data[..]).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save_redelegations(&self, redelegations: &[crate::consensus::Redelegation]) {
+        self.put_raw(b"validators:redelegations", redelegations.encode()).expect("Failed to save redelegations");
+    }
+
+    pub fn save_slashing_params(&self, params: &crate::consensus::SlashingParams) {
+        self.put_raw(b"state:slashing", params.encode()).expect("Failed to save slashing params");
+    }
+
+    /// Downtime rules set at genesis; chains initialized without them use the defaults
+    pub fn get_slashing_params(&self) -> crate::consensus::SlashingParams {
+        self.get_raw(b"state:slashing").ok().flatten()
+            .and_then(|data| crate::consensus::SlashingParams::decode(&mut &data[..]).ok())
+            .unwrap_or_default()
     }
 
     pub fn save_chain_state(&self, state: &crate::core::ChainState) {
         let encoded = state.encode();
-        self.db.insert(b"state:global", encoded).expect("Failed to save chain state");
+        self.put_raw(b"state:global", encoded).expect("Failed to save chain state");
+    }
+
+    pub fn save_chain_id(&self, chain_id: u64) {
+        self.put_raw(b"chain:id", chain_id.to_be_bytes()).expect("Failed to save chain id");
+    }
+
+    /// Chain id fixed at genesis; data dirs created before chain ids existed run on the default
+    pub fn get_chain_id(&self) -> u64 {
+        match self.get_raw(b"chain:id").ok().flatten() {
+            Some(data) => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&data);
+                u64::from_be_bytes(bytes)
+            },
+            None => crate::core::DEFAULT_CHAIN_ID,
+        }
     }
 
     pub fn get_chain_state(&self) -> Option<crate::core::ChainState> {
-        self.db.get(b"state:global").ok()?.and_then(|data| crate::core::ChainState::decode(&mut &data[..]).ok())
+        self.get_raw(b"state:global").ok()?.and_then(|data| crate::core::ChainState::decode(&mut &data[..]).ok())
     }
 
     // --- EVM State Persistence ---
 
     pub fn get_account_code(&self, address: [u8; 20]) -> Vec<u8> {
-        self.db.get(format!("code:{:x?}", address).as_bytes())
+        self.get_raw(format!("code:{:x?}", address).as_bytes())
             .ok().flatten()
             .map(|v| v.to_vec())
             .unwrap_or_default()
     }
 
     pub fn save_account_code(&self, address: [u8; 20], code: Vec<u8>) {
-        self.db.insert(format!("code:{:x?}", address).as_bytes(), code).expect("Sled error");
+        self.put_raw(format!("code:{:x?}", address).as_bytes(), code).expect("Sled error");
     }
 
     pub fn get_storage_slot(&self, address: [u8; 20], slot: [u8; 32]) -> [u8; 32] {
         let key = format!("storage:{:x?}:{:x?}", address, slot);
-        match self.db.get(key.as_bytes()).ok().flatten() {
+        match self.get_raw(key.as_bytes()).ok().flatten() {
             Some(data) => {
                 let mut res = [0u8; 32];
                 res.copy_from_slice(&data);
@@ -105,18 +406,18 @@ impl ChainStorage {
 
     pub fn save_storage_slot(&self, address: [u8; 20], slot: [u8; 32], value: [u8; 32]) {
         let key = format!("storage:{:x?}:{:x?}", address, slot);
-        self.db.insert(key.as_bytes(), &value[..]).expect("Sled error");
+        self.put_raw(key.as_bytes(), &value[..]).expect("Sled error");
     }
 
     // --- Property Registry ---
 
     pub fn save_property(&self, property: &crate::core::Property) {
         let encoded = property.encode();
-        self.db.insert(format!("property:{}", property.id).as_bytes(), encoded).expect("Failed to save property");
+        self.put_raw(format!("property:{}", property.id).as_bytes(), encoded).expect("Failed to save property");
     }
 
     pub fn get_property(&self, id: &str) -> Option<crate::core::Property> {
-        self.db.get(format!("property:{}", id).as_bytes()).ok()?.and_then(|data| crate::core::Property::decode(&mut &data[..]).ok())
+        self.get_raw(format!("property:{}", id).as_bytes()).ok()?.and_then(|data| crate::core::Property::decode(&mut &data[..]).ok())
     }
 
     // --- Golden Visa Applications ---
@@ -124,32 +425,32 @@ impl ChainStorage {
     pub fn save_visa_application(&self, app: &crate::core::VisaApplication) {
         let encoded = app.encode();
         // Indexed by applicant because a user usually tracks their own visa status
-        self.db.insert(format!("visa:{}", app.applicant).as_bytes(), encoded).expect("Failed to save visa application");
+        self.put_raw(format!("visa:{}", app.applicant).as_bytes(), encoded).expect("Failed to save visa application");
     }
 
     pub fn get_visa_application(&self, applicant: &str) -> Option<crate::core::VisaApplication> {
-        self.db.get(format!("visa:{}", applicant).as_bytes()).ok()?.and_then(|data| crate::core::VisaApplication::decode(&mut &data[..]).ok())
+        self.get_raw(format!("visa:{}", applicant).as_bytes()).ok()?.and_then(|data| crate::core::VisaApplication::decode(&mut &data[..]).ok())
     }
 
     // --- Compliance Persistence ---
 
     pub fn save_compliance_profile(&self, profile: &ComplianceProfile) {
         let encoded = profile.encode();
-        self.db.insert(format!("compliance:{}", profile.address).as_bytes(), encoded).expect("Failed to save compliance profile");
+        self.put_raw(format!("compliance:{}", profile.address).as_bytes(), encoded).expect("Failed to save compliance profile");
     }
 
     pub fn get_compliance_profile(&self, address: &str) -> Option<ComplianceProfile> {
-        self.db.get(format!("compliance:{}", address).as_bytes()).ok()?.and_then(|data| ComplianceProfile::decode(&mut &data[..]).ok())
+        self.get_raw(format!("compliance:{}", address).as_bytes()).ok()?.and_then(|data| ComplianceProfile::decode(&mut &data[..]).ok())
     }
 
     // --- Oracle Persistence ---
 
     pub fn save_oracle_price(&self, asset_id: &str, price: u64) {
-        self.db.insert(format!("oracle_price:{}", asset_id).as_bytes(), &price.to_be_bytes()).expect("Failed to save oracle price");
+        self.put_raw(format!("oracle_price:{}", asset_id).as_bytes(), price.to_be_bytes()).expect("Failed to save oracle price");
     }
 
     pub fn get_oracle_price(&self, asset_id: &str) -> Option<u64> {
-        match self.db.get(format!("oracle_price:{}", asset_id).as_bytes()).ok().flatten() {
+        match self.get_raw(format!("oracle_price:{}", asset_id).as_bytes()).ok().flatten() {
             Some(data) => {
                 let mut bytes = [0u8; 8];
                 bytes.copy_from_slice(&data);
@@ -159,38 +460,120 @@ impl ChainStorage {
         }
     }
 
-    /// Calculate Deterministic State Root
+    // --- State Tree ---
+
+    fn state_tree_root(&self) -> Hash {
+        self.get_raw(b"smt:root").ok().flatten()
+            .and_then(|data| data.as_ref().try_into().ok())
+            .unwrap_or(EMPTY_ROOT)
+    }
+
+    fn update_state_tree(&self, key: &[u8], value: Option<&[u8]>) {
+        let root = state_tree::update(self, self.state_tree_root(), key, value);
+        self.put_raw(b"smt:root", root).expect("Failed to update state root");
+    }
+
+    /// Build the state tree for data dirs created before it existed or before the current set of state prefixes
+    fn migrate_state_tree(&self) {
+        let version = self.db.get(b"smt:version").ok().flatten().and_then(|v| v.first().copied());
+        if version == Some(STATE_TREE_VERSION) && self.db.contains_key(b"smt:root").unwrap_or(true) {
+            return;
+        }
+        self.db.remove(b"smt:root").expect("Failed to reset state root");
+        for prefix in STATE_PREFIXES {
+            for (k, v) in self.db.scan_prefix(prefix).flatten() {
+                self.update_state_tree(&k, Some(&v));
+            }
+        }
+        self.db.insert(b"smt:version", &[STATE_TREE_VERSION]).expect("Failed to record state tree version");
+    }
+
+    /// Root of the state tree over all state prefixes
     pub fn calculate_state_root(&self) -> String {
-        use sha3::{Digest, Keccak256};
-        let mut hasher = Keccak256::new();
-        
-        let mut items = Vec::new();
-        
-        // Scan all state-relevant prefixes
-        for prefix in &[&b"balance:"[..], &b"property:"[..], &b"nonce:"[..], &b"compliance:"[..], &b"oracle_price:"[..], &b"escrow:"[..]] {
-            for item in self.db.scan_prefix(*prefix) {
-                if let Ok((k, v)) = item {
-                    items.push((k.to_vec(), v.to_vec()));
-                }
+        hex::encode(self.state_tree_root())
+    }
+
+    /// Remember the current state root as the one reached after block `height`
+    pub fn record_state_root(&self, height: u64) {
+        self.put_raw(format!("state_root:{}", height).as_bytes(), self.state_tree_root()).expect("Failed to record state root");
+    }
+
+    /// Delete the state roots recorded before block `keep_from`, and every committed state tree
+    /// node no longer reachable from the current root or a root still recorded.
+    /// Returns the number of nodes deleted.
+    pub fn prune_state_tree(&self, keep_from: u64) -> usize {
+        let mut batch = sled::Batch::default();
+        let mut live = HashSet::new();
+        state_tree::mark_reachable(self, self.state_tree_root(), &mut live);
+        for (key, root) in self.db.scan_prefix(b"state_root:").flatten() {
+            let height = std::str::from_utf8(&key["state_root:".len()..]).ok().and_then(|h| h.parse::<u64>().ok());
+            match (height, root.as_ref().try_into()) {
+                (Some(height), _) if height < keep_from => batch.remove(key),
+                (_, Ok(root)) => state_tree::mark_reachable(self, root, &mut live),
+                _ => {}
             }
         }
-        
-        // Must sort for determinism
-        items.sort_by(|a, b| a.0.cmp(&b.0));
 
-        for (k, v) in items {
-            hasher.update(&k);
-            hasher.update(&v);
+        let mut pruned = 0;
+        for key in self.db.scan_prefix(b"smt:").keys().flatten() {
+            // `smt:root` and `smt:version` are not nodes
+            let Some(hash) = hex::decode(&key[b"smt:".len()..]).ok().and_then(|h| Hash::try_from(h).ok()) else { continue };
+            if !live.contains(&hash) {
+                batch.remove(key);
+                pruned += 1;
+            }
+        }
+        self.db.apply_batch(batch).expect("Failed to prune state tree");
+        pruned
+    }
+
+    pub fn get_state_root_at(&self, height: u64) -> Option<String> {
+        self.get_raw(format!("state_root:{}", height).as_bytes()).ok()?.map(hex::encode)
+    }
+
+    /// Merkle proof for a state key against the current root, or the root after block `height`
+    pub fn get_state_proof(&self, key: &str, height: Option<u64>) -> Option<(String, StateProof)> {
+        let root = match height {
+            Some(h) => hex::decode(self.get_state_root_at(h)?).ok()?.try_into().ok()?,
+            None => self.state_tree_root(),
+        };
+        Some((hex::encode(root), state_tree::prove(self, root, key)))
+    }
+
+    pub fn get_raw_value(&self, key: &str) -> Option<Vec<u8>> {
+        self.get_raw(key.as_bytes()).ok()?.map(|v| v.to_vec())
+    }
+
+    // --- Snapshots ---
+
+    /// Every key/value pair committed to by the state root, sorted by key
+    pub fn state_entries(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut entries: Vec<(Vec<u8>, Vec<u8>)> = STATE_PREFIXES.iter()
+            .flat_map(|prefix| self.scan_raw(prefix).flatten().map(|(k, v)| (k.to_vec(), v.to_vec())))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// Replace the whole state with `entries`, dropping keys that are not among them.
+    /// Entries outside the state prefixes are ignored.
+    pub fn replace_state(&self, entries: &[(Vec<u8>, Vec<u8>)]) {
+        let keep: std::collections::HashSet<&[u8]> = entries.iter().map(|(k, _)| k.as_slice()).collect();
+        for (key, _) in self.state_entries() {
+            if !keep.contains(key.as_slice()) {
+                self.delete_raw(&key).expect("Failed to remove state entry");
+            }
+        }
+        for (key, value) in entries.iter().filter(|(k, _)| is_state_key(k)) {
+            self.put_raw(key, value).expect("Failed to import state entry");
         }
-        
-        hex::encode(hasher.finalize())
     }
 
     // --- Nonce Management ---
 
     pub fn get_nonce(&self, address: &str) -> u64 {
         let key = format!("nonce:{}", address);
-        match self.db.get(key.as_bytes()).ok().flatten() {
+        match self.get_raw(key.as_bytes()).ok().flatten() {
             Some(data) => {
                 let mut bytes = [0u8; 8];
                 bytes.copy_from_slice(&data);
@@ -203,33 +586,154 @@ impl ChainStorage {
     pub fn increment_nonce(&self, address: &str) {
         let current = self.get_nonce(address);
         let key = format!("nonce:{}", address);
-        self.db.insert(key.as_bytes(), &(current + 1).to_be_bytes()).expect("Sled error");
+        self.put_raw(key.as_bytes(), (current + 1).to_be_bytes()).expect("Sled error");
     }
 
     // --- Multi-Sig Storage ---
 
     pub fn save_multisig(&self, account: &crate::core::MultiSigAccount) {
         let encoded = account.encode();
-        self.db.insert(format!("multisig:{}", account.address).as_bytes(), encoded).expect("Failed to save multisig");
+        self.put_raw(format!("multisig:{}", account.address).as_bytes(), encoded).expect("Failed to save multisig");
     }
 
     pub fn get_multisig(&self, address: &str) -> Option<crate::core::MultiSigAccount> {
-        self.db.get(format!("multisig:{}", address).as_bytes()).ok()?.and_then(|data| crate::core::MultiSigAccount::decode(&mut &data[..]).ok())
+        self.get_raw(format!("multisig:{}", address).as_bytes()).ok()?.and_then(|data| crate::core::MultiSigAccount::decode(&mut &data[..]).ok())
     }
 
     // --- Escrow Storage ---
 
     pub fn save_escrow(&self, escrow: &crate::core::Escrow) {
         let encoded = escrow.encode();
-        self.db.insert(format!("escrow:{}", escrow.id).as_bytes(), encoded).expect("Failed to save escrow");
+        self.put_raw(format!("escrow:{}", escrow.id).as_bytes(), encoded).expect("Failed to save escrow");
     }
 
     pub fn get_escrow(&self, id: &str) -> Option<crate::core::Escrow> {
-        self.db.get(format!("escrow:{}", id).as_bytes()).ok()?.and_then(|data| crate::core::Escrow::decode(&mut &data[..]).ok())
+        self.get_raw(format!("escrow:{}", id).as_bytes()).ok()?.and_then(|data| crate::core::Escrow::decode(&mut &data[..]).ok())
+    }
+
+    // --- Receipts ---
+
+    pub fn save_receipt(&self, receipt: &crate::execution::Receipt) {
+        let encoded = receipt.encode();
+        self.put_raw(format!("receipt:{}", receipt.tx_hash).as_bytes(), encoded).expect("Failed to save receipt");
+    }
+
+    pub fn get_receipt(&self, tx_hash: &str) -> Option<crate::execution::Receipt> {
+        self.get_raw(format!("receipt:{}", tx_hash).as_bytes()).ok()?.and_then(|data| crate::execution::Receipt::decode(&mut &data[..]).ok())
+    }
+
+    /// Look up an included transaction through its receipt
+    pub fn get_transaction(&self, tx_hash: &str) -> Option<(crate::core::Transaction, crate::execution::Receipt)> {
+        let receipt = self.get_receipt(tx_hash)?;
+        let block = self.get_block(receipt.block_height)?;
+        let tx = block.transactions.get(receipt.index as usize)?.clone();
+        Some((tx, receipt))
+    }
+
+    // --- Transaction Indexes ---
+
+    /// Index the executed transactions of a committed block by address, property and escrow
+    pub fn index_block(&self, block: &Block, receipts: &[Receipt]) {
+        for (tx, receipt) in block.transactions.iter().zip(receipts) {
+            if receipt.status == ReceiptStatus::Rejected {
+                continue;
+            }
+            let (height, index) = (receipt.block_height, receipt.index);
+            let entry = |sent: bool, received: bool| TxIndexEntry {
+                tx_hash: receipt.tx_hash.clone(),
+                block_height: height,
+                index,
+                tx_type: tx.tx_type.name().to_string(),
+                sent,
+                received,
+            };
+
+            let self_transfer = tx.sender == tx.receiver;
+            self.put_raw(index_key("idx_addr", &tx.sender, height, index).as_bytes(), entry(true, self_transfer).encode()).expect("Failed to index sender");
+            if !tx.receiver.is_empty() && !self_transfer {
+                self.put_raw(index_key("idx_addr", &tx.receiver, height, index).as_bytes(), entry(false, true).encode()).expect("Failed to index receiver");
+            }
+
+            let mut property_id = receipt.property_id.clone();
+            if let TransactionType::ApplyForVisa { property_id: id, .. } | TransactionType::TransferFraction { property_id: id, .. } = &tx.tx_type {
+                property_id = Some(id.clone());
+            }
+            if let Some(id) = property_id {
+                self.put_raw(index_key("idx_property", &id, height, index).as_bytes(), entry(false, false).encode()).expect("Failed to index property");
+            }
+            if let Some(id) = &receipt.escrow_id {
+                self.put_raw(index_key("idx_escrow", id, height, index).as_bytes(), entry(false, false).encode()).expect("Failed to index escrow");
+            }
+        }
+    }
+
+    /// One page of an address history plus the cursor for the next page, if any
+    pub fn get_transactions_by_address(&self, address: &str, query: &TxQuery) -> (Vec<TxIndexEntry>, Option<(u64, u32)>) {
+        let prefix = format!("idx_addr:{}:", address);
+        let (start, end) = match (query.cursor, query.newest_first) {
+            (Some((h, i)), true) => (prefix.clone(), index_key("idx_addr", address, h, i)),
+            // Appending a byte moves the start just past the cursor entry
+            (Some((h, i)), false) => (format!("{}\0", index_key("idx_addr", address, h, i)), format!("{};", prefix.trim_end_matches(':'))),
+            (None, _) => (prefix.clone(), format!("{};", prefix.trim_end_matches(':'))),
+        };
+
+        let range = self.db.range(start.as_bytes()..end.as_bytes());
+        let entries: Box<dyn Iterator<Item = _>> = if query.newest_first { Box::new(range.rev()) } else { Box::new(range) };
+
+        let limit = query.limit.clamp(1, 100);
+        let mut page: Vec<TxIndexEntry> = entries
+            .filter_map(|item| item.ok().and_then(|(_, v)| TxIndexEntry::decode(&mut &v[..]).ok()))
+            .filter(|e| match query.direction {
+                TxDirection::All => true,
+                TxDirection::Sent => e.sent,
+                TxDirection::Received => e.received,
+            })
+            .filter(|e| query.tx_type.as_ref().is_none_or(|t| &e.tx_type == t))
+            .take(limit + 1)
+            .collect();
+
+        let next_cursor = if page.len() > limit {
+            page.truncate(limit);
+            page.last().map(|e| (e.block_height, e.index))
+        } else {
+            None
+        };
+        (page, next_cursor)
+    }
+
+    pub fn get_transactions_by_property(&self, property_id: &str) -> Vec<TxIndexEntry> {
+        self.scan_index(&format!("idx_property:{}:", property_id))
+    }
+
+    pub fn get_transactions_by_escrow(&self, escrow_id: &str) -> Vec<TxIndexEntry> {
+        self.scan_index(&format!("idx_escrow:{}:", escrow_id))
+    }
+
+    fn scan_index(&self, prefix: &str) -> Vec<TxIndexEntry> {
+        self.scan_raw(prefix.as_bytes())
+            .filter_map(|item| item.ok().and_then(|(_, v)| TxIndexEntry::decode(&mut &v[..]).ok()))
+            .collect()
+    }
+
+    // --- Mempool Journal ---
+
+    pub fn journal_pending_tx(&self, tx: &crate::core::Transaction, inserted_at: u64) {
+        self.mempool.insert(tx.hash().as_bytes(), (inserted_at, tx).encode()).expect("Failed to journal pending transaction");
+    }
+
+    pub fn remove_pending_tx(&self, tx_hash: &str) {
+        self.mempool.remove(tx_hash.as_bytes()).expect("Failed to remove pending transaction");
+    }
+
+    /// Journaled pending transactions with their original insertion time
+    pub fn list_pending_txs(&self) -> Vec<(u64, crate::core::Transaction)> {
+        self.mempool.iter()
+            .filter_map(|item| item.ok().and_then(|(_, v)| <(u64, crate::core::Transaction)>::decode(&mut &v[..]).ok()))
+            .collect()
     }
 
     pub fn list_properties(&self) -> Vec<crate::core::Property> {
-        self.db.scan_prefix(b"property:")
+        self.scan_raw(b"property:")
             .filter_map(|item| {
                 item.ok().and_then(|(_, v)| crate::core::Property::decode(&mut &v[..]).ok())
             })
@@ -237,7 +741,7 @@ impl ChainStorage {
     }
 
     pub fn list_escrows(&self) -> Vec<crate::core::Escrow> {
-        self.db.scan_prefix(b"escrow:")
+        self.scan_raw(b"escrow:")
             .filter_map(|item| {
                 item.ok().and_then(|(_, v)| crate::core::Escrow::decode(&mut &v[..]).ok())
             })
@@ -249,3 +753,15 @@ impl ChainStorage {
         self.db.flush().expect("Failed to flush database");
     }
 }
+
+impl NodeStore for ChainStorage {
+    fn get_node(&self, hash: &Hash) -> Option<Node> {
+        self.get_raw(format!("smt:{}", hex::encode(hash)).as_bytes()).ok()?.and_then(|data| Node::decode(&mut &data[..]).ok())
+    }
+
+    fn put_node(&self, node: &Node) -> Hash {
+        let hash = node.hash();
+        self.put_raw(format!("smt:{}", hex::encode(hash)).as_bytes(), node.encode()).expect("Failed to save state tree node");
+        hash
+    }
+}
@@ -0,0 +1,163 @@
+use crate::consensus::ConsensusEngine;
+use crate::execution::Receipt;
+use crate::network::{SyncRequest, SyncedBlock, MAX_SYNC_BATCH};
+use crate::storage::ChainStorage;
+use crate::validation::{self, BlockError};
+use crate::vm::AureumVM;
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::sync::Arc;
+use log::info;
+
+/// How long a starting node waits for peers to report a higher tip before joining consensus
+pub const STATUS_WAIT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncState {
+    /// Waiting for peers to report their tip
+    Discovering { since: u64 },
+    /// Downloading and replaying blocks up to `target`
+    Downloading { target: u64 },
+    /// Caught up; the node takes part in consensus
+    Synced,
+}
+
+/// Drives block range sync: tracks peer tips and decides which range to request next
+pub struct BlockSync {
+    pub state: SyncState,
+    peer_heights: HashMap<PeerId, u64>,
+    in_flight: Option<PeerId>,
+}
+
+impl BlockSync {
+    pub fn new(now: u64) -> Self {
+        Self { state: SyncState::Discovering { since: now }, peer_heights: HashMap::new(), in_flight: None }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.state == SyncState::Synced
+    }
+
+    /// Record a peer's tip, either from a status response or a block it gossiped ahead of us
+    pub fn on_peer_height(&mut self, peer: PeerId, height: u64, local_height: u64) {
+        self.peer_heights.insert(peer, height);
+        if height <= local_height {
+            return;
+        }
+        let target = match self.state {
+            SyncState::Downloading { target } => target.max(height),
+            _ => {
+                info!("🔄 Sync: peer {} is at height {}, we are at {}", peer, height, local_height);
+                height
+            }
+        };
+        self.state = SyncState::Downloading { target };
+    }
+
+    /// The next request to send, if any; also moves to `Synced` once there is nothing left to fetch
+    pub fn next_request(&mut self, local_height: u64, now: u64) -> Option<(PeerId, SyncRequest)> {
+        if self.in_flight.is_some() {
+            return None;
+        }
+        match self.state {
+            SyncState::Discovering { since } => {
+                if now >= since + STATUS_WAIT_SECS {
+                    self.finish(local_height);
+                }
+                None
+            }
+            SyncState::Downloading { target } => {
+                let best = self.peer_heights.iter()
+                    .filter(|(_, h)| **h > local_height)
+                    .max_by_key(|(peer, h)| (**h, std::cmp::Reverse(**peer)))
+                    .map(|(peer, h)| (*peer, *h));
+                let (true, Some((peer, peer_height))) = (local_height < target, best) else {
+                    self.finish(local_height);
+                    return None;
+                };
+                self.in_flight = Some(peer);
+                let count = (peer_height.min(target) - local_height).min(MAX_SYNC_BATCH as u64) as u32;
+                Some((peer, SyncRequest::Blocks { from: local_height + 1, count }))
+            }
+            SyncState::Synced => None,
+        }
+    }
+
+    /// A block batch from `peer` was replayed up to `local_height`
+    pub fn on_blocks_applied(&mut self, peer: &PeerId, local_height: u64) {
+        if self.in_flight.as_ref() == Some(peer) {
+            self.in_flight = None;
+        }
+        if let SyncState::Downloading { target } = self.state
+            && local_height >= target
+        {
+            self.finish(local_height);
+        }
+    }
+
+    /// `peer` failed to answer or served bad blocks; stop relying on it
+    pub fn on_failure(&mut self, peer: &PeerId) {
+        if self.in_flight.as_ref() == Some(peer) {
+            self.in_flight = None;
+        }
+        self.peer_heights.remove(peer);
+    }
+
+    fn finish(&mut self, local_height: u64) {
+        if self.state != SyncState::Synced {
+            info!("✅ Sync complete at height {}, joining consensus", local_height);
+        }
+        self.state = SyncState::Synced;
+    }
+}
+
+/// Verify a downloaded block against its commit certificate, replay it and commit it with
+/// the certificate, then move the engine past it
+pub fn apply_synced_block(storage: &ChainStorage, vm: &AureumVM, engine: &mut ConsensusEngine, synced: &SyncedBlock) -> Result<Vec<Receipt>, BlockError> {
+    let cert = synced.certificate.as_ref().ok_or(BlockError::MissingCertificate)?;
+    let state = Arc::new(storage.begin_block());
+    let receipts = validation::validate_block(&state, vm, engine, &synced.block)?;
+    // `storage` still holds the validator set the block was finalized by
+    validation::verify_commit(storage, engine, &synced.block, cert)?;
+    state.save_commit_certificate(cert);
+    state.commit_block();
+    engine.finalize_block(storage, &synced.block);
+    Ok(receipts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_state_machine() {
+        let (a, b) = (PeerId::random(), PeerId::random());
+
+        // Nobody ahead of us: join consensus once the status wait elapses
+        let mut sync = BlockSync::new(100);
+        sync.on_peer_height(a, 5, 5);
+        assert_eq!(sync.next_request(5, 105), None);
+        assert!(!sync.is_synced());
+        assert_eq!(sync.next_request(5, 110), None);
+        assert!(sync.is_synced());
+
+        // Download from the highest peer in bounded batches, one request at a time
+        let mut sync = BlockSync::new(100);
+        sync.on_peer_height(a, 100, 0);
+        sync.on_peer_height(b, 150, 0);
+        assert_eq!(sync.state, SyncState::Downloading { target: 150 });
+        assert_eq!(sync.next_request(0, 100), Some((b, SyncRequest::Blocks { from: 1, count: MAX_SYNC_BATCH })));
+        assert_eq!(sync.next_request(0, 100), None);
+        sync.on_blocks_applied(&b, 64);
+
+        // A failing peer is dropped and the next best one is used
+        assert_eq!(sync.next_request(64, 100), Some((b, SyncRequest::Blocks { from: 65, count: MAX_SYNC_BATCH })));
+        sync.on_failure(&b);
+        assert_eq!(sync.next_request(64, 100), Some((a, SyncRequest::Blocks { from: 65, count: 36 })));
+        sync.on_blocks_applied(&a, 100);
+
+        // Nobody left to serve the rest of the range
+        assert_eq!(sync.next_request(100, 100), None);
+        assert!(sync.is_synced());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::core::{Block, BLOCK_VERSION};
+use crate::consensus::{CertificateError, CommitCertificate, ConsensusEngine};
+use crate::execution::{self, ExecError, Receipt};
+use crate::storage::ChainStorage;
+use crate::vm::AureumVM;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How far ahead of local time a block's timestamp may be, in seconds
+pub const MAX_BLOCK_TIME_DRIFT_SECS: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockError {
+    UnsupportedVersion(u8),
+    WrongChainId { expected: u64, found: u64 },
+    UnexpectedHeight { expected: u64, found: u64 },
+    UnknownProposer(String),
+    ValidatorSetMismatch { expected: String, found: String },
+    ParentMismatch { expected: String, found: String },
+    TimestampNotAfterParent { parent: u64, found: u64 },
+    TimestampTooFarAhead { max: u64, found: u64 },
+    MerkleRootMismatch { expected: String, found: String },
+    StateRootMismatch { expected: String, found: String },
+    InvalidTransaction { index: usize, error: ExecError },
+    Execution(ExecError),
+    MissingCertificate,
+    InvalidCertificate(CertificateError),
+    LastCommitMismatch { expected: String, found: String },
+    MissingLastCommit,
+    InvalidLastCommit(CertificateError),
+}
+
+impl BlockError {
+    /// Whether the block proves its sender misbehaved, as opposed to merely being early or late
+    pub fn is_fault(&self) -> bool {
+        // A block from the future may only mean our clock is behind, and a peer may serve blocks
+        // it committed before it kept certificates
+        !matches!(self, BlockError::UnexpectedHeight { .. } | BlockError::TimestampTooFarAhead { .. } | BlockError::MissingCertificate)
+    }
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::UnsupportedVersion(v) => write!(f, "unsupported block version {}", v),
+            BlockError::WrongChainId { expected, found } => write!(f, "wrong chain id: expected {}, got {}", expected, found),
+            BlockError::UnexpectedHeight { expected, found } => write!(f, "unexpected block height {} (expected {})", found, expected),
+            BlockError::UnknownProposer(p) => write!(f, "proposer {} is not an active validator", p),
+            BlockError::ValidatorSetMismatch { expected, found } => write!(f, "validator set hash {} does not match local set ({})", found, expected),
+            BlockError::ParentMismatch { expected, found } => write!(f, "parent hash {} does not match local head {}", found, expected),
+            BlockError::TimestampNotAfterParent { parent, found } => write!(f, "timestamp {} is not after the parent's ({})", found, parent),
+            BlockError::TimestampTooFarAhead { max, found } => write!(f, "timestamp {} is ahead of local time (at most {})", found, max),
+            BlockError::MerkleRootMismatch { expected, found } => write!(f, "tx merkle root {} does not match transactions ({})", found, expected),
+            BlockError::StateRootMismatch { expected, found } => write!(f, "state root {} does not match re-executed state ({})", found, expected),
+            BlockError::InvalidTransaction { index, error } => write!(f, "transaction {} is invalid: {}", index, error),
+            BlockError::Execution(e) => write!(f, "execution failed: {}", e),
+            BlockError::MissingCertificate => write!(f, "block has no commit certificate"),
+            BlockError::InvalidCertificate(e) => write!(f, "invalid commit certificate: {}", e),
+            BlockError::LastCommitMismatch { expected, found } => write!(f, "last commit hash {} does not match carried certificate ({})", found, expected),
+            BlockError::MissingLastCommit => write!(f, "block does not carry its parent's commit certificate"),
+            BlockError::InvalidLastCommit(e) => write!(f, "invalid last commit: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+/// Check that `cert` finalizes `block` under the validator set the block was built against
+pub fn verify_commit(state: &ChainStorage, engine: &ConsensusEngine, block: &Block, cert: &CommitCertificate) -> Result<(), BlockError> {
+    let validator_set = state.get_validator_set().unwrap_or_else(|| engine.validator_set.clone());
+    cert.verify(state.get_chain_id(), block.header.height, &block.hash(), &validator_set, engine.authority_veto_active)
+        .map_err(BlockError::InvalidCertificate)
+}
+
+/// Execute `block` and its consensus finalization into `state`, a handle from
+/// `ChainStorage::begin_block`, returning the receipts and the resulting state root
+pub fn execute_and_finalize(state: &Arc<ChainStorage>, vm: &AureumVM, engine: &ConsensusEngine, block: &Block) -> Result<(Vec<Receipt>, String), ExecError> {
+    // The set signing this block's certificate, kept to verify it when the next block carries it
+    let signing_set = state.get_validator_set().unwrap_or_else(|| engine.validator_set.clone());
+    let receipts = execution::execute_block(state, vm, block)?;
+    engine.apply_finalization(state, block);
+    state.save_previous_validator_set(&signing_set);
+    Ok((receipts, state.calculate_state_root()))
+}
+
+/// Fully validate a block and execute it into `state`, a handle from `ChainStorage::begin_block`.
+///
+/// Checks the header version and chain id, height continuity, parent linkage, that the
+/// timestamp moves forward without running ahead of local time, the parent's commit
+/// certificate (required from height 2), the proposer and validator set, the transaction merkle root
+/// and every transaction envelope, then re-executes the block and compares the
+/// post-execution state root. Nothing is committed; on success the caller commits `state`.
+pub fn validate_block(state: &Arc<ChainStorage>, vm: &AureumVM, engine: &ConsensusEngine, block: &Block) -> Result<Vec<Receipt>, BlockError> {
+    let header = &block.header;
+    if header.version != BLOCK_VERSION {
+        return Err(BlockError::UnsupportedVersion(header.version));
+    }
+    let chain_id = state.get_chain_id();
+    if header.chain_id != chain_id {
+        return Err(BlockError::WrongChainId { expected: chain_id, found: header.chain_id });
+    }
+
+    let latest = state.get_latest_height();
+    if header.height != latest + 1 {
+        return Err(BlockError::UnexpectedHeight { expected: latest + 1, found: header.height });
+    }
+
+    let parent = state.get_block(latest);
+    let head = parent.as_ref().map(|b| b.hash()).unwrap_or_default();
+    if header.parent_hash != head {
+        return Err(BlockError::ParentMismatch { expected: head, found: header.parent_hash.clone() });
+    }
+
+    let parent_time = parent.map(|b| b.header.timestamp).unwrap_or_default();
+    if header.timestamp <= parent_time {
+        return Err(BlockError::TimestampNotAfterParent { parent: parent_time, found: header.timestamp });
+    }
+    let max_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + MAX_BLOCK_TIME_DRIFT_SECS;
+    if header.timestamp > max_time {
+        return Err(BlockError::TimestampTooFarAhead { max: max_time, found: header.timestamp });
+    }
+
+    let last_commit_hash = block.calculate_last_commit_hash();
+    if header.last_commit_hash != last_commit_hash {
+        return Err(BlockError::LastCommitMismatch { expected: last_commit_hash, found: header.last_commit_hash.clone() });
+    }
+    // Every block after the first proves its parent was committed; liveness is tracked from it
+    match &block.last_commit {
+        Some(cert) => {
+            let signing_set = state.get_previous_validator_set()
+                .or_else(|| state.get_validator_set())
+                .unwrap_or_else(|| engine.validator_set.clone());
+            cert.verify(chain_id, latest, &head, &signing_set, engine.authority_veto_active).map_err(BlockError::InvalidLastCommit)?;
+        }
+        None if header.height > 1 => return Err(BlockError::MissingLastCommit),
+        None => {}
+    }
+
+    let validator_set = state.get_validator_set().unwrap_or_else(|| engine.validator_set.clone());
+    if validator_set.active(&header.proposer).is_none() {
+        return Err(BlockError::UnknownProposer(header.proposer.clone()));
+    }
+    let set_hash = validator_set.hash();
+    if header.validator_set_hash != set_hash {
+        return Err(BlockError::ValidatorSetMismatch { expected: set_hash, found: header.validator_set_hash.clone() });
+    }
+
+    let merkle_root = block.calculate_merkle_root();
+    if header.tx_merkle_root != merkle_root {
+        return Err(BlockError::MerkleRootMismatch { expected: merkle_root, found: header.tx_merkle_root.clone() });
+    }
+
+    // A proposer must never include transactions that could not have passed admission
+    for (index, tx) in block.transactions.iter().enumerate() {
+        execution::check_envelope(chain_id, tx).map_err(|error| BlockError::InvalidTransaction { index, error })?;
+    }
+
+    let (receipts, state_root) = execute_and_finalize(state, vm, engine, block).map_err(BlockError::Execution)?;
+    if header.state_root != state_root {
+        return Err(BlockError::StateRootMismatch { expected: state_root, found: header.state_root.clone() });
+    }
+    Ok(receipts)
+}
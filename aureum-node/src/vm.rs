@@ -84,6 +84,12 @@ impl AureumVM {
         Self { storage, compliance }
     }
 
+    /// VM reading and writing through another storage handle, e.g. a block overlay
+    pub fn with_storage(&self, storage: Arc<ChainStorage>) -> Self {
+        let compliance = Arc::new(self.compliance.with_storage(storage.clone()));
+        Self { storage, compliance }
+    }
+
     pub fn execute_transaction(&self, caller: &str, target: &str, data: Vec<u8>, value: u64) -> Result<ExecutionResult, String> {
         let mut db = AureumDB { storage: self.storage.clone() };
         
@@ -148,9 +154,8 @@ impl AureumVM {
         Ok(Address::from(arr))
     }
 
-    pub fn verify_compliance(&self, tx: &crate::core::Transaction) -> bool {
-        let current_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-        
+    /// Compliance check as of `current_time`; during execution this is the block timestamp
+    pub fn verify_compliance(&self, tx: &crate::core::Transaction, current_time: u64) -> bool {
         // Default to Global jurisdiction for simple transfers
         let jurisdiction = match &tx.tx_type {
             crate::core::TransactionType::ApplyForVisa { program, .. } => {
@@ -0,0 +1,149 @@
+use crate::core::Block;
+use crate::consensus::{BftMessage, BftStep};
+use parity_scale_codec::{Encode, Decode};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// File in the data dir holding what this validator last signed
+pub const WAL_FILE: &str = "consensus.wal";
+
+/// The last vote a validator signed, and the block it was locked on when it did.
+///
+/// A proposal is recorded as a `Propose` step vote for the proposed block's hash.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct SignedState {
+    pub vote: BftMessage,
+    pub locked_block: Option<(u32, Block)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalError {
+    Io(String),
+    Corrupt(String),
+    /// The vote is for an earlier height, round or step than one already signed
+    Regression { height: u64, round: u32, step: BftStep },
+    /// A different vote was already signed for the same height, round and step
+    Conflict { height: u64, round: u32, step: BftStep, signed: Option<String> },
+}
+
+impl fmt::Display for WalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalError::Io(e) => write!(f, "i/o error: {}", e),
+            WalError::Corrupt(e) => write!(f, "corrupt consensus WAL: {}", e),
+            WalError::Regression { height, round, step } => write!(f, "already signed a later vote than {:?} at height {} round {}", step, height, round),
+            WalError::Conflict { height, round, step, signed } => write!(f, "already signed {:?} for {:?} at height {} round {}", step, signed, height, round),
+        }
+    }
+}
+
+impl std::error::Error for WalError {}
+
+impl From<std::io::Error> for WalError {
+    fn from(e: std::io::Error) -> Self {
+        WalError::Io(e.to_string())
+    }
+}
+
+/// Position of a vote in the order a validator signs them
+fn sign_order(vote: &BftMessage) -> (u64, u32, u8) {
+    let step = match vote.step {
+        BftStep::Propose => 0,
+        BftStep::Prevote => 1,
+        BftStep::Precommit => 2,
+        BftStep::Commit => 3,
+    };
+    (vote.height, vote.round, step)
+}
+
+/// Consensus write-ahead log: every proposal and vote is recorded here before it leaves the
+/// node, so a restarted validator resumes its round and never signs one conflicting with an
+/// earlier one
+pub struct ConsensusWal {
+    path: PathBuf,
+    last: Option<SignedState>,
+}
+
+impl ConsensusWal {
+    /// Open the WAL in `data_dir`, loading what was last signed
+    pub fn open(data_dir: &Path) -> Result<Self, WalError> {
+        let path = data_dir.join(WAL_FILE);
+        let last = match std::fs::read(&path) {
+            Ok(data) => Some(SignedState::decode(&mut &data[..]).map_err(|e| WalError::Corrupt(e.to_string()))?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, last })
+    }
+
+    pub fn last_signed(&self) -> Option<&SignedState> {
+        self.last.as_ref()
+    }
+
+    /// Refuse `vote` if it precedes the last signed vote or differs from it at the same step
+    pub fn check(&self, vote: &BftMessage) -> Result<(), WalError> {
+        let Some(last) = &self.last else { return Ok(()) };
+        let signed = &last.vote;
+        match sign_order(vote).cmp(&sign_order(signed)) {
+            std::cmp::Ordering::Greater => Ok(()),
+            std::cmp::Ordering::Equal if vote.block_hash == signed.block_hash => Ok(()),
+            std::cmp::Ordering::Equal => Err(WalError::Conflict { height: vote.height, round: vote.round, step: vote.step.clone(), signed: signed.block_hash.clone() }),
+            std::cmp::Ordering::Less => Err(WalError::Regression { height: vote.height, round: vote.round, step: vote.step.clone() }),
+        }
+    }
+
+    /// Check `vote` and durably record it, with the current lock, before it is released
+    pub fn record(&mut self, vote: &BftMessage, locked_block: Option<(u32, Block)>) -> Result<(), WalError> {
+        self.check(vote)?;
+        let state = SignedState { vote: vote.clone(), locked_block };
+
+        // Write-then-rename, so a crash leaves either the previous record or the new one
+        let tmp = self.path.with_extension("wal.tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&state.encode())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        self.last = Some(state);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(height: u64, round: u32, step: BftStep, block_hash: Option<&str>) -> BftMessage {
+        BftMessage { height, round, step, block_hash: block_hash.map(String::from), validator: "val1".into(), signature: vec![1] }
+    }
+
+    #[test]
+    fn test_wal_refuses_conflicting_and_earlier_votes() {
+        let dir = Path::new("test_data/wal_test");
+        std::fs::create_dir_all(dir).unwrap();
+        let _ = std::fs::remove_file(dir.join(WAL_FILE));
+
+        let mut wal = ConsensusWal::open(dir).unwrap();
+        assert!(wal.last_signed().is_none());
+        wal.record(&vote(5, 1, BftStep::Prevote, Some("a")), None).unwrap();
+        wal.record(&vote(5, 1, BftStep::Precommit, Some("a")), Some((1, Block::new_genesis(1)))).unwrap();
+
+        // Survives a restart
+        let wal = ConsensusWal::open(dir).unwrap();
+        let last = wal.last_signed().unwrap();
+        assert_eq!(sign_order(&last.vote), (5, 1, 2));
+        assert_eq!(last.locked_block.as_ref().map(|(r, _)| *r), Some(1));
+
+        assert_eq!(wal.check(&vote(5, 1, BftStep::Precommit, Some("a"))), Ok(()));
+        assert_eq!(wal.check(&vote(5, 1, BftStep::Precommit, None)), Err(WalError::Conflict { height: 5, round: 1, step: BftStep::Precommit, signed: Some("a".into()) }));
+        assert_eq!(wal.check(&vote(5, 1, BftStep::Prevote, Some("b"))), Err(WalError::Regression { height: 5, round: 1, step: BftStep::Prevote }));
+        assert!(wal.check(&vote(5, 0, BftStep::Precommit, Some("a"))).is_err());
+        assert!(wal.check(&vote(4, 9, BftStep::Precommit, Some("a"))).is_err());
+        assert_eq!(wal.check(&vote(5, 2, BftStep::Prevote, Some("b"))), Ok(()));
+        assert_eq!(wal.check(&vote(6, 0, BftStep::Prevote, None)), Ok(()));
+
+        std::fs::write(dir.join(WAL_FILE), b"garbage").unwrap();
+        assert!(matches!(ConsensusWal::open(dir), Err(WalError::Corrupt(_))));
+        std::fs::remove_file(dir.join(WAL_FILE)).unwrap();
+    }
+}
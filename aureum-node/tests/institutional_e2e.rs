@@ -1,12 +1,12 @@
-use aureum_node::core::{Transaction, TransactionType, VisaProgram, Validator, ValidatorRole, ValidatorSet, Block, BlockHeader};
+use aureum_node::core::{Transaction, TransactionType, VisaProgram, Validator, ValidatorRole, ValidatorSet, ValidatorStatus, Block, BlockHeader, TX_VERSION, DEFAULT_CHAIN_ID, BLOCK_VERSION};
 use aureum_node::storage::ChainStorage;
 use aureum_node::vm::AureumVM;
 use aureum_node::compliance::{ComplianceEngine, ComplianceProfile, Jurisdiction};
 use aureum_node::consensus::{ConsensusEngine, BftStep};
-use ed25519_dalek::{SigningKey, Signer};
+use aureum_node::execution::{apply_block, ReceiptStatus};
+use ed25519_dalek::SigningKey;
 use rand_core::OsRng;
 use std::sync::Arc;
-use parity_scale_codec::Encode;
 
 #[tokio::test]
 async fn test_institutional_workflow() {
@@ -45,8 +45,10 @@ async fn test_institutional_workflow() {
     // Seed balances
     storage.update_balance(&institution_addr, 10_000_000_000); // 10B AUR
 
-    // 4. Create Institutional Transfer Transaction
-    let mut tx = Transaction {
+    // 4. Create Institutional Transfer + Golden Visa Application
+    let mut transfer = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
         sender: institution_addr.clone(),
         receiver: investor_addr.clone(),
         amount: 1_000_000_000, // 1B AUR
@@ -54,26 +56,34 @@ async fn test_institutional_workflow() {
         fee: 1000,
         signature: vec![],
         pub_key: institution_key.verifying_key().to_bytes().to_vec(),
+        tx_type: TransactionType::Transfer,
+        hash: None,
+    };
+    transfer.sign(&institution_key);
+
+    let mut visa = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
+        sender: institution_addr.clone(),
+        receiver: investor_addr.clone(),
+        amount: 1_000_000_000,
+        nonce: 1,
+        fee: 1000,
+        signature: vec![],
+        pub_key: institution_key.verifying_key().to_bytes().to_vec(),
         tx_type: TransactionType::ApplyForVisa { 
             property_id: "prop_golden_1".to_string(), 
             program: VisaProgram::Portugal 
         },
+        hash: None,
     };
-
-    // Sign
-    let mut msg = Vec::new();
-    msg.extend_from_slice(tx.sender.as_bytes());
-    msg.extend_from_slice(tx.receiver.as_bytes());
-    msg.extend_from_slice(&tx.amount.to_be_bytes());
-    msg.extend_from_slice(&tx.nonce.to_be_bytes());
-    msg.extend_from_slice(&tx.fee.to_be_bytes());
-    msg.extend_from_slice(&tx.pub_key);
-    msg.extend_from_slice(&tx.tx_type.encode());
-    tx.signature = institution_key.sign(&msg).to_vec();
+    visa.sign(&institution_key);
 
     // 5. Verify Compliance & Sig
-    assert!(tx.verify_signature());
-    assert!(vm.verify_compliance(&tx));
+    for tx in [&transfer, &visa] {
+        assert!(tx.verify_signature());
+        assert!(vm.verify_compliance(tx, 1672531200));
+    }
 
     // 6. Simulate Consensus Finalization
     let val_set = ValidatorSet {
@@ -83,39 +93,51 @@ async fn test_institutional_workflow() {
             stake: 1000,
             role: ValidatorRole::Authority,
             last_active: 0,
+            proposer_priority: 0,
+            status: ValidatorStatus::Active,
+            commission_bps: 0,
         }],
         total_stake: 1000,
     };
     let mut engine = ConsensusEngine::new(val_set);
+    assert_eq!(engine.step, BftStep::Propose);
     
-    let block = Block {
+    let mut block = Block {
         header: BlockHeader {
-            parent_hash: "0".into(),
-            timestamp: 1672531200,
+            version: BLOCK_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
             height: 1,
-            state_root: storage.calculate_state_root(),
+            timestamp: 1672531200,
+            parent_hash: "0".into(),
             tx_merkle_root: "0".into(),
+            state_root: storage.calculate_state_root(),
+            proposer: "val1".into(),
+            validator_set_hash: engine.validator_set.hash(),
+            last_commit_hash: "".into(),
         },
-        transactions: vec![tx.clone()],
+        transactions: vec![transfer, visa],
+        last_commit: None,
     };
+    block.header.tx_merkle_root = block.calculate_merkle_root();
+    engine.set_proposal(block.clone());
     
-    // Execute block contents
-    for tx in &block.transactions {
-        if vm.verify_compliance(tx) {
-            let res = vm.execute_transaction(&tx.sender, &tx.receiver, tx.encode(), tx.amount);
-            println!("VM execution result: {:?}", res);
-            assert!(res.is_ok());
-            storage.increment_nonce(&tx.sender);
-        }
-    }
-    storage.save_block(&block);
+    // Execute block contents through the shared state transition
+    let receipts = apply_block(&storage, &vm, &block).expect("block applies");
+    assert!(receipts.iter().all(|r| r.status == ReceiptStatus::Success), "receipts: {:?}", receipts);
 
     // 7. Verify Final State
     assert_eq!(storage.get_balance(&investor_addr), 1_000_000_000);
-    assert_eq!(storage.get_nonce(&institution_addr), 1);
+    assert_eq!(storage.get_balance(&institution_addr), 10_000_000_000 - 1_000_000_000 - 2 * 1000);
+    assert_eq!(storage.get_nonce(&institution_addr), 2);
+
+    let app = storage.get_visa_application(&institution_addr).expect("visa application saved");
+    assert_eq!(app.property_id, "prop_golden_1");
     
     let latest_height = storage.get_latest_height();
     assert_eq!(latest_height, 1);
+
+    // The same block cannot be applied twice
+    assert!(apply_block(&storage, &vm, &block).is_err());
     
     println!("INSTITUTIONAL E2E TEST PASSED!");
 }
@@ -1,11 +1,17 @@
-use aureum_node::core::{Transaction, TransactionType, VisaProgram};
-use aureum_node::storage::ChainStorage;
+use aureum_node::core::{ChainState, Delegation, Transaction, TransactionType, VisaProgram, PropertyStatus, Block, BlockHeader, Validator, ValidatorRole, ValidatorSet, ValidatorStatus, TX_VERSION, DEFAULT_CHAIN_ID, BLOCK_VERSION};
+use aureum_node::consensus::{slash_validator, BftMessage, BftStep, CommitCertificate, ConsensusEngine, Redelegation, BLOCK_ISSUANCE, SlashingParams, UnbondingEntry};
+use aureum_node::network::SyncedBlock;
+use aureum_node::sync::apply_synced_block;
+use aureum_node::snapshot::{self, SnapshotError};
+use aureum_node::storage::{ChainStorage, TxQuery, TxDirection};
 use aureum_node::vm::AureumVM;
 use aureum_node::compliance::{ComplianceEngine, ComplianceProfile, Jurisdiction};
-use ed25519_dalek::{SigningKey, Signer};
+use aureum_node::execution::{apply_block, check_envelope, execute_block, ReceiptStatus, MIN_VALIDATOR_STAKE};
+use aureum_node::mempool::{Mempool, MempoolConfig};
+use aureum_node::validation::{validate_block, execute_and_finalize, BlockError};
+use ed25519_dalek::SigningKey;
 use rand_core::OsRng;
 use std::sync::Arc;
-use parity_scale_codec::Encode;
 
 #[tokio::test]
 async fn test_system_wide_operations() {
@@ -33,6 +39,8 @@ async fn test_system_wide_operations() {
 
     // 4. TRANSACTION 1: Transfer 500k to Alice
     let mut tx1 = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
         sender: genesis_addr.clone(),
         receiver: alice_addr.clone(),
         amount: 500_000,
@@ -41,37 +49,32 @@ async fn test_system_wide_operations() {
         signature: vec![],
         pub_key: genesis_key.verifying_key().to_bytes().to_vec(),
         tx_type: TransactionType::Transfer,
+        hash: None,
     };
     
-    // Sign TX1
-    let mut msg1 = Vec::new();
-    msg1.extend_from_slice(tx1.sender.as_bytes());
-    msg1.extend_from_slice(tx1.receiver.as_bytes());
-    msg1.extend_from_slice(&tx1.amount.to_be_bytes());
-    msg1.extend_from_slice(&tx1.nonce.to_be_bytes());
-    msg1.extend_from_slice(&tx1.fee.to_be_bytes());
-    msg1.extend_from_slice(&tx1.pub_key);
-    msg1.extend_from_slice(&tx1.tx_type.encode());
-    
-    tx1.signature = genesis_key.sign(&msg1).to_vec();
-
-    // Execute TX1
+    tx1.sign(&genesis_key);
     assert!(tx1.verify_signature());
-    
-    // Simulate execution loop logic
-    let bal_gen = storage.get_balance(&genesis_addr);
-    assert!(bal_gen >= tx1.amount + tx1.fee);
-    storage.update_balance(&genesis_addr, bal_gen - (tx1.amount + tx1.fee));
-    storage.update_balance(&alice_addr, storage.get_balance(&alice_addr) + tx1.amount);
-    storage.increment_nonce(&genesis_addr);
+
+    // Execute TX1 in block 1
+    let receipts = apply_block(&storage, &vm, &block(1, vec![tx1.clone()])).expect("block 1 applies");
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert_eq!(receipts[0].fee_charged, 100);
+
+    // Receipts and transactions are retrievable by hash once the block is committed
+    let (included, receipt) = storage.get_transaction(&tx1.hash()).expect("tx1 indexed");
+    assert_eq!(included.amount, 500_000);
+    assert_eq!((receipt.block_height, receipt.index), (1, 0));
 
     assert_eq!(storage.get_balance(&alice_addr), 500_000);
+    assert_eq!(storage.get_balance(&genesis_addr), 1_000_000 - 500_000 - 100);
     assert_eq!(storage.get_nonce(&genesis_addr), 1);
 
     // 5. TRANSACTION 2: Alice registers a Property (Villa)
     let prop_metadata = "QmHashOfDeed".to_string();
     let prop_addr = "Rua Augusta, Lisbon".to_string();
     let mut tx2 = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
         sender: alice_addr.clone(),
         receiver: "".to_string(), // System
         amount: 350_000, // Valuation
@@ -83,51 +86,37 @@ async fn test_system_wide_operations() {
             address: prop_addr.clone(), 
             metadata: prop_metadata.clone() 
         },
+        hash: None,
     };
 
-    let mut msg2 = Vec::new();
-    msg2.extend_from_slice(tx2.sender.as_bytes());
-    msg2.extend_from_slice(tx2.receiver.as_bytes());
-    msg2.extend_from_slice(&tx2.amount.to_be_bytes());
-    msg2.extend_from_slice(&tx2.nonce.to_be_bytes());
-    msg2.extend_from_slice(&tx2.fee.to_be_bytes());
-    msg2.extend_from_slice(&tx2.pub_key);
-    msg2.extend_from_slice(&tx2.tx_type.encode());
-    
-    tx2.signature = alice_key.sign(&msg2).to_vec();
-
-    // Execute TX2
+    tx2.sign(&alice_key);
     assert!(tx2.verify_signature());
-    
-    let prop = aureum_node::core::Property {
-        id: format!("prop_{}", tx2.nonce),
-        owner: tx2.sender.clone(),
-        co_owners: vec![],
-        jurisdiction: "Portugal".to_string(),
-        legal_description: prop_addr.clone(),
-        coordinates: (0.0, 0.0),
-        valuation_eur: tx2.amount,
-        valuation_timestamp: 1234567890,
-        valuation_oracle: "system".to_string(),
-        title_deed_hash: prop_metadata.clone(),
-        survey_hash: "".to_string(),
-        visa_program_eligible: true,
-        minimum_investment_met: tx2.amount >= 250_000,
-        kyc_status: 1,
-        aml_cleared: true,
-        mortgages: vec![],
-        liens: vec![],
-    };
-    storage.save_property(&prop);
-    storage.increment_nonce(&alice_addr);
-
-    // Verify Property
-    let saved_prop = storage.get_property("prop_0").expect("Property not found");
+
+    // Execute TX2 in block 2
+    let receipts = apply_block(&storage, &vm, &block(2, vec![tx2.clone()])).expect("block 2 applies");
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert_eq!(receipts[0].property_id, Some(tx2.hash()));
+
+    // Verify Property (tokenized properties are keyed by tx hash)
+    let saved_prop = storage.get_property(&tx2.hash()).expect("Property not found");
     assert_eq!(saved_prop.owner, alice_addr);
     assert_eq!(saved_prop.valuation_eur, 350_000);
+    assert_eq!(saved_prop.status, PropertyStatus::Available);
+    assert_eq!(storage.get_nonce(&alice_addr), 1);
+    assert_eq!(storage.get_balance(&alice_addr), 500_000 - 500);
+
+    // 6. TRANSACTION 3: Alice applies for Golden Visa (Portugal requires institutional KYC)
+    compliance.register_profile(ComplianceProfile {
+        address: alice_addr.clone(),
+        jurisdiction: Jurisdiction::Portugal,
+        kyc_level: 2,
+        is_verified: true,
+        last_updated: 0,
+    });
 
-    // 6. TRANSACTION 3: Alice applies for Golden Visa
     let mut tx3 = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
         sender: alice_addr.clone(),
         receiver: "".to_string(),
         amount: 0,
@@ -139,41 +128,21 @@ async fn test_system_wide_operations() {
             property_id: saved_prop.id.clone(), 
             program: VisaProgram::Portugal 
         },
+        hash: None,
     };
 
-    let mut msg3 = Vec::new();
-    msg3.extend_from_slice(tx3.sender.as_bytes());
-    msg3.extend_from_slice(tx3.receiver.as_bytes());
-    msg3.extend_from_slice(&tx3.amount.to_be_bytes());
-    msg3.extend_from_slice(&tx3.nonce.to_be_bytes());
-    msg3.extend_from_slice(&tx3.fee.to_be_bytes());
-    msg3.extend_from_slice(&tx3.pub_key);
-    msg3.extend_from_slice(&tx3.tx_type.encode());
-    
-    tx3.signature = alice_key.sign(&msg3).to_vec();
-
-    // Execute TX3
+    tx3.sign(&alice_key);
     assert!(tx3.verify_signature());
 
-    if let Some(p) = storage.get_property(&saved_prop.id) {
-        if p.owner == tx3.sender {
-            let app = aureum_node::core::VisaApplication {
-                applicant: tx3.sender.clone(),
-                property_id: p.id.clone(),
-                investment_amount: p.valuation_eur,
-                program: VisaProgram::Portugal,
-                status: aureum_node::core::ApplicationStatus::Pending,
-                timestamp: 1234567899,
-            };
-            storage.save_visa_application(&app);
-            storage.increment_nonce(&tx3.sender);
-        }
-    }
+    // Execute TX3 in block 3
+    let receipts = apply_block(&storage, &vm, &block(3, vec![tx3.clone()])).expect("block 3 applies");
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
 
     // Verify Visa
     let saved_app = storage.get_visa_application(&alice_addr).expect("Visa app not found");
-    assert_eq!(saved_app.property_id, "prop_0");
+    assert_eq!(saved_app.property_id, saved_prop.id);
     assert_eq!(saved_app.status, aureum_node::core::ApplicationStatus::Pending);
+    assert_eq!(storage.get_latest_height(), 3);
 
     // 7. Calculate State Root
     let root = storage.calculate_state_root();
@@ -182,3 +151,1102 @@ async fn test_system_wide_operations() {
 
     println!("SYSTEM TEST PASSED: Full lifecycle executed successfully.");
 }
+
+#[tokio::test]
+async fn test_nonce_replay_protection() {
+    let db_path = "test_data/nonce_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    storage.update_balance(&sender, 10_000);
+
+    let transfer = |nonce: u64, amount: u64| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: sender.clone(),
+            receiver: "Abob".to_string(),
+            amount,
+            nonce,
+            fee: 10,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type: TransactionType::Transfer,
+            hash: None,
+        };
+        tx.sign(&key);
+        tx
+    };
+
+    // Nonce 0 succeeds, a replay of it and a nonce from the future are rejected without touching state
+    let first = transfer(0, 1_000);
+    let receipts = apply_block(&storage, &vm, &block(1, vec![first.clone(), first.clone(), transfer(5, 1)])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert_eq!(receipts[1].status, ReceiptStatus::Rejected);
+    assert_eq!(receipts[1].error.as_deref(), Some("nonce too low: expected 1, got 0"));
+    assert_eq!(receipts[2].error.as_deref(), Some("nonce gap: expected 1, got 5"));
+    assert_eq!(receipts[2].fee_charged, 0);
+    assert_eq!(storage.get_balance(&sender), 10_000 - 1_010);
+    assert_eq!(storage.get_nonce(&sender), 1);
+
+    // A transfer that cannot cover its amount still pays the fee and consumes the nonce
+    let overdraft = transfer(1, 1_000_000);
+    let overdraft_hash = overdraft.hash();
+    let receipts = apply_block(&storage, &vm, &block(2, vec![overdraft.clone()])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Failed);
+    assert_eq!(receipts[0].fee_charged, 10);
+    assert_eq!(storage.get_balance(&sender), 10_000 - 1_010 - 10);
+    assert_eq!(storage.get_nonce(&sender), 2);
+
+    // ...so it cannot be replayed either
+    let receipts = apply_block(&storage, &vm, &block(3, vec![overdraft])).unwrap();
+    assert_eq!(receipts[0].error.as_deref(), Some("nonce too low: expected 2, got 1"));
+    assert_eq!(storage.get_balance(&sender), 10_000 - 1_010 - 10);
+
+    // The rejected replays did not overwrite the receipts of the executed originals
+    assert_eq!(storage.get_receipt(&first.hash()).unwrap().status, ReceiptStatus::Success);
+    let original = storage.get_receipt(&overdraft_hash).unwrap();
+    assert_eq!((original.status, original.block_height), (ReceiptStatus::Failed, 2));
+}
+
+#[tokio::test]
+async fn test_chain_id_domain_separation() {
+    let db_path = "test_data/chain_id_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+    storage.save_chain_id(7);
+
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    storage.update_balance(&sender, 10_000);
+
+    let mut tx = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
+        sender: sender.clone(),
+        receiver: "Abob".to_string(),
+        amount: 100,
+        nonce: 0,
+        fee: 10,
+        signature: vec![],
+        pub_key: key.verifying_key().to_bytes().to_vec(),
+        tx_type: TransactionType::Transfer,
+        hash: None,
+    };
+    tx.sign(&key);
+    assert!(tx.verify_signature());
+
+    // Re-targeting the signed transaction at another chain invalidates the signature
+    let mut retargeted = tx.clone();
+    retargeted.chain_id = 7;
+    assert!(!retargeted.verify_signature());
+    assert_ne!(retargeted.hash(), tx.hash());
+
+    // A transaction signed for another chain is rejected without touching state
+    let receipts = apply_block(&storage, &vm, &block(1, vec![tx.clone()])).unwrap();
+    assert_eq!(receipts[0].error.as_deref(), Some("wrong chain id: expected 7, got 1"));
+    assert_eq!(storage.get_nonce(&sender), 0);
+
+    tx.chain_id = 7;
+    tx.sign(&key);
+    let receipts = apply_block(&storage, &vm, &block(2, vec![tx.clone()])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert_eq!(storage.get_balance("Abob"), 100);
+
+    // Types the chain cannot execute are refused up front, without charging the fee
+    let mut multisig = tx;
+    multisig.nonce = 1;
+    multisig.tx_type = TransactionType::CreateMultiSig { owners: vec![sender.clone()], threshold: 1 };
+    multisig.sign(&key);
+    assert_eq!(check_envelope(7, &multisig).unwrap_err().to_string(), "transaction type CreateMultiSig is not supported");
+    let receipts = apply_block(&storage, &vm, &block(3, vec![multisig])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Rejected);
+    assert_eq!((storage.get_nonce(&sender), storage.get_balance(&sender)), (1, 10_000 - 100 - 10));
+}
+
+#[tokio::test]
+async fn test_address_history_index() {
+    let db_path = "test_data/history_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+
+    let key = SigningKey::generate(&mut OsRng);
+    let alice = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    storage.update_balance(&alice, 100_000);
+
+    let signed = |nonce: u64, tx_type: TransactionType| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: alice.clone(),
+            receiver: "Abob".to_string(),
+            amount: 100,
+            nonce,
+            fee: 1,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type,
+            hash: None,
+        };
+        tx.sign(&key);
+        tx
+    };
+
+    // Blocks 1-4: one transfer each; block 5: an escrow that is released in block 6
+    for nonce in 0..4 {
+        apply_block(&storage, &vm, &block(nonce + 1, vec![signed(nonce, TransactionType::Transfer)])).unwrap();
+    }
+    let escrow_tx = signed(4, TransactionType::EscrowCreate { arbiter: alice.clone(), conditions: "deed".into(), property_id: None });
+    let escrow_id = escrow_tx.hash();
+    apply_block(&storage, &vm, &block(5, vec![escrow_tx])).unwrap();
+    apply_block(&storage, &vm, &block(6, vec![signed(5, TransactionType::EscrowRelease { escrow_id: escrow_id.clone() })])).unwrap();
+
+    // Newest first, two per page
+    let mut query = TxQuery { limit: 2, ..Default::default() };
+    let (page, cursor) = storage.get_transactions_by_address(&alice, &query);
+    assert_eq!(page.iter().map(|e| e.block_height).collect::<Vec<_>>(), vec![6, 5]);
+    query.cursor = cursor;
+    let (page, _) = storage.get_transactions_by_address(&alice, &query);
+    assert_eq!(page.iter().map(|e| e.block_height).collect::<Vec<_>>(), vec![4, 3]);
+
+    // Oldest first, filtered by type, walking every page
+    let mut query = TxQuery { limit: 3, newest_first: false, tx_type: Some("Transfer".into()), ..Default::default() };
+    let mut heights = vec![];
+    loop {
+        let (page, cursor) = storage.get_transactions_by_address(&alice, &query);
+        heights.extend(page.iter().map(|e| e.block_height));
+        match cursor {
+            Some(c) => query.cursor = Some(c),
+            None => break,
+        }
+    }
+    assert_eq!(heights, vec![1, 2, 3, 4]);
+
+    // Direction filter from the receiver's side
+    let received = TxQuery { direction: TxDirection::Received, limit: 100, ..Default::default() };
+    assert_eq!(storage.get_transactions_by_address("Abob", &received).0.len(), 6);
+    let sent = TxQuery { direction: TxDirection::Sent, ..Default::default() };
+    assert!(storage.get_transactions_by_address("Abob", &sent).0.is_empty());
+
+    // Escrow lifecycle is indexed by escrow id
+    let escrow_history = storage.get_transactions_by_escrow(&escrow_id);
+    assert_eq!(escrow_history.iter().map(|e| e.tx_type.as_str()).collect::<Vec<_>>(), vec!["EscrowCreate", "EscrowRelease"]);
+}
+
+#[tokio::test]
+async fn test_mempool_survives_restart() {
+    let db_path = "test_data/mempool_journal_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    let transfer = |key: &SigningKey, nonce: u64, amount: u64| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: aureum_node::core::generate_address(key.verifying_key().as_bytes()),
+            receiver: "Abob".to_string(),
+            amount,
+            nonce,
+            fee: 10,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type: TransactionType::Transfer,
+            hash: None,
+        };
+        tx.sign(key);
+        tx
+    };
+
+    let pending = transfer(&key, 1, 100);
+    {
+        let storage = Arc::new(ChainStorage::new(db_path));
+        let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+        let vm = AureumVM::new(storage.clone(), compliance.clone());
+        storage.update_balance(&sender, 10_000);
+
+        let mut pool = Mempool::restore(MempoolConfig::default(), storage.clone(), 0, |_| true);
+        let committed = transfer(&key, 0, 100);
+        pool.insert(committed.clone(), 0, 0).unwrap();
+        pool.insert(pending.clone(), 0, 0).unwrap();
+
+        // Journaled entries that no longer pass admission once the node restarts
+        let mut forged = transfer(&SigningKey::generate(&mut OsRng), 0, 100);
+        forged.amount = 5;
+        pool.insert(forged, 0, 0).unwrap();
+        pool.insert(transfer(&SigningKey::generate(&mut OsRng), 0, 2_000_000), 0, 0).unwrap();
+        assert_eq!(storage.list_pending_txs().len(), 4);
+
+        // Nonce 0 is committed after being journaled, making it stale
+        apply_block(&storage, &vm, &block(1, vec![committed])).unwrap();
+        storage.flush();
+    }
+
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+    let pool = Mempool::restore(MempoolConfig::default(), storage.clone(), 0, |tx| vm.verify_compliance(tx, 1672531200));
+
+    assert_eq!(pool.len(), 1);
+    assert!(pool.contains(&pending.hash()));
+    assert_eq!(pool.pending_nonce(&sender, storage.get_nonce(&sender)), 2);
+    // Rejected entries are removed from the journal as well
+    let journaled: Vec<String> = storage.list_pending_txs().iter().map(|(_, tx)| tx.hash()).collect();
+    assert_eq!(journaled, vec![pending.hash()]);
+}
+
+#[tokio::test]
+async fn test_block_commit_is_atomic() {
+    let db_path = "test_data/atomic_commit_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    storage.update_balance(&sender, 10_000);
+
+    // Writes of a reverted transaction vanish, committed ones stay buffered until the block commits
+    let state = storage.begin_block();
+    state.update_balance("Acarol", 5);
+    state.revert_tx();
+    assert_eq!(state.get_balance("Acarol"), 0);
+    state.update_balance("Acarol", 7);
+    state.commit_tx();
+    assert_eq!(state.get_balance("Acarol"), 7);
+    assert_eq!(storage.get_balance("Acarol"), 0);
+    drop(state);
+    assert_eq!(storage.get_balance("Acarol"), 0);
+
+    let mut tx = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
+        sender: sender.clone(),
+        receiver: "Abob".to_string(),
+        amount: 1_000,
+        nonce: 0,
+        fee: 10,
+        signature: vec![],
+        pub_key: key.verifying_key().to_bytes().to_vec(),
+        tx_type: TransactionType::Transfer,
+        hash: None,
+    };
+    tx.sign(&key);
+
+    // Nothing of an executed block is visible before commit_block
+    let state = Arc::new(storage.begin_block());
+    let receipts = execute_block(&state, &vm, &block(1, vec![tx.clone()])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert_eq!(state.get_balance("Abob"), 1_000);
+    assert_eq!(state.get_latest_height(), 1);
+    let pending_root = state.calculate_state_root();
+
+    assert_eq!(storage.get_latest_height(), 0);
+    assert!(storage.get_block(1).is_none());
+    assert!(storage.get_receipt(&tx.hash()).is_none());
+    assert_eq!(storage.get_balance(&sender), 10_000);
+    assert_ne!(storage.calculate_state_root(), pending_root);
+
+    state.commit_block();
+    assert_eq!(storage.get_latest_height(), 1);
+    assert_eq!(storage.get_balance(&sender), 10_000 - 1_010);
+    assert_eq!(storage.get_nonce(&sender), 1);
+    assert!(storage.get_receipt(&tx.hash()).is_some());
+    assert_eq!(storage.calculate_state_root(), pending_root);
+}
+
+#[tokio::test]
+async fn test_deterministic_replay() {
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    let block_time = block(1, vec![]).header.timestamp;
+
+    let signed = |nonce: u64, amount: u64, tx_type: TransactionType| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: sender.clone(),
+            receiver: "Abob".to_string(),
+            amount,
+            nonce,
+            fee: 10,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type,
+            hash: None,
+        };
+        tx.sign(&key);
+        tx
+    };
+    let tokenize = signed(0, 600_000, TransactionType::TokenizeProperty { address: "Rua Augusta 1".into(), metadata: "deed".into() });
+    let escrow = signed(1, 5_000, TransactionType::EscrowCreate { arbiter: "Aarbiter".into(), conditions: "deed transfer".into(), property_id: None });
+    let visa = signed(2, 500_000, TransactionType::ApplyForVisa { property_id: tokenize.hash(), program: VisaProgram::Portugal });
+    let txs = vec![tokenize.clone(), escrow.clone(), visa];
+
+    let mut roots = vec![];
+    for db_path in ["test_data/replay_a_db", "test_data/replay_b_db"] {
+        let _ = std::fs::remove_dir_all(db_path);
+        let storage = Arc::new(ChainStorage::new(db_path));
+        let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+        let vm = AureumVM::new(storage.clone(), compliance.clone());
+        storage.update_balance(&sender, 10_000_000);
+        // The Golden Visa holding period ends one second after the block time
+        compliance.register_profile(ComplianceProfile {
+            address: sender.clone(),
+            jurisdiction: Jurisdiction::Portugal,
+            kyc_level: 2,
+            is_verified: true,
+            last_updated: block_time - 157_680_000 + 1,
+        });
+
+        let receipts = apply_block(&storage, &vm, &block(1, txs.clone())).unwrap();
+        assert_eq!(receipts[0].status, ReceiptStatus::Success);
+        assert_eq!(receipts[1].status, ReceiptStatus::Success);
+        // Judged at block time, not at the (much later) wall clock time
+        assert_eq!(receipts[2].status, ReceiptStatus::Failed);
+
+        assert_eq!(storage.get_property(&tokenize.hash()).unwrap().valuation_timestamp, block_time);
+        assert_eq!(storage.get_escrow(&escrow.hash()).unwrap().created_at, block_time);
+        roots.push(storage.calculate_state_root());
+    }
+    assert_eq!(roots[0], roots[1]);
+}
+
+#[tokio::test]
+async fn test_state_proofs() {
+    let db_path = "test_data/state_proof_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    storage.update_balance(&sender, 10_000);
+
+    let mut tx = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
+        sender: sender.clone(),
+        receiver: "Abob".to_string(),
+        amount: 1_000,
+        nonce: 0,
+        fee: 10,
+        signature: vec![],
+        pub_key: key.verifying_key().to_bytes().to_vec(),
+        tx_type: TransactionType::Transfer,
+        hash: None,
+    };
+    tx.sign(&key);
+    apply_block(&storage, &vm, &block(1, vec![tx])).unwrap();
+    let root_1 = storage.get_state_root_at(1).unwrap();
+    assert_eq!(root_1, storage.calculate_state_root());
+
+    let balance_key = format!("balance:{}", sender);
+    let (root, proof) = storage.get_state_proof(&balance_key, None).unwrap();
+    assert_eq!(root, root_1);
+    assert!(proof.verify(&root, Some(&8_990u64.to_be_bytes())));
+    assert!(!proof.verify(&root, Some(&10_000u64.to_be_bytes())));
+
+    let (_, absent) = storage.get_state_proof("balance:Anobody", None).unwrap();
+    assert!(absent.verify(&root, None));
+
+    // Later blocks change the root, but proofs against block 1 still verify
+    storage.update_balance(&sender, 1);
+    let block_2 = block(2, vec![]);
+    apply_block(&storage, &vm, &block_2).unwrap();
+    assert_ne!(storage.get_state_root_at(2).unwrap(), root_1);
+    let (historical_root, historical) = storage.get_state_proof(&balance_key, Some(1)).unwrap();
+    assert_eq!(historical_root, root_1);
+    assert!(historical.verify(&root_1, Some(&8_990u64.to_be_bytes())));
+    assert!(storage.get_state_proof(&balance_key, Some(99)).is_none());
+
+    // Pruning forgets roots before the retention window, and the nodes only they reached
+    let root_1_node = format!("smt:{}", root_1);
+    assert!(storage.get_raw_value(&root_1_node).is_some());
+    assert!(storage.prune_state_tree(2) > 0);
+    assert!(storage.get_raw_value(&root_1_node).is_none());
+    assert!(storage.get_state_proof(&balance_key, Some(1)).is_none());
+    let (root_2, proof) = storage.get_state_proof(&balance_key, Some(2)).unwrap();
+    assert!(proof.verify(&root_2, Some(&storage.get_balance(&sender).to_be_bytes())));
+    let (_, absent) = storage.get_state_proof("balance:Anobody", None).unwrap();
+    assert!(absent.verify(&storage.calculate_state_root(), None));
+
+    // Reopening a data dir without a state tree rebuilds the same root
+    drop(vm);
+    drop(compliance);
+    let expected = storage.calculate_state_root();
+    drop(storage);
+    {
+        let db = sled::open(db_path).unwrap();
+        db.remove(b"smt:root").unwrap();
+        db.flush().unwrap();
+    }
+    assert_eq!(ChainStorage::new(db_path).calculate_state_root(), expected);
+}
+
+#[tokio::test]
+async fn test_gossiped_block_validation() {
+    let db_path = "test_data/block_validation_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    storage.update_balance(&sender, 10_000);
+    let genesis = Block::new_genesis(DEFAULT_CHAIN_ID);
+    storage.save_block(&genesis);
+    let validator_set = ValidatorSet {
+        validators: vec![Validator { address: "val1".into(), public_key: vec![], stake: 1000, role: ValidatorRole::Authority, last_active: 0, proposer_priority: 0, status: ValidatorStatus::Active, commission_bps: 0 }],
+        total_stake: 1000,
+    };
+    storage.save_validator_set(&validator_set);
+    let engine = ConsensusEngine::new(validator_set.clone());
+
+    let mut tx = Transaction {
+        version: TX_VERSION,
+        chain_id: DEFAULT_CHAIN_ID,
+        sender: sender.clone(),
+        receiver: "Abob".to_string(),
+        amount: 1_000,
+        nonce: 0,
+        fee: 10,
+        signature: vec![],
+        pub_key: key.verifying_key().to_bytes().to_vec(),
+        tx_type: TransactionType::Transfer,
+        hash: None,
+    };
+    tx.sign(&key);
+
+    let valid = {
+        let mut b = block(1, vec![tx.clone()]);
+        b.header.parent_hash = genesis.hash();
+        b.header.proposer = "val1".into();
+        b.header.validator_set_hash = validator_set.hash();
+        // The proposer commits to the state after executing and finalizing the block
+        let (_, state_root) = execute_and_finalize(&Arc::new(storage.begin_block()), &vm, &engine, &b).unwrap();
+        b.header.state_root = state_root;
+        b
+    };
+    let validate = |b: &Block| validate_block(&Arc::new(storage.begin_block()), &vm, &engine, b);
+
+    let mut wrong = valid.clone();
+    wrong.header.version = 0;
+    assert_eq!(validate(&wrong).unwrap_err(), BlockError::UnsupportedVersion(0));
+
+    let mut wrong = valid.clone();
+    wrong.header.chain_id = DEFAULT_CHAIN_ID + 1;
+    assert!(matches!(validate(&wrong), Err(BlockError::WrongChainId { .. })));
+
+    let mut wrong = valid.clone();
+    wrong.header.height = 3;
+    let err = validate(&wrong).unwrap_err();
+    assert_eq!(err, BlockError::UnexpectedHeight { expected: 1, found: 3 });
+    assert!(!err.is_fault());
+
+    let mut wrong = valid.clone();
+    wrong.header.parent_hash = "00".into();
+    assert!(matches!(validate(&wrong), Err(BlockError::ParentMismatch { .. })));
+
+    let mut wrong = valid.clone();
+    wrong.header.timestamp = genesis.header.timestamp;
+    let err = validate(&wrong).unwrap_err();
+    assert_eq!(err, BlockError::TimestampNotAfterParent { parent: genesis.header.timestamp, found: genesis.header.timestamp });
+    assert!(err.is_fault());
+
+    // A block stamped too far ahead may just mean our clock is behind
+    let mut wrong = valid.clone();
+    wrong.header.timestamp = u64::MAX;
+    let err = validate(&wrong).unwrap_err();
+    assert!(matches!(err, BlockError::TimestampTooFarAhead { .. }) && !err.is_fault());
+
+    let mut wrong = valid.clone();
+    wrong.header.proposer = "mallory".into();
+    assert_eq!(validate(&wrong).unwrap_err(), BlockError::UnknownProposer("mallory".into()));
+
+    let mut wrong = valid.clone();
+    wrong.header.validator_set_hash = "00".into();
+    assert!(matches!(validate(&wrong), Err(BlockError::ValidatorSetMismatch { .. })));
+
+    let mut wrong = valid.clone();
+    wrong.transactions[0].amount = 9_000;
+    assert!(matches!(validate(&wrong), Err(BlockError::MerkleRootMismatch { .. })));
+    wrong.header.tx_merkle_root = wrong.calculate_merkle_root();
+    assert!(matches!(validate(&wrong), Err(BlockError::InvalidTransaction { index: 0, .. })));
+
+    let mut wrong = valid.clone();
+    wrong.header.state_root = "ff".repeat(32);
+    let err = validate(&wrong).unwrap_err();
+    assert!(matches!(err, BlockError::StateRootMismatch { .. }) && err.is_fault());
+
+    // Rejected blocks leave no trace
+    assert_eq!(storage.get_latest_height(), 0);
+    assert_eq!(storage.get_balance(&sender), 10_000);
+
+    let state = Arc::new(storage.begin_block());
+    let receipts = validate_block(&state, &vm, &engine, &valid).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    state.commit_block();
+    assert_eq!(storage.get_latest_height(), 1);
+    assert_eq!(storage.calculate_state_root(), valid.header.state_root);
+    assert_eq!(storage.get_balance("Abob"), 1_000);
+}
+
+#[tokio::test]
+async fn test_block_range_sync() {
+    let validator_key = SigningKey::generate(&mut OsRng);
+    let validator = aureum_node::core::generate_address(validator_key.verifying_key().as_bytes());
+    let validator_set = ValidatorSet {
+        validators: vec![Validator { address: validator.clone(), public_key: validator_key.verifying_key().to_bytes().to_vec(), stake: 1000, role: ValidatorRole::Authority, last_active: 0, proposer_priority: 0, status: ValidatorStatus::Active, commission_bps: 0 }],
+        total_stake: 1000,
+    };
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+
+    // Two nodes from the same genesis: `source` produces blocks, `fresh` catches up
+    let node = |db_path: &str| {
+        let _ = std::fs::remove_dir_all(db_path);
+        let storage = Arc::new(ChainStorage::new(db_path));
+        let vm = AureumVM::new(storage.clone(), Arc::new(ComplianceEngine::new(storage.clone())));
+        storage.save_block(&Block::new_genesis(DEFAULT_CHAIN_ID));
+        storage.save_validator_set(&validator_set);
+        storage.update_balance(&sender, 10_000);
+        (storage, vm, ConsensusEngine::new(validator_set.clone()))
+    };
+    let (source, source_vm, mut source_engine) = node("test_data/sync_source_db");
+    let (fresh, fresh_vm, mut fresh_engine) = node("test_data/sync_fresh_db");
+
+    for height in 1..=3 {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: sender.clone(),
+            receiver: "Abob".to_string(),
+            amount: 100,
+            nonce: height - 1,
+            fee: 10,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type: TransactionType::Transfer,
+            hash: None,
+        };
+        tx.sign(&key);
+        let mut b = block(height, vec![tx]);
+        b.header.parent_hash = source.get_block(height - 1).unwrap().hash();
+        b.header.proposer = validator.clone();
+        b.header.validator_set_hash = source.get_validator_set().unwrap().hash();
+        b.last_commit = source.get_commit_certificate(height - 1);
+        b.header.last_commit_hash = b.calculate_last_commit_hash();
+        b.header.state_root = execute_and_finalize(&Arc::new(source.begin_block()), &source_vm, &source_engine, &b).unwrap().1;
+
+        // The parent's certificate must match the header and be signed by the parent's validators
+        if height == 2 {
+            let mut unlinked = b.clone();
+            unlinked.last_commit = None;
+            assert!(matches!(validate_block(&Arc::new(source.begin_block()), &source_vm, &source_engine, &unlinked), Err(BlockError::LastCommitMismatch { .. })));
+            unlinked.header.last_commit_hash = unlinked.calculate_last_commit_hash();
+            assert_eq!(validate_block(&Arc::new(source.begin_block()), &source_vm, &source_engine, &unlinked).unwrap_err(), BlockError::MissingLastCommit);
+            let mut forged = b.clone();
+            forged.last_commit.as_mut().unwrap().precommits[0].sign(DEFAULT_CHAIN_ID, &key);
+            forged.header.last_commit_hash = forged.calculate_last_commit_hash();
+            assert!(matches!(validate_block(&Arc::new(source.begin_block()), &source_vm, &source_engine, &forged), Err(BlockError::InvalidLastCommit(_))));
+        }
+
+        let mut precommit = BftMessage { height, round: 0, step: BftStep::Precommit, block_hash: Some(b.hash()), validator: validator.clone(), signature: vec![] };
+        precommit.sign(DEFAULT_CHAIN_ID, &validator_key);
+        let state = Arc::new(source.begin_block());
+        validate_block(&state, &source_vm, &source_engine, &b).unwrap();
+        state.save_commit_certificate(&CommitCertificate { height, round: 0, block_hash: b.hash(), precommits: vec![precommit] });
+        state.commit_block();
+        source_engine.finalize_block(&source, &b);
+    }
+
+    let served = |height: u64| SyncedBlock { block: source.get_block(height).unwrap(), certificate: source.get_commit_certificate(height) };
+
+    // Blocks must come with a certificate signed by the validator set
+    let mut uncertified = served(1);
+    uncertified.certificate = None;
+    let err = apply_synced_block(&fresh, &fresh_vm, &mut fresh_engine, &uncertified).unwrap_err();
+    assert_eq!(err, BlockError::MissingCertificate);
+    assert!(!err.is_fault());
+    let mut forged = served(1);
+    forged.certificate.as_mut().unwrap().precommits[0].sign(DEFAULT_CHAIN_ID, &key);
+    let err = apply_synced_block(&fresh, &fresh_vm, &mut fresh_engine, &forged).unwrap_err();
+    assert!(matches!(err, BlockError::InvalidCertificate(_)) && err.is_fault());
+    assert_eq!(fresh.get_latest_height(), 0);
+
+    for height in 1..=3 {
+        let receipts = apply_synced_block(&fresh, &fresh_vm, &mut fresh_engine, &served(height)).unwrap();
+        assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    }
+    assert_eq!(fresh.get_latest_height(), 3);
+    assert_eq!(fresh_engine.height, 4);
+    assert_eq!(fresh.calculate_state_root(), source.calculate_state_root());
+    assert_eq!(fresh.get_balance("Abob"), 300);
+    assert!(fresh.get_commit_certificate(3).is_some());
+
+    // Replaying a block twice is refused without penalty
+    let err = apply_synced_block(&fresh, &fresh_vm, &mut fresh_engine, &served(3)).unwrap_err();
+    assert!(!err.is_fault());
+}
+
+#[tokio::test]
+async fn test_state_snapshot_roundtrip() {
+    let source_path = "test_data/snapshot_source_db";
+    let snapshot_dir = std::path::Path::new("test_data/snapshot_export");
+    let _ = std::fs::remove_dir_all(source_path);
+    let _ = std::fs::remove_dir_all(snapshot_dir);
+    let source = Arc::new(ChainStorage::new(source_path));
+    let vm = AureumVM::new(source.clone(), Arc::new(ComplianceEngine::new(source.clone())));
+    let validator_key = SigningKey::from_bytes(&[9; 32]);
+    let genesis_set = ValidatorSet {
+        validators: vec![Validator { address: "val1".into(), public_key: validator_key.verifying_key().to_bytes().to_vec(), stake: 1000, role: ValidatorRole::Authority, last_active: 0, proposer_priority: 0, status: ValidatorStatus::Active, commission_bps: 0 }],
+        total_stake: 1000,
+    };
+    source.save_block(&Block::new_genesis(DEFAULT_CHAIN_ID));
+    source.save_validator_set(&genesis_set);
+
+    let key = SigningKey::generate(&mut OsRng);
+    let sender = aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    source.update_balance(&sender, 10_000);
+    for height in 1..=2 {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: sender.clone(),
+            receiver: format!("Areceiver{}", height),
+            amount: 500,
+            nonce: height - 1,
+            fee: 10,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type: TransactionType::Transfer,
+            hash: None,
+        };
+        tx.sign(&key);
+        let mut b = block(height, vec![tx]);
+        b.header.parent_hash = source.get_block(height - 1).unwrap().hash();
+        let preview = Arc::new(source.begin_block());
+        execute_block(&preview, &vm, &b).unwrap();
+        b.header.state_root = preview.calculate_state_root();
+        apply_block(&source, &vm, &b).unwrap();
+    }
+    let certificate = |signer: &SigningKey| {
+        let mut precommit = BftMessage { height: 2, round: 0, step: BftStep::Precommit, block_hash: Some(source.get_block(2).unwrap().hash()), validator: "val1".into(), signature: vec![] };
+        precommit.sign(DEFAULT_CHAIN_ID, signer);
+        CommitCertificate { height: 2, round: 0, block_hash: source.get_block(2).unwrap().hash(), precommits: vec![precommit] }
+    };
+    source.save_commit_certificate(&certificate(&validator_key));
+
+    // Only the current state can be exported
+    assert_eq!(snapshot::export(&source, 1, snapshot_dir).unwrap_err(), SnapshotError::HeightUnavailable { requested: 1, latest: 2 });
+    let manifest = snapshot::export(&source, 2, snapshot_dir).unwrap();
+    assert_eq!(manifest.state_root, source.get_block(2).unwrap().header.state_root);
+    assert_eq!(snapshot::read_manifest(snapshot_dir).unwrap().chunks, manifest.chunks);
+
+    let fresh_node = |db_path: &str| {
+        let _ = std::fs::remove_dir_all(db_path);
+        let storage = ChainStorage::new(db_path);
+        storage.save_block(&Block::new_genesis(DEFAULT_CHAIN_ID));
+        storage.save_validator_set(&genesis_set);
+        storage.update_balance("Astale", 1);
+        storage
+    };
+
+    // Chunks and the block are checked before anything is written
+    let fresh = fresh_node("test_data/snapshot_fresh_db");
+    let (_, mut chunks) = snapshot::build(&source, 2).unwrap();
+    chunks[0].push(0);
+    assert_eq!(snapshot::restore(&fresh, &manifest, &chunks, None, true).unwrap_err(), SnapshotError::ChunkMismatch(0));
+    assert!(matches!(snapshot::import(&fresh, snapshot_dir, Some("00"), true), Err(SnapshotError::BlockMismatch { .. })));
+    let mut forged = manifest.clone();
+    forged.state_root = "ff".repeat(32);
+    assert!(matches!(snapshot::restore(&fresh, &forged, &snapshot::build(&source, 2).unwrap().1, None, true), Err(SnapshotError::StateRootMismatch { .. })));
+
+    // Without a trusted hash, only a certificate from the genesis validators vouches for the block
+    let chunks = snapshot::build(&source, 2).unwrap().1;
+    let mut uncertified = manifest.clone();
+    uncertified.certificate = None;
+    assert_eq!(snapshot::restore(&fresh, &uncertified, &chunks, None, true).unwrap_err(), SnapshotError::Untrusted);
+    let mut forged = manifest.clone();
+    forged.certificate = Some(certificate(&key));
+    assert!(matches!(snapshot::restore(&fresh, &forged, &chunks, None, true), Err(SnapshotError::InvalidCertificate(_))));
+    assert_eq!(fresh.get_latest_height(), 0);
+    assert_eq!(fresh.get_balance("Astale"), 1);
+
+    // An operator-supplied block hash is enough on its own
+    let pinned = fresh_node("test_data/snapshot_pinned_db");
+    snapshot::restore(&pinned, &uncertified, &chunks, Some(&source.get_block(2).unwrap().hash()), true).unwrap();
+    assert_eq!(pinned.calculate_state_root(), source.calculate_state_root());
+
+    snapshot::import(&fresh, snapshot_dir, None, true).unwrap();
+    assert_eq!(fresh.get_latest_height(), 2);
+    assert_eq!(fresh.calculate_state_root(), source.calculate_state_root());
+    assert_eq!(fresh.get_state_root_at(2), source.get_state_root_at(2));
+    assert_eq!(fresh.get_balance(&sender), source.get_balance(&sender));
+    assert_eq!(fresh.get_balance("Areceiver2"), 500);
+    assert_eq!(fresh.get_balance("Astale"), 0);
+    assert_eq!(fresh.get_nonce(&sender), 2);
+    assert_eq!(fresh.get_validator_set().unwrap().hash(), source.get_validator_set().unwrap().hash());
+
+    // A data dir that already has blocks is never overwritten
+    assert_eq!(snapshot::import(&fresh, snapshot_dir, None, true).unwrap_err(), SnapshotError::NotEmpty(2));
+}
+
+#[tokio::test]
+async fn test_equivocation_evidence() {
+    let db_path = "test_data/evidence_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let compliance = Arc::new(ComplianceEngine::new(storage.clone()));
+    let vm = AureumVM::new(storage.clone(), compliance.clone());
+
+    let validator_key = SigningKey::from_bytes(&[3; 32]);
+    let offender = aureum_node::core::generate_address(validator_key.verifying_key().as_bytes());
+    storage.save_validator_set(&ValidatorSet {
+        validators: vec![Validator { address: offender.clone(), public_key: validator_key.verifying_key().to_bytes().to_vec(), stake: 1000, role: ValidatorRole::Authority, last_active: 0, proposer_priority: 0, status: ValidatorStatus::Active, commission_bps: 0 }],
+        total_stake: 1000,
+    });
+    storage.update_balance(&offender, 5_000);
+
+    let reporter_key = SigningKey::generate(&mut OsRng);
+    let reporter = aureum_node::core::generate_address(reporter_key.verifying_key().as_bytes());
+    let vote = |round: u32, block_hash: &str| {
+        let mut vote = BftMessage { height: 7, round, step: BftStep::Precommit, block_hash: Some(block_hash.into()), validator: offender.clone(), signature: vec![] };
+        vote.sign(DEFAULT_CHAIN_ID, &validator_key);
+        vote
+    };
+    let evidence = |nonce: u64, first: BftMessage, second: BftMessage| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: reporter.clone(),
+            receiver: offender.clone(),
+            amount: 0,
+            nonce,
+            fee: 0,
+            signature: vec![],
+            pub_key: reporter_key.verifying_key().to_bytes().to_vec(),
+            tx_type: TransactionType::Evidence { first, second },
+            hash: None,
+        };
+        tx.sign(&reporter_key);
+        tx
+    };
+
+    // Votes that do not conflict, or whose signature was tampered with, prove nothing
+    let mut forged = vote(0, "b");
+    forged.signature[0] ^= 1;
+    let receipts = apply_block(&storage, &vm, &block(1, vec![
+        evidence(0, vote(0, "a"), vote(0, "a")),
+        evidence(1, vote(0, "a"), vote(1, "b")),
+        evidence(2, vote(0, "a"), forged),
+    ])).unwrap();
+    assert_eq!(receipts[0].error.as_deref(), Some("invalid evidence: votes do not conflict"));
+    assert_eq!(receipts[1].error.as_deref(), Some("invalid evidence: votes do not conflict"));
+    assert_eq!(receipts[2].error.as_deref(), Some(&*format!("invalid evidence: vote is not signed by {}", offender)));
+    assert_eq!(storage.get_validator_set().unwrap().total_stake, 1000);
+
+    // Two signed precommits for different blocks in one round: slashed by half and tombstoned
+    let receipts = apply_block(&storage, &vm, &block(2, vec![evidence(3, vote(0, "a"), vote(0, "b"))])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    let set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators[0].stake, set.total_stake), (500, 500));
+    assert_eq!(set.validators[0].status, ValidatorStatus::Tombstoned);
+    assert_eq!(storage.get_balance(&offender), 5_000);
+
+    // The same offence is only punished once, and a tombstoned validator is not punished again
+    let mut later = vote(0, "c");
+    later.height = 8;
+    later.sign(DEFAULT_CHAIN_ID, &validator_key);
+    let mut conflicting = later.clone();
+    conflicting.block_hash = Some("d".into());
+    conflicting.sign(DEFAULT_CHAIN_ID, &validator_key);
+    let receipts = apply_block(&storage, &vm, &block(3, vec![evidence(4, vote(0, "b"), vote(0, "c")), evidence(5, later, conflicting)])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Failed);
+    assert_eq!(receipts[1].error.as_deref(), Some(&*format!("invalid evidence: {} is already tombstoned", offender)));
+    assert_eq!(storage.get_validator_set().unwrap().total_stake, 500);
+}
+
+#[tokio::test]
+async fn test_validator_unjail() {
+    let db_path = "test_data/unjail_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let vm = AureumVM::new(storage.clone(), Arc::new(ComplianceEngine::new(storage.clone())));
+
+    let validator_key = SigningKey::from_bytes(&[4; 32]);
+    let validator = aureum_node::core::generate_address(validator_key.verifying_key().as_bytes());
+    let outsider_key = SigningKey::generate(&mut OsRng);
+    let entry = |address: &str, key: &SigningKey, status: ValidatorStatus| Validator {
+        address: address.into(), public_key: key.verifying_key().to_bytes().to_vec(), stake: 1000, role: ValidatorRole::Standard, last_active: 0, proposer_priority: -700, status, commission_bps: 0,
+    };
+    storage.save_validator_set(&ValidatorSet {
+        validators: vec![entry(&validator, &validator_key, ValidatorStatus::Jailed { until: 5 }), entry("Aother", &outsider_key, ValidatorStatus::Active)],
+        total_stake: 2000,
+    });
+    storage.update_balance(&validator, 1_000);
+
+    let unjail = |key: &SigningKey, nonce: u64| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: aureum_node::core::generate_address(key.verifying_key().as_bytes()),
+            receiver: "".into(),
+            amount: 0,
+            nonce,
+            fee: 0,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type: TransactionType::Unjail,
+            hash: None,
+        };
+        tx.sign(key);
+        tx
+    };
+
+    // Jailed validators carry no weight until they are let out
+    let engine = ConsensusEngine::new(storage.get_validator_set().unwrap());
+    assert_eq!(engine.select_proposer(), "Aother");
+    assert!(engine.validator_address(&validator_key).is_none());
+
+    // Only a validator can unjail, and only once its jail period is over
+    let receipts = apply_block(&storage, &vm, &block(1, vec![unjail(&outsider_key, 0), unjail(&validator_key, 0)])).unwrap();
+    assert!(receipts[0].error.as_deref().unwrap().contains("is not authorized to unjail"));
+    assert_eq!(receipts[1].error.as_deref(), Some(&*format!("validator {} is jailed until height 5", validator)));
+
+    for height in 2..5 {
+        apply_block(&storage, &vm, &block(height, vec![])).unwrap();
+    }
+    let receipts = apply_block(&storage, &vm, &block(5, vec![unjail(&validator_key, 1)])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    let set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators[0].status.clone(), set.validators[0].proposer_priority), (ValidatorStatus::Active, 0));
+    assert_eq!(ConsensusEngine::new(set).validator_address(&validator_key), Some(validator.clone()));
+
+    // An active validator has nothing to unjail
+    let receipts = apply_block(&storage, &vm, &block(6, vec![unjail(&validator_key, 2)])).unwrap();
+    assert_eq!(receipts[0].error.as_deref(), Some(&*format!("validator {} is Active, not Jailed", validator)));
+}
+
+#[tokio::test]
+async fn test_stake_and_unstake() {
+    let db_path = "test_data/staking_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let vm = AureumVM::new(storage.clone(), Arc::new(ComplianceEngine::new(storage.clone())));
+    storage.save_slashing_params(&SlashingParams { unbonding_blocks: 3, ..SlashingParams::default() });
+
+    let genesis_key = SigningKey::from_bytes(&[5; 32]);
+    let genesis = aureum_node::core::generate_address(genesis_key.verifying_key().as_bytes());
+    storage.save_validator_set(&ValidatorSet {
+        validators: vec![Validator { address: genesis.clone(), public_key: genesis_key.verifying_key().to_bytes().to_vec(), stake: 1000, role: ValidatorRole::Authority, last_active: 0, proposer_priority: 0, status: ValidatorStatus::Active, commission_bps: 0 }],
+        total_stake: 1000,
+    });
+    let staker_key = SigningKey::generate(&mut OsRng);
+    let staker = aureum_node::core::generate_address(staker_key.verifying_key().as_bytes());
+    storage.update_balance(&staker, 10_000);
+
+    let tx = |key: &SigningKey, nonce: u64, tx_type: TransactionType| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: aureum_node::core::generate_address(key.verifying_key().as_bytes()),
+            receiver: "".into(),
+            amount: 0,
+            nonce,
+            fee: 0,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type,
+            hash: None,
+        };
+        tx.sign(key);
+        tx
+    };
+
+    // Staking moves AUR out of the balance and makes the sender a validator signing with its key
+    let receipts = apply_block(&storage, &vm, &block(1, vec![tx(&staker_key, 0, TransactionType::Stake { amount: 4000 })])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    let set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators.len(), set.total_stake, set.validators[1].proposer_priority), (2, 5000, -1000));
+    assert_eq!(storage.get_balance(&staker), 6000);
+    assert_eq!(ConsensusEngine::new(set).validator_address(&staker_key), Some(staker.clone()));
+
+    // A new validator bonds at least the minimum, and a partial unstake cannot leave less behind
+    let small_key = SigningKey::generate(&mut OsRng);
+    let small = aureum_node::core::generate_address(small_key.verifying_key().as_bytes());
+    storage.update_balance(&small, 10_000);
+    let receipts = apply_block(&storage, &vm, &block(2, vec![
+        tx(&staker_key, 1, TransactionType::Stake { amount: 1000 }),
+        tx(&staker_key, 2, TransactionType::Unstake { amount: 6000 }),
+        tx(&staker_key, 3, TransactionType::Unstake { amount: 4500 }),
+        tx(&small_key, 0, TransactionType::Stake { amount: 0 }),
+        tx(&small_key, 1, TransactionType::Stake { amount: MIN_VALIDATOR_STAKE - 1 }),
+    ])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert_eq!(receipts[1].error.as_deref(), Some(&*format!("insufficient stake: {} has 5000 staked but unstakes 6000", staker)));
+    assert_eq!(receipts[2].error.as_deref(), Some(&*format!("stake below minimum: {} would have 500 bonded, at least 1000 required", staker)));
+    assert_eq!(receipts[3].error.as_deref(), Some("amount must be greater than zero"));
+    assert_eq!(receipts[4].error.as_deref(), Some(&*format!("stake below minimum: {} would have 999 bonded, at least 1000 required", small)));
+    let set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators.len(), set.validators[1].stake, set.total_stake), (2, 5000, 6000));
+    assert_eq!(storage.get_balance(&small), 10_000);
+
+    // Unstaked AUR leaves the set at once but waits out the unbonding period, still slashable
+    apply_block(&storage, &vm, &block(3, vec![tx(&staker_key, 4, TransactionType::Unstake { amount: 2000 })])).unwrap();
+    assert_eq!(storage.get_unbonding_queue(), vec![UnbondingEntry { validator: staker.clone(), owner: staker.clone(), amount: 2000, release_height: 6 }]);
+    let mut set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators[1].stake, set.total_stake), (3000, 4000));
+    assert_eq!(slash_validator(&storage, &mut set, &staker, 50, ValidatorStatus::Jailed { until: 100 }), Some(2500));
+    assert_eq!(storage.get_unbonding_queue()[0].amount, 1000);
+
+    // A jailed validator can still unstake, and the last active stake cannot leave
+    let receipts = apply_block(&storage, &vm, &block(4, vec![
+        tx(&staker_key, 5, TransactionType::Unstake { amount: 1500 }),
+        tx(&genesis_key, 0, TransactionType::Unstake { amount: 1000 }),
+    ])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert!(receipts[1].error.as_deref().unwrap().contains("is not authorized to withdraw the last active stake"));
+    let set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators.len(), set.validators[1].stake, set.total_stake), (2, 0, 1000));
+
+    // Matured entries are paid back as blocks are finalized
+    let engine = ConsensusEngine::new(set);
+    engine.apply_finalization(&storage, &block(5, vec![]));
+    assert_eq!(storage.get_balance(&staker), 5000);
+    engine.apply_finalization(&storage, &block(6, vec![]));
+    assert_eq!(storage.get_balance(&staker), 6000);
+    engine.apply_finalization(&storage, &block(7, vec![]));
+    assert_eq!(storage.get_balance(&staker), 7500);
+    assert!(storage.get_unbonding_queue().is_empty());
+}
+
+#[tokio::test]
+async fn test_delegated_staking() {
+    let db_path = "test_data/delegation_test_db";
+    let _ = std::fs::remove_dir_all(db_path);
+    let storage = Arc::new(ChainStorage::new(db_path));
+    let vm = AureumVM::new(storage.clone(), Arc::new(ComplianceEngine::new(storage.clone())));
+    storage.save_slashing_params(&SlashingParams { unbonding_blocks: 2, ..SlashingParams::default() });
+
+    let keys: Vec<SigningKey> = (6..=8u8).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+    let address = |key: &SigningKey| aureum_node::core::generate_address(key.verifying_key().as_bytes());
+    let (a, b, delegator) = (address(&keys[0]), address(&keys[1]), address(&keys[2]));
+    let entry = |key: &SigningKey| Validator { address: address(key), public_key: key.verifying_key().to_bytes().to_vec(), stake: 1000, role: ValidatorRole::Standard, last_active: 0, proposer_priority: 0, status: ValidatorStatus::Active, commission_bps: 0 };
+    storage.save_validator_set(&ValidatorSet { validators: vec![entry(&keys[0]), entry(&keys[1])], total_stake: 2000 });
+    storage.update_balance(&delegator, 10_000);
+
+    let tx = |key: &SigningKey, nonce: u64, tx_type: TransactionType| {
+        let mut tx = Transaction {
+            version: TX_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            sender: address(key),
+            receiver: "".into(),
+            amount: 0,
+            nonce,
+            fee: 0,
+            signature: vec![],
+            pub_key: key.verifying_key().to_bytes().to_vec(),
+            tx_type,
+            hash: None,
+        };
+        tx.sign(key);
+        tx
+    };
+
+    // Delegating bonds the delegator's AUR into the validator's voting stake
+    let receipts = apply_block(&storage, &vm, &block(1, vec![
+        tx(&keys[0], 0, TransactionType::SetCommission { basis_points: 1_000 }),
+        tx(&keys[0], 1, TransactionType::SetCommission { basis_points: 20_000 }),
+        tx(&keys[2], 0, TransactionType::Delegate { validator: a.clone(), amount: 3000 }),
+        tx(&keys[2], 1, TransactionType::Delegate { validator: "Anobody".into(), amount: 1 }),
+        tx(&keys[2], 2, TransactionType::Delegate { validator: b.clone(), amount: 0 }),
+        tx(&keys[2], 3, TransactionType::Delegate { validator: b.clone(), amount: u64::MAX }),
+    ])).unwrap();
+    assert_eq!(receipts[0].status, ReceiptStatus::Success);
+    assert_eq!(receipts[1].error.as_deref(), Some("commission of 20000 basis points exceeds 10000"));
+    assert_eq!(receipts[2].status, ReceiptStatus::Success);
+    assert_eq!(receipts[3].error.as_deref(), Some("validator Anobody not found"));
+    assert_eq!(receipts[4].error.as_deref(), Some("amount must be greater than zero"));
+    // An overflowing delegation is refused before the balance is even checked
+    assert_eq!(receipts[5].error.as_deref(), Some(&*format!("stake overflow for {}", b)));
+    let set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators[0].stake, set.validators[0].commission_bps, set.total_stake), (4000, 1_000, 5000));
+    assert_eq!(storage.get_delegations(&a), vec![Delegation { delegator: delegator.clone(), amount: 3000 }]);
+    assert_eq!(storage.get_balance(&delegator), 7000);
+
+    // The issuance is shared by voting stake (80 / 20), then split between a validator and its
+    // delegators pro rata, with a 10% commission on the delegated part
+    storage.save_chain_state(&ChainState { total_supply: 1_000_000, burned_fees: 0 });
+    ConsensusEngine::new(set).apply_finalization(&storage, &block(1, vec![]));
+    assert_eq!(storage.get_balance(&delegator), 7000 + 54);
+    assert_eq!(storage.get_balance(&a), 26);
+    assert_eq!(storage.get_balance(&b), 20);
+    assert_eq!(storage.get_chain_state().unwrap().total_supply, 1_000_000 + BLOCK_ISSUANCE);
+
+    // Validators only unstake their own bond; delegators move or unbond theirs
+    let receipts = apply_block(&storage, &vm, &block(2, vec![
+        tx(&keys[2], 4, TransactionType::Undelegate { validator: a.clone(), amount: 4000 }),
+        tx(&keys[2], 5, TransactionType::Redelegate { from: a.clone(), to: b.clone(), amount: 1000 }),
+        tx(&keys[2], 6, TransactionType::Undelegate { validator: a.clone(), amount: 2000 }),
+        tx(&keys[1], 0, TransactionType::Unstake { amount: 1500 }),
+    ])).unwrap();
+    assert_eq!(receipts[0].error.as_deref(), Some(&*format!("insufficient delegation: {} has 3000 delegated to {} but moves 4000", delegator, a)));
+    assert_eq!(receipts[1].status, ReceiptStatus::Success);
+    assert_eq!(receipts[2].status, ReceiptStatus::Success);
+    assert_eq!(receipts[3].error.as_deref(), Some(&*format!("insufficient stake: {} has 1000 staked but unstakes 1500", b)));
+    let mut set = storage.get_validator_set().unwrap();
+    assert_eq!((set.validators[0].stake, set.validators[1].stake, set.total_stake), (1000, 2000, 3000));
+    assert!(storage.get_delegations(&a).is_empty());
+    assert_eq!(storage.get_unbonding_queue(), vec![UnbondingEntry { validator: a.clone(), owner: delegator.clone(), amount: 2000, release_height: 4 }]);
+    assert_eq!(storage.get_redelegations(), vec![Redelegation { delegator: delegator.clone(), from: a.clone(), to: b.clone(), amount: 1000, completion_height: 4 }]);
+
+    // Slashing takes the same share from the validator's bond, its delegators, what is unbonding
+    // from it and what was redelegated away from it
+    assert_eq!(slash_validator(&storage, &mut set, &b, 10, ValidatorStatus::Jailed { until: 100 }), Some(200));
+    assert_eq!(storage.get_delegations(&b), vec![Delegation { delegator: delegator.clone(), amount: 900 }]);
+    assert_eq!(slash_validator(&storage, &mut set, &a, 50, ValidatorStatus::Jailed { until: 100 }), Some(2000));
+    assert_eq!(storage.get_delegations(&b), vec![Delegation { delegator: delegator.clone(), amount: 400 }]);
+    assert_eq!((set.validators[0].stake, set.validators[1].stake, set.total_stake), (500, 1300, 1800));
+
+    // With both validators jailed no rewards are paid, only the slashed unbonding comes back
+    let engine = ConsensusEngine::new(set);
+    engine.apply_finalization(&storage, &block(3, vec![]));
+    assert_eq!(storage.get_balance(&delegator), 7054);
+    engine.apply_finalization(&storage, &block(4, vec![]));
+    assert_eq!(storage.get_balance(&delegator), 7054 + 1000);
+    assert!(storage.get_redelegations().is_empty());
+}
+
+fn block(height: u64, transactions: Vec<Transaction>) -> Block {
+    let mut block = Block {
+        header: BlockHeader {
+            version: BLOCK_VERSION,
+            chain_id: DEFAULT_CHAIN_ID,
+            height,
+            timestamp: 1672531200 + height,
+            parent_hash: "0".into(),
+            tx_merkle_root: "".into(),
+            state_root: "".into(),
+            proposer: "".into(),
+            validator_set_hash: "".into(),
+            last_commit_hash: "".into(),
+        },
+        transactions,
+        last_commit: None,
+    };
+    block.header.tx_merkle_root = block.calculate_merkle_root();
+    block
+}
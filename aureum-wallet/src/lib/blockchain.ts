@@ -92,83 +92,141 @@ async function rpcCall(method: string, params: any[] = []): Promise<any> {
 }
 
 /**
- * Helper to convert number to 8-byte BigEndian array
+ * Domain tag and envelope version prefixed to every signing payload (must match core.rs)
  */
-function u64toBeBytes(n: number): Uint8Array {
+const TX_DOMAIN_TAG = new TextEncoder().encode("AUREUM_TX");
+const TX_VERSION = 1;
+
+let cachedChainId: { url: string; id: bigint } | null = null;
+
+/**
+ * Chain id of the connected node, cached per RPC endpoint
+ */
+export async function getChainId(): Promise<bigint> {
+    if (cachedChainId && cachedChainId.url === currentRpcUrl) {
+        return cachedChainId.id;
+    }
+    const id = BigInt(await rpcCall("aureum_chainId", []));
+    cachedChainId = { url: currentRpcUrl, id };
+    return id;
+}
+
+/**
+ * Helper to convert number to 8-byte LittleEndian array (SCALE u64)
+ */
+function u64toLeBytes(n: number | bigint): Uint8Array {
     const buf = new ArrayBuffer(8);
     const view = new DataView(buf);
-    view.setBigUint64(0, BigInt(n), false); // false = BigEndian
+    view.setBigUint64(0, BigInt(n), true); // true = LittleEndian
     return new Uint8Array(buf);
 }
 
+/**
+ * SCALE compact encoding of a length prefix
+ */
+function compactLen(len: number): Uint8Array {
+    if (len < 1 << 6) {
+        return new Uint8Array([len << 2]);
+    }
+    if (len < 1 << 14) {
+        return new Uint8Array([((len & 0x3F) << 2) | 0x01, len >> 6]);
+    }
+    if (len < 1 << 30) {
+        const v = (len << 2) | 0x02;
+        return new Uint8Array([v & 0xFF, (v >> 8) & 0xFF, (v >> 16) & 0xFF, (v >>> 24) & 0xFF]);
+    }
+    throw new Error("Field too long to encode");
+}
+
+function concatBytes(parts: Uint8Array[]): Uint8Array {
+    const out = new Uint8Array(parts.reduce((n, p) => n + p.length, 0));
+    let offset = 0;
+    for (const p of parts) {
+        out.set(p, offset);
+        offset += p.length;
+    }
+    return out;
+}
+
+function encodeBytes(bytes: Uint8Array): Uint8Array {
+    return concatBytes([compactLen(bytes.length), bytes]);
+}
+
 function encodeString(str: string): Uint8Array {
-    const encoder = new TextEncoder();
-    const bytes = encoder.encode(str);
-    const len = bytes.length;
-    // Simple Compact encoding for length < 64
-    const lenByte = len << 2;
-    const res = new Uint8Array(1 + len);
-    res[0] = lenByte;
-    res.set(bytes, 1);
-    return res;
+    return encodeBytes(new TextEncoder().encode(str));
+}
+
+interface TxFields {
+    sender: string;
+    receiver: string;
+    amount: number;
+    nonce: number;
+    fee: number;
 }
 
 /**
- * Sign and send a transaction
+ * Sign the canonical payload and submit the transaction.
+ *
+ * `txTypeBytes` is the SCALE encoding of the TransactionType (variant index
+ * followed by its fields) and `txType` its JSON form.
  */
-export async function signAndSendTransaction(
-    sender: string,
-    receiver: string,
-    amount: number,
-    nonce: number,
-    fee: number,
+async function signAndSubmit(
+    fields: TxFields,
+    txTypeBytes: Uint8Array,
+    txType: any,
     privateKeyHex: string
 ): Promise<string> {
-    const encoder = new TextEncoder();
-
-    // Parse private key from hex string
     const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g)!.map(byte => parseInt(byte, 16)));
     const keyPair = nacl.sign.keyPair.fromSeed(pkBytes);
     const pubKey = keyPair.publicKey;
-
-    // Construct message to sign (must match core.rs)
-    const senderBytes = encoder.encode(sender);
-    const receiverBytes = encoder.encode(receiver);
-    const amountBytes = u64toBeBytes(amount);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(fee);
-
-    // Default Transfer (index 0)
-    const typeBytes = new Uint8Array([0]);
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length + nonceBytes.length + feeBytes.length + pubKey.length + typeBytes.length;
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(pubKey, offset); offset += pubKey.length;
-    message.set(typeBytes, offset);
+    const chainId = await getChainId();
+
+    // Domain tag, version, chain id, then the SCALE-encoded body (must match core.rs)
+    const message = concatBytes([
+        TX_DOMAIN_TAG,
+        new Uint8Array([TX_VERSION]),
+        u64toLeBytes(chainId),
+        encodeString(fields.sender),
+        encodeString(fields.receiver),
+        u64toLeBytes(fields.amount),
+        u64toLeBytes(fields.nonce),
+        u64toLeBytes(fields.fee),
+        encodeBytes(pubKey),
+        txTypeBytes,
+    ]);
 
     const signature = nacl.sign.detached(message, keyPair.secretKey);
 
     const tx = {
-        sender,
-        receiver,
-        amount,
-        nonce,
-        fee,
+        version: TX_VERSION,
+        // Sent as a number so serde reads it as a u64
+        chain_id: Number(chainId),
+        ...fields,
         signature: Array.from(signature),
         pub_key: Array.from(pubKey),
-        tx_type: "Transfer"
+        tx_type: txType
     };
 
     return await rpcCall("aureum_submitTransaction", [tx]);
 }
 
+/**
+ * Sign and send a transaction
+ */
+export async function signAndSendTransaction(
+    sender: string,
+    receiver: string,
+    amount: number,
+    nonce: number,
+    fee: number,
+    privateKeyHex: string
+): Promise<string> {
+    // Default Transfer (index 0)
+    const typeBytes = new Uint8Array([0]);
+
+    return await signAndSubmit({ sender, receiver, amount, nonce, fee }, typeBytes, "Transfer", privateKeyHex);
+}
+
 /**
  * Tokenize a new property on the blockchain
  */
@@ -180,56 +238,20 @@ export async function tokenizeProperty(
     nonce: number,
     privateKeyHex: string
 ): Promise<string> {
-    const encoder = new TextEncoder();
-    const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g)!.map(byte => parseInt(byte, 16)));
-    const keyPair = nacl.sign.keyPair.fromSeed(pkBytes);
-    const pubKey = keyPair.publicKey;
-
-    // Encodings for message signing
-    const senderBytes = encoder.encode(owner);
-    const receiverBytes = encoder.encode("0"); // System address for tokenization
-    const amountBytes = u64toBeBytes(valuationAUR);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(10); // Standard fee
-
     // TransactionType::TokenizeProperty is variant index 3
-    const typeBytes = new Uint8Array([3]);
-    // String encodings for SCALE (length-prefixed)
-    const addrBytes = encoder.encode(physicalAddress);
-    const metaBytes = encoder.encode(metadata);
-
-    // We append the strings to the message for signing
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length + nonceBytes.length + feeBytes.length + pubKey.length + typeBytes.length + addrBytes.length + metaBytes.length + 2; // +2 for simple len prefix bytes
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(pubKey, offset); offset += pubKey.length;
-    message.set(typeBytes, offset); offset += typeBytes.length;
-    // Simple mock SCALE string encoding for the signature message
-    message[offset] = addrBytes.length << 2; offset++;
-    message.set(addrBytes, offset); offset += addrBytes.length;
-    message[offset] = metaBytes.length << 2; offset++;
-    message.set(metaBytes, offset);
-
-    const signature = nacl.sign.detached(message, keyPair.secretKey);
-
-    const tx = {
-        sender: owner,
-        receiver: "0",
-        amount: valuationAUR,
-        nonce,
-        fee: 10,
-        signature: Array.from(signature),
-        pub_key: Array.from(pubKey),
-        tx_type: { TokenizeProperty: { address: physicalAddress, metadata } }
-    };
-
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    const typeBytes = concatBytes([
+        new Uint8Array([3]),
+        encodeString(physicalAddress),
+        encodeString(metadata),
+    ]);
+
+    return await signAndSubmit(
+        // System receiver for tokenization, standard fee
+        { sender: owner, receiver: "0", amount: valuationAUR, nonce, fee: 10 },
+        typeBytes,
+        { TokenizeProperty: { address: physicalAddress, metadata } },
+        privateKeyHex
+    );
 }
 
 /**
@@ -238,62 +260,27 @@ export async function tokenizeProperty(
 export async function applyForVisa(
     applicant: string,
     propertyId: string,
-    programIndex: number, // 0=PT, 1=UAE, 2=UK, 3=Malta
+    programIndex: number, // 0=Portugal, 1=Spain, 2=Greece, 3=UAE
     investmentAmount: number,
     nonce: number,
     privateKeyHex: string
 ): Promise<string> {
-    const encoder = new TextEncoder();
-    const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g)!.map(byte => parseInt(byte, 16)));
-    const keyPair = nacl.sign.keyPair.fromSeed(pkBytes);
-    const pubKey = keyPair.publicKey;
-
-    const senderBytes = encoder.encode(applicant);
-    const receiverBytes = encoder.encode("0");
-    const amountBytes = u64toBeBytes(investmentAmount);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(25); // Higher fee for compliance processing
-
-    // TransactionType::ApplyForVisa is variant index 4
-    const typeBytes = new Uint8Array([4]);
-    // Property ID is a string (len-prefixed)
-    const propIdBytes = encoder.encode(propertyId);
-    // Program is an enum (1 byte)
-    const progByte = new Uint8Array([programIndex]);
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length + nonceBytes.length + feeBytes.length + pubKey.length + typeBytes.length + propIdBytes.length + 1 + progByte.length;
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(pubKey, offset); offset += pubKey.length;
-    message.set(typeBytes, offset); offset += typeBytes.length;
-    // SCALE string
-    message[offset] = propIdBytes.length << 2; offset++;
-    message.set(propIdBytes, offset); offset += propIdBytes.length;
-    // Enum
-    message.set(progByte, offset);
-
-    const signature = nacl.sign.detached(message, keyPair.secretKey);
-
-    const programs = ["PortugalGoldenVisa", "UAEGoldenVisa", "UKHighValueResidency", "MaltaCitizenshipByInvestment"];
-
-    const tx = {
-        sender: applicant,
-        receiver: "0",
-        amount: investmentAmount,
-        nonce,
-        fee: 25,
-        signature: Array.from(signature),
-        pub_key: Array.from(pubKey),
-        tx_type: { ApplyForVisa: { property_id: propertyId, program: programs[programIndex] } }
-    };
-
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    // TransactionType::ApplyForVisa is variant index 4; the program is a fieldless enum (1 byte)
+    const typeBytes = concatBytes([
+        new Uint8Array([4]),
+        encodeString(propertyId),
+        new Uint8Array([programIndex]),
+    ]);
+
+    const programs = ["Portugal", "Spain", "Greece", "UAE"];
+
+    return await signAndSubmit(
+        // Higher fee for compliance processing
+        { sender: applicant, receiver: "0", amount: investmentAmount, nonce, fee: 25 },
+        typeBytes,
+        { ApplyForVisa: { property_id: propertyId, program: programs[programIndex] } },
+        privateKeyHex
+    );
 }
 
 /**
@@ -309,62 +296,26 @@ export async function createEscrow(
     nonce: number,
     privateKeyHex: string
 ): Promise<string> {
-    const encoder = new TextEncoder();
-    const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g)!.map(byte => parseInt(byte, 16)));
-    const keyPair = nacl.sign.keyPair.fromSeed(pkBytes);
-    const pubKey = keyPair.publicKey;
-
-    const senderBytes = encoder.encode(sender);
-    const receiverBytes = encoder.encode(receiver);
-    const amountBytes = u64toBeBytes(amount);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(50); // Escrow fee
-
-    // TransactionType::EscrowCreate is variant index 11
-    const typeBytes = new Uint8Array([11]);
-    const arbiterBytes = encodeString(arbiter);
-    const condBytes = encodeString(conditions);
-
     // SCALE encode Option<String> for propertyId
-    let propIdEncoded: Uint8Array;
-    if (propertyId) {
-        const propIdBytes = encodeString(propertyId);
-        propIdEncoded = new Uint8Array(1 + propIdBytes.length);
-        propIdEncoded[0] = 1; // Some
-        propIdEncoded.set(propIdBytes, 1);
-    } else {
-        propIdEncoded = new Uint8Array([0]); // None
-    }
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length + nonceBytes.length + feeBytes.length + pubKey.length + typeBytes.length + arbiterBytes.length + condBytes.length + propIdEncoded.length;
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(pubKey, offset); offset += pubKey.length;
-    message.set(typeBytes, offset); offset += typeBytes.length;
-    message.set(arbiterBytes, offset); offset += arbiterBytes.length;
-    message.set(condBytes, offset); offset += condBytes.length;
-    message.set(propIdEncoded, offset);
-
-    const signature = nacl.sign.detached(message, keyPair.secretKey);
+    const propIdEncoded = propertyId
+        ? concatBytes([new Uint8Array([1]), encodeString(propertyId)]) // Some
+        : new Uint8Array([0]); // None
 
-    const tx = {
-        sender,
-        receiver,
-        amount,
-        nonce,
-        fee: 50,
-        signature: Array.from(signature),
-        pub_key: Array.from(pubKey),
-        tx_type: { EscrowCreate: { arbiter, conditions, property_id: propertyId } }
-    };
-
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    // TransactionType::EscrowCreate is variant index 11
+    const typeBytes = concatBytes([
+        new Uint8Array([11]),
+        encodeString(arbiter),
+        encodeString(conditions),
+        propIdEncoded,
+    ]);
+
+    return await signAndSubmit(
+        // Escrow fee
+        { sender, receiver, amount, nonce, fee: 50 },
+        typeBytes,
+        { EscrowCreate: { arbiter, conditions, property_id: propertyId } },
+        privateKeyHex
+    );
 }
 
 /**
@@ -376,64 +327,16 @@ export async function releaseEscrow(
     nonce: number,
     privateKeyHex: string
 ): Promise<string> {
-    const encoder = new TextEncoder();
-    const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g)!.map(byte => parseInt(byte, 16)));
-    const keyPair = nacl.sign.keyPair.fromSeed(pkBytes);
-    const pubKey = keyPair.publicKey;
-
-    const senderBytes = encoder.encode(sender);
-    const receiverBytes = encoder.encode("0"); // System receiver for command
-    const amountBytes = u64toBeBytes(0);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(10); // Standard fee
-
-    // SCALE encode the TransactionType::EscrowRelease
     // Enum index 12 for EscrowRelease, followed by SCALE-encoded String
-    const escrowIdBytes = encoder.encode(escrowId);
-    const escrowIdLen = escrowIdBytes.length;
-
-    // SCALE compact encoding for string length
-    let lenBytes: Uint8Array;
-    if (escrowIdLen < 64) {
-        lenBytes = new Uint8Array([escrowIdLen << 2]);
-    } else {
-        // For longer strings, use multi-byte compact encoding
-        lenBytes = new Uint8Array([((escrowIdLen & 0x3F) << 2) | 0x01, (escrowIdLen >> 6) & 0xFF]);
-    }
-
-    // Build the SCALE-encoded tx_type: variant_index + compact_length + data
-    const txTypeEncoded = new Uint8Array(1 + lenBytes.length + escrowIdLen);
-    txTypeEncoded[0] = 12; // EscrowRelease variant index
-    txTypeEncoded.set(lenBytes, 1);
-    txTypeEncoded.set(escrowIdBytes, 1 + lenBytes.length);
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length +
-        nonceBytes.length + feeBytes.length + pubKey.length + txTypeEncoded.length;
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(pubKey, offset); offset += pubKey.length;
-    message.set(txTypeEncoded, offset);
-
-    const signature = nacl.sign.detached(message, keyPair.secretKey);
-
-    const tx = {
-        sender,
-        receiver: "0",
-        amount: 0,
-        nonce,
-        fee: 10,
-        signature: Array.from(signature),
-        pub_key: Array.from(pubKey),
-        tx_type: { EscrowRelease: { escrow_id: escrowId } }
-    };
-
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    const typeBytes = concatBytes([new Uint8Array([12]), encodeString(escrowId)]);
+
+    return await signAndSubmit(
+        // System receiver for command
+        { sender, receiver: "0", amount: 0, nonce, fee: 10 },
+        typeBytes,
+        { EscrowRelease: { escrow_id: escrowId } },
+        privateKeyHex
+    );
 }
 
 /**
@@ -445,63 +348,15 @@ export async function refundEscrow(
     nonce: number,
     privateKeyHex: string
 ): Promise<string> {
-    const encoder = new TextEncoder();
-    const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g)!.map(byte => parseInt(byte, 16)));
-    const keyPair = nacl.sign.keyPair.fromSeed(pkBytes);
-    const pubKey = keyPair.publicKey;
-
-    const senderBytes = encoder.encode(sender);
-    const receiverBytes = encoder.encode("0");
-    const amountBytes = u64toBeBytes(0);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(10);
-
-    // SCALE encode the TransactionType::EscrowRefund
     // Enum index 13 for EscrowRefund, followed by SCALE-encoded String
-    const escrowIdBytes = encoder.encode(escrowId);
-    const escrowIdLen = escrowIdBytes.length;
-
-    // SCALE compact encoding for string length
-    let lenBytes: Uint8Array;
-    if (escrowIdLen < 64) {
-        lenBytes = new Uint8Array([escrowIdLen << 2]);
-    } else {
-        lenBytes = new Uint8Array([((escrowIdLen & 0x3F) << 2) | 0x01, (escrowIdLen >> 6) & 0xFF]);
-    }
-
-    // Build the SCALE-encoded tx_type: variant_index + compact_length + data
-    const txTypeEncoded = new Uint8Array(1 + lenBytes.length + escrowIdLen);
-    txTypeEncoded[0] = 13; // EscrowRefund variant index
-    txTypeEncoded.set(lenBytes, 1);
-    txTypeEncoded.set(escrowIdBytes, 1 + lenBytes.length);
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length +
-        nonceBytes.length + feeBytes.length + pubKey.length + txTypeEncoded.length;
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(pubKey, offset); offset += pubKey.length;
-    message.set(txTypeEncoded, offset);
-
-    const signature = nacl.sign.detached(message, keyPair.secretKey);
-
-    const tx = {
-        sender,
-        receiver: "0",
-        amount: 0,
-        nonce,
-        fee: 10,
-        signature: Array.from(signature),
-        pub_key: Array.from(pubKey),
-        tx_type: { EscrowRefund: { escrow_id: escrowId } }
-    };
-
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    const typeBytes = concatBytes([new Uint8Array([13]), encodeString(escrowId)]);
+
+    return await signAndSubmit(
+        { sender, receiver: "0", amount: 0, nonce, fee: 10 },
+        typeBytes,
+        { EscrowRefund: { escrow_id: escrowId } },
+        privateKeyHex
+    );
 }
 
 /**
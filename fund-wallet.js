@@ -21,53 +21,36 @@ async function rpcCall(method, params = []) {
     return data.result;
 }
 
-function u64toBeBytes(n) {
-    const buf = new ArrayBuffer(8);
-    const view = new DataView(buf);
-    view.setBigUint64(0, BigInt(n), false);
-    return new Uint8Array(buf);
+// Sign the node's canonical payload (domain tag, version, chain id, SCALE body) and submit
+async function signAndSubmit(tx, keypair) {
+    const payloadHex = await rpcCall("aureum_getSigningPayload", [tx]);
+    const message = Buffer.from(payloadHex.slice(2), "hex");
+    tx.signature = Array.from(nacl.sign.detached(message, keypair.secretKey));
+    return await rpcCall("aureum_submitTransaction", [tx]);
 }
 
 async function fund(address, amount) {
-    const encoder = new TextEncoder();
     const nonceStr = await rpcCall("aureum_getNonce", [VALIDATOR_ADDRESS]);
     const nonce = parseInt(nonceStr);
+    const chainId = parseInt(await rpcCall("aureum_chainId", []));
     const fee = 1;
     const pkBytes = new Uint8Array(VALIDATOR_PRIVATE_KEY.match(/.{1,2}/g).map(byte => parseInt(byte, 16)));
     const keypair = nacl.sign.keyPair.fromSeed(pkBytes);
 
-    const senderBytes = encoder.encode(VALIDATOR_ADDRESS);
-    const receiverBytes = encoder.encode(address);
-    const amountBytes = u64toBeBytes(amount);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(fee);
-    const typeBytes = new Uint8Array([0]);
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length +
-        nonceBytes.length + feeBytes.length + keypair.publicKey.length + typeBytes.length;
-    const message = new Uint8Array(totalLen);
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(keypair.publicKey, offset); offset += keypair.publicKey.length;
-    message.set(typeBytes, offset);
-
-    const signature = nacl.sign.detached(message, keypair.secretKey);
     const tx = {
+        version: 1,
+        chain_id: chainId,
         sender: VALIDATOR_ADDRESS,
         receiver: address,
         amount,
         nonce,
         fee,
-        signature: Array.from(signature),
+        signature: [],
         pub_key: Array.from(keypair.publicKey),
         tx_type: "Transfer"
     };
 
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    return await signAndSubmit(tx, keypair);
 }
 
 const target = process.argv[2] || "Ab019b41ff4f3a95a9f7aa503e3cb2e3e3aa56569";
@@ -13,68 +13,33 @@ async function rpcCall(method, params = []) {
     return data.result;
 }
 
-function u64toBeBytes(n) {
-    const buf = new ArrayBuffer(8);
-    const view = new DataView(buf);
-    view.setBigUint64(0, BigInt(n), false);
-    return new Uint8Array(buf);
-}
-
-function encodeString(str) {
-    const encoder = new TextEncoder();
-    const bytes = encoder.encode(str);
-    const len = bytes.length;
-    const res = new Uint8Array(1 + len);
-    res[0] = len << 2;
-    res.set(bytes, 1);
-    return res;
+// Sign the node's canonical payload (domain tag, version, chain id, SCALE body) and submit
+async function signAndSubmit(tx, keypair) {
+    const payloadHex = await rpcCall("aureum_getSigningPayload", [tx]);
+    const message = Buffer.from(payloadHex.slice(2), "hex");
+    tx.signature = Array.from(nacl.sign.detached(message, keypair.secretKey));
+    return await rpcCall("aureum_submitTransaction", [tx]);
 }
 
 async function tokenizeProperty(owner, address, valuationAUR, metadata, nonce, privateKeyHex) {
     const pkBytes = Buffer.from(privateKeyHex, 'hex');
     const keyPair = nacl.sign.keyPair.fromSeed(pkBytes);
-    const pubKey = keyPair.publicKey;
-
-    const encoder = new TextEncoder();
-    const senderBytes = encoder.encode(owner);
-    const receiverBytes = encoder.encode("0");
-    const amountBytes = u64toBeBytes(valuationAUR);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(10);
-    const typeBytes = new Uint8Array([3]);
-    const addrBytes = encoder.encode(address);
-    const metaBytes = encoder.encode(metadata);
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length + nonceBytes.length + feeBytes.length + pubKey.length + typeBytes.length + addrBytes.length + metaBytes.length + 2;
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(pubKey, offset); offset += pubKey.length;
-    message.set(typeBytes, offset); offset += typeBytes.length;
-    message[offset] = addrBytes.length << 2; offset++;
-    message.set(addrBytes, offset); offset += addrBytes.length;
-    message[offset] = metaBytes.length << 2; offset++;
-    message.set(metaBytes, offset);
-
-    const signature = nacl.sign.detached(message, keyPair.secretKey);
+    const chainId = parseInt(await rpcCall("aureum_chainId", []));
 
     const tx = {
+        version: 1,
+        chain_id: chainId,
         sender: owner,
         receiver: "0",
         amount: valuationAUR,
         nonce,
         fee: 10,
-        signature: Array.from(signature),
-        pub_key: Array.from(pubKey),
+        signature: [],
+        pub_key: Array.from(keyPair.publicKey),
         tx_type: { TokenizeProperty: { address, metadata } }
     };
 
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    return await signAndSubmit(tx, keyPair);
 }
 
 module.exports = { rpcCall, tokenizeProperty };
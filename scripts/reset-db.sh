@@ -6,17 +6,20 @@ pm2 stop all
 
 echo "🧹 Clearing blockchain data..."
 rm -rf ./data/blockchain/*
+# The signing record belongs to the old chain; kept, it would block votes on the new one
+rm -f ./data/consensus.wal
 
 echo "🚀 Re-initializing node..."
 # Use the compiled aureum-node to init
 cd aureum-node
-cargo run -- init --data-dir ../data
+# --dev makes the genesis validator the well-known testnet key the seed scripts sign with
+cargo run -- init --dev --data-dir ../data
 
 echo "📦 Funding test wallets and tokenizing initial properties..."
 # We can use a script to tokenize some properties via RPC once the node is running
 # But first, let's start the node in the background
 cd ..
-pm2 start "cd aureum-node && cargo run -- run --rpc-port 8545 --data-dir ../data" --name aureum-node
+pm2 start "cd aureum-node && cargo run -- run --validator --rpc-port 8545 --data-dir ../data" --name aureum-node
 
 echo "Wait for node to start..."
 sleep 5
@@ -27,34 +27,12 @@ async function rpcCall(method, params = []) {
     return data.result;
 }
 
-function u64toBeBytes(n) {
-    const buf = new ArrayBuffer(8);
-    const view = new DataView(buf);
-    view.setBigUint64(0, BigInt(n), false);
-    return new Uint8Array(buf);
-}
-
-function encodeString(str) {
-    const encoder = new TextEncoder();
-    const bytes = encoder.encode(str);
-    const len = bytes.length;
-
-    if (len < 64) {
-        const lenByte = len << 2;
-        const res = new Uint8Array(1 + len);
-        res[0] = lenByte;
-        res.set(bytes, 1);
-        return res;
-    } else if (len < 16384) {
-        const val = (len << 2) | 1;
-        const res = new Uint8Array(2 + len);
-        res[0] = val & 0xFF;
-        res[1] = (val >>> 8) & 0xFF;
-        res.set(bytes, 2);
-        return res;
-    } else {
-        throw new Error("String too long for test");
-    }
+// Sign the node's canonical payload (domain tag, version, chain id, SCALE body) and submit
+async function signAndSubmit(tx, keypair) {
+    const payloadHex = await rpcCall("aureum_getSigningPayload", [tx]);
+    const message = Buffer.from(payloadHex.slice(2), "hex");
+    tx.signature = Array.from(nacl.sign.detached(message, keypair.secretKey));
+    return await rpcCall("aureum_submitTransaction", [tx]);
 }
 
 function generateAddress(pubKey) {
@@ -63,69 +41,37 @@ function generateAddress(pubKey) {
 }
 
 async function sendTransaction(senderAddr, receiverAddr, amount, privateKeyHex, typeObj = "Transfer") {
-    const encoder = new TextEncoder();
     const nonce = parseInt(await rpcCall("aureum_getNonce", [senderAddr]));
+    const chainId = parseInt(await rpcCall("aureum_chainId", []));
     const fee = typeObj === "Transfer" ? 1 : 10; // Simple fee logic
 
     const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g).map(byte => parseInt(byte, 16)));
     const keypair = nacl.sign.keyPair.fromSeed(pkBytes);
 
-    const senderBytes = encoder.encode(senderAddr);
-    const receiverBytes = encoder.encode((typeObj === "Transfer" || typeObj.type === "EscrowCreate") ? receiverAddr : "0");
-    const amountBytes = u64toBeBytes(amount);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(fee);
-
-    let typeBytes;
     let txTypeJSON;
 
     if (typeObj === "Transfer") {
-        typeBytes = new Uint8Array([0]);
         txTypeJSON = "Transfer";
     } else if (typeObj.type === "EscrowCreate") {
-        const arbiterBytes = encodeString(typeObj.arbiter);
-        const condBytes = encodeString(typeObj.conditions);
-        typeBytes = new Uint8Array(1 + arbiterBytes.length + condBytes.length);
-        typeBytes[0] = 11;
-        typeBytes.set(arbiterBytes, 1);
-        typeBytes.set(condBytes, 1 + arbiterBytes.length);
-        txTypeJSON = { EscrowCreate: { arbiter: typeObj.arbiter, conditions: typeObj.conditions } };
+        txTypeJSON = { EscrowCreate: { arbiter: typeObj.arbiter, conditions: typeObj.conditions, property_id: null } };
     } else if (typeObj.type === "EscrowRelease") {
-        const idBytes = encodeString(typeObj.escrow_id);
-        typeBytes = new Uint8Array(1 + idBytes.length);
-        typeBytes[0] = 12;
-        typeBytes.set(idBytes, 1);
         txTypeJSON = { EscrowRelease: { escrow_id: typeObj.escrow_id } };
     }
 
-    const keyBytes = keypair.publicKey;
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length +
-        nonceBytes.length + feeBytes.length + keyBytes.length + typeBytes.length;
-
-    const message = new Uint8Array(totalLen);
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(keyBytes, offset); offset += keyBytes.length;
-    message.set(typeBytes, offset);
-
-    const signature = nacl.sign.detached(message, keypair.secretKey);
-
     const tx = {
+        version: 1,
+        chain_id: chainId,
         sender: senderAddr,
         receiver: (typeObj === "Transfer" || typeObj.type === "EscrowCreate") ? receiverAddr : "0",
         amount,
         nonce,
         fee,
-        signature: Array.from(signature),
+        signature: [],
         pub_key: Array.from(keypair.publicKey),
         tx_type: txTypeJSON
     };
 
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    return await signAndSubmit(tx, keypair);
 }
 
 async function main() {
@@ -29,11 +29,12 @@ async function rpcCall(method, params = []) {
     return data.result;
 }
 
-function u64toBeBytes(n) {
-    const buf = new ArrayBuffer(8);
-    const view = new DataView(buf);
-    view.setBigUint64(0, BigInt(n), false);
-    return new Uint8Array(buf);
+// Sign the node's canonical payload (domain tag, version, chain id, SCALE body) and submit
+async function signAndSubmit(tx, keypair) {
+    const payloadHex = await rpcCall("aureum_getSigningPayload", [tx]);
+    const message = Buffer.from(payloadHex.slice(2), "hex");
+    tx.signature = Array.from(nacl.sign.detached(message, keypair.secretKey));
+    return await rpcCall("aureum_submitTransaction", [tx]);
 }
 
 function generateAddress(pubKey) {
@@ -42,50 +43,28 @@ function generateAddress(pubKey) {
 }
 
 async function sendTransaction(senderAddr, receiverAddr, amount, privateKeyHex) {
-    const encoder = new TextEncoder();
-
     const nonceStr = await rpcCall("aureum_getNonce", [senderAddr]);
     const nonce = parseInt(nonceStr);
+    const chainId = parseInt(await rpcCall("aureum_chainId", []));
     const fee = 1;
 
     const pkBytes = new Uint8Array(privateKeyHex.match(/.{1,2}/g).map(byte => parseInt(byte, 16)));
     const keypair = nacl.sign.keyPair.fromSeed(pkBytes);
 
-    // Build message exactly as core.rs does in verify_signature
-    const senderBytes = encoder.encode(senderAddr);
-    const receiverBytes = encoder.encode(receiverAddr);
-    const amountBytes = u64toBeBytes(amount);
-    const nonceBytes = u64toBeBytes(nonce);
-    const feeBytes = u64toBeBytes(fee);
-    const typeBytes = new Uint8Array([0]); // SCALE encoding for Transfer variant (variant 0)
-
-    const totalLen = senderBytes.length + receiverBytes.length + amountBytes.length +
-        nonceBytes.length + feeBytes.length + keypair.publicKey.length + typeBytes.length;
-    const message = new Uint8Array(totalLen);
-
-    let offset = 0;
-    message.set(senderBytes, offset); offset += senderBytes.length;
-    message.set(receiverBytes, offset); offset += receiverBytes.length;
-    message.set(amountBytes, offset); offset += amountBytes.length;
-    message.set(nonceBytes, offset); offset += nonceBytes.length;
-    message.set(feeBytes, offset); offset += feeBytes.length;
-    message.set(keypair.publicKey, offset); offset += keypair.publicKey.length;
-    message.set(typeBytes, offset);
-
-    const signature = nacl.sign.detached(message, keypair.secretKey);
-
     const tx = {
+        version: 1,
+        chain_id: chainId,
         sender: senderAddr,
         receiver: receiverAddr,
         amount,
         nonce,
         fee,
-        signature: Array.from(signature),
+        signature: [],
         pub_key: Array.from(keypair.publicKey),
         tx_type: "Transfer"
     };
 
-    console.log("   Debug - Message length:", message.length);
+    console.log("   Debug - Chain id:", chainId);
     console.log("   Debug - Sender:", senderAddr);
     const derived = generateAddress(keypair.publicKey);
     console.log("   Debug - Derived address from pubkey:", derived);
@@ -95,7 +74,7 @@ async function sendTransaction(senderAddr, receiverAddr, amount, privateKeyHex)
         return "Invalid Address Derivation";
     }
 
-    return await rpcCall("aureum_submitTransaction", [tx]);
+    return await signAndSubmit(tx, keypair);
 }
 
 async function main() {
@@ -126,8 +105,8 @@ async function main() {
     if (vBalInt === 0) {
         console.log("⚠️  Validator has 0 balance. Resetting node state...");
         console.log("   1. Stop node");
-        console.log("   2. Run: cargo run --bin aureum-node --release -- init");
-        console.log("   3. Start node: cargo run --bin aureum-node --release -- run");
+        console.log("   2. Run: cargo run --bin aureum-node --release -- init --dev");
+        console.log("   3. Start node: cargo run --bin aureum-node --release -- run --validator");
         return;
     }
 